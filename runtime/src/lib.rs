@@ -0,0 +1,91 @@
+// the main of projects compiled by `build`, which runs the project.json compiled into them
+// with their compiled custom blocks and scripts and interprets the rest,
+// with say and think going to stdout and ask and wait answered from stdin
+
+use std::os::raw::{c_char, c_int};
+use std::slice;
+
+use bumpalo::Bump;
+use silicon_scratch_rust::scratch::ast::lower;
+use silicon_scratch_rust::scratch::parse::project::archive;
+use silicon_scratch_rust::scratch::runtime::interpreter::Interpreter;
+use silicon_scratch_rust::scratch::runtime::native::{CoroutineEntry, Entry, Native, ScriptEntry};
+use silicon_scratch_rust::scratch::runtime::stage::Stage;
+
+// defined by the compiled object, see compile::llvm::aot
+extern "C" {
+    static scratch_project: [u8; 0];
+    static scratch_project_len: u64;
+    static scratch_project_version: u32;
+    static scratch_function_count: u64;
+    static scratch_function_ids: [u64; 0];
+    static scratch_function_entries: [Entry; 0];
+    static scratch_script_count: u64;
+    static scratch_script_ids: [u64; 0];
+    static scratch_script_entries: [ScriptEntry; 0];
+    static scratch_coroutine_count: u64;
+    static scratch_coroutine_ids: [u64; 0];
+    static scratch_coroutine_entries: [CoroutineEntry; 0];
+}
+
+// by sprite scripts index and script index, which are in pairs in the ids
+fn scripts<T: Copy>(ids: &[u64], entries: &[T]) -> Vec<((usize, usize), T)> {
+    ids.chunks(2)
+        .zip(entries)
+        .map(|(ids, &entry)| ((ids[0] as usize, ids[1] as usize), entry))
+        .collect()
+}
+
+unsafe fn table<T>(table: &[T; 0], len: u64) -> &'static [T] {
+    slice::from_raw_parts(table.as_ptr(), len as usize)
+}
+
+#[no_mangle]
+pub extern "C" fn main(_argc: c_int, _argv: *const *const c_char) -> c_int {
+    let json = unsafe { table(&scratch_project, scratch_project_len) };
+    let project = match archive::parse_project(json, unsafe { scratch_project_version }) {
+        Ok(project) => project,
+        Err(error) => {
+            eprintln!("can't load the project: {:?}", error);
+            return 1;
+        }
+    };
+    let bump = Bump::new();
+    let lowered = lower::lower(&bump, &project);
+    let program = &lowered.program;
+
+    let (function_ids, function_entries) = unsafe {
+        (
+            table(&scratch_function_ids, scratch_function_count),
+            table(&scratch_function_entries, scratch_function_count),
+        )
+    };
+    let functions = function_ids
+        .iter()
+        .zip(function_entries)
+        .map(|(&id, &entry)| (program.functions()[id as usize], entry))
+        .collect();
+    let (scripts, coroutines) = unsafe {
+        (
+            scripts(
+                table(&scratch_script_ids, 2 * scratch_script_count),
+                table(&scratch_script_entries, scratch_script_count),
+            ),
+            scripts(
+                table(&scratch_coroutine_ids, 2 * scratch_coroutine_count),
+                table(&scratch_coroutine_entries, scratch_coroutine_count),
+            ),
+        )
+    };
+
+    let mut interpreter = Interpreter::new(program, Stage::new(program, &project));
+    interpreter.set_native(Native::new(program, functions, scripts, coroutines));
+    interpreter.answer_from_stdin();
+    match interpreter.green_flag() {
+        Ok(()) => 0,
+        Err(halt) => {
+            eprintln!("stopped early: {:?}", halt);
+            1
+        }
+    }
+}
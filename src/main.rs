@@ -1,15 +1,208 @@
-#![feature(slice_from_raw_parts)]
-
-#[allow(dead_code)]
-mod scratch;
-
-use crate::scratch::parse::project;
+use silicon_scratch_rust::scratch::ast::broadcast_graph::BroadcastGraph;
+use silicon_scratch_rust::scratch::ast::compute_kind_report::ComputeKindReport;
+use silicon_scratch_rust::scratch::ast::lower::{self, Lowered};
+use silicon_scratch_rust::scratch::ast::variable_usage::ScopedVariable;
+use silicon_scratch_rust::scratch::ast::Event;
+use silicon_scratch_rust::scratch::compile::llvm::{aot, codegen, jit};
+use silicon_scratch_rust::scratch::parse::project;
+use silicon_scratch_rust::scratch::runtime::interpreter::Interpreter;
+use silicon_scratch_rust::scratch::runtime::render::Renderer;
+use silicon_scratch_rust::scratch::runtime::stage::Stage;
 use project::path::ProjectPath;
 use project::archive::ProjectArchive;
+use project::link;
+use project::sb3::Target;
+use bumpalo::Bump;
+use inkwell::context::Context;
+use std::path::PathBuf;
+
+fn print_target(name: &str, target: &Target) {
+    let bump = Bump::new();
+    match link::link(&bump, target) {
+        Ok(linked) => println!("{}: {} blocks, {} scripts", name, target.blocks.len(), linked.scripts().count()),
+        Err(errors) => println!("{}: {:?}", name, errors),
+    }
+}
+
+fn print_program(lowered: &Lowered) {
+    let program = &lowered.program;
+    for sprite_scripts in program.sprite_scripts() {
+        println!("{}: {} scripts", sprite_scripts.sprite().name(), sprite_scripts.scripts().len());
+    }
+    let flag = program.scripts_triggered_by(&Event::FlagClicked).count();
+    println!("{} scripts run when the green flag is clicked", flag);
+    for function in program.functions() {
+        let warp = if function.warp() { ", without screen refresh" } else { "" };
+        println!("{}: custom block \"{}\" ({} params{})", function.sprite().name(), function.name(), function.params().len(), warp);
+    }
+    let graph = BroadcastGraph::new(program);
+    for broadcast in graph.sent_but_not_received() {
+        println!("broadcast \"{}\" is never received", broadcast.name());
+    }
+    for broadcast in graph.received_but_not_sent() {
+        println!("broadcast \"{}\" is never sent", broadcast.name());
+    }
+    let describe = |scoped: &ScopedVariable| {
+        let kind = if scoped.is_list { "list" } else { "variable" };
+        match scoped.owner {
+            Some(owner) => format!("{}'s {} \"{}\"", owner.name(), kind, scoped.variable.name()),
+            None => format!("global {} \"{}\"", kind, scoped.variable.name()),
+        }
+    };
+    for scoped in program.unused_variables() {
+        println!("{} is unused", describe(&scoped));
+    }
+    for scoped in program.write_only_variables() {
+        println!("{} is never read", describe(&scoped));
+    }
+    for (scoped, writers) in program.variables_written_by_multiple_sprites() {
+        let writers = writers.iter().map(|writer| writer.name()).collect::<Vec<_>>();
+        println!("{} is written by {}", describe(&scoped), writers.join(", "));
+    }
+    for error in &lowered.errors {
+        println!("{:?}", error);
+    }
+}
 
 fn main() {
     let path = ProjectPath::from_args().unwrap();
-    println!("{:?}", path);
+    // the command after the project path, if any
+    let command = std::env::args().nth(2);
+    if command.is_none() {
+        println!("{:?}", path);
+    }
     let mut archive = ProjectArchive::new(path).unwrap();
-    archive.print_entries();
+    if command.is_none() {
+        archive.print_entries();
+    }
+    if archive.is_sprite() {
+        let (sprite, _) = archive.load_sprite().unwrap();
+        print_target(&sprite.name, &sprite.target);
+        return;
+    }
+    let project = archive.load_project().unwrap();
+    let bump = Bump::new();
+    let lowered = lower::lower(&bump, &project);
+    match command.as_deref() {
+        None => print_program(&lowered),
+        Some("broadcasts") => print!("{}", BroadcastGraph::new(&lowered.program).to_dot()),
+        Some("compute-kinds") => {
+            let report = ComputeKindReport::new(&lowered.program);
+            println!("{}", serde_json::to_string_pretty(&report.to_json()).unwrap());
+        }
+        Some("run") => {
+            let stage = Stage::new(&lowered.program, &project);
+            let mut interpreter = Interpreter::new(&lowered.program, stage);
+            // for touching, which needs the costumes
+            let (renderer, errors) = Renderer::load(&mut archive, &project);
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            interpreter.set_renderer(renderer);
+            // the context has to outlive the compiled code
+            let context = Context::create();
+            for flag in std::env::args().skip(3) {
+                match flag.as_str() {
+                    "--jit" => match jit::jit(&lowered.program, codegen::compile(&context, &lowered.program)) {
+                        Ok(native) => interpreter.set_native(native),
+                        Err(error) => eprintln!("can't start the JIT: {}", error),
+                    },
+                    "--turbo" => interpreter.scheduler.set_turbo(true),
+                    // frames per second
+                    _ if flag.starts_with("--fps=") => match flag["--fps=".len()..].parse::<f64>() {
+                        Ok(fps) if fps > 0. => interpreter.scheduler.set_frame_rate(fps),
+                        _ => eprintln!("bad frame rate: {}", flag),
+                    },
+                    _ => eprintln!("unknown flag: {}", flag),
+                }
+            }
+            interpreter.answer_from_stdin();
+            if let Err(halt) = interpreter.green_flag() {
+                eprintln!("stopped early: {:?}", halt);
+            }
+            println!("{}", serde_json::to_string_pretty(&interpreter.variables_json()).unwrap());
+        }
+        Some("render") => {
+            // a PNG a frame, into frames unless given, until every script finishes or for as many frames as given
+            let dir = std::env::args().nth(3).map_or_else(|| PathBuf::from("frames"), PathBuf::from);
+            let limit = std::env::args().nth(4).and_then(|n| n.parse::<usize>().ok());
+            let (renderer, errors) = Renderer::load(&mut archive, &project);
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            std::fs::create_dir_all(&dir).unwrap();
+            let stage = Stage::new(&lowered.program, &project);
+            let mut interpreter = Interpreter::new(&lowered.program, stage);
+            interpreter.set_renderer(renderer);
+            interpreter.answer_from_stdin();
+            interpreter.start_green_flag();
+            // the first is before anything's run
+            let mut frames = 0;
+            loop {
+                let path = dir.join(format!("{:04}.png", frames));
+                let frame = interpreter.renderer().unwrap().render(&interpreter.stage);
+                if let Err(error) = frame.save_png(&path) {
+                    eprintln!("can't save {}: {:?}", path.display(), error);
+                    break;
+                }
+                frames += 1;
+                if interpreter.scheduler.is_empty() || limit.is_some_and(|limit| frames >= limit) {
+                    break;
+                }
+                if let Err(halt) = interpreter.frame() {
+                    eprintln!("stopped early: {:?}", halt);
+                    break;
+                }
+            }
+            println!("{} frames in {}", frames, dir.display());
+        }
+        Some("pen") => {
+            // runs until every script finishes, and saves what the pen drew to pen.png unless given
+            let path = std::env::args().nth(3).map_or_else(|| PathBuf::from("pen.png"), PathBuf::from);
+            let (renderer, errors) = Renderer::load(&mut archive, &project);
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            let stage = Stage::new(&lowered.program, &project);
+            let mut interpreter = Interpreter::new(&lowered.program, stage);
+            interpreter.set_renderer(renderer);
+            interpreter.answer_from_stdin();
+            if let Err(halt) = interpreter.green_flag() {
+                eprintln!("stopped early: {:?}", halt);
+            }
+            if let Err(error) = interpreter.renderer().unwrap().pen().save_png(&path) {
+                eprintln!("can't save {}: {:?}", path.display(), error);
+            }
+        }
+        Some("llvm") => {
+            let context = Context::create();
+            let compiled = codegen::compile(&context, &lowered.program);
+            for error in &compiled.errors {
+                eprintln!("{:?}", error);
+            }
+            if let Err(error) = compiled.module.verify() {
+                eprintln!("{}", error);
+            }
+            print!("{}", compiled.module.print_to_string());
+        }
+        Some("build") => {
+            // next to the project, without its extension, unless given
+            let output = std::env::args()
+                .nth(3)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| archive.path().path.with_extension(""));
+            let json = archive.read_project_json().unwrap();
+            let version = archive.path().version;
+            match aot::build(&lowered.program, &json, version, &aot::default_runtime(), &output) {
+                Ok(errors) => {
+                    for error in &errors {
+                        eprintln!("{:?}", error);
+                    }
+                    println!("{}", output.display());
+                }
+                Err(error) => eprintln!("can't build {}: {:?}", output.display(), error),
+            }
+        }
+        Some(command) => eprintln!("unknown command: {}", command),
+    }
 }
@@ -0,0 +1,206 @@
+// which scripts and custom blocks send each broadcast, and which scripts receive it
+
+use std::fmt::Write;
+
+use crate::scratch::ast::{Block, BroadCast, Event, Function, Program, Script, SpriteScripts};
+use crate::scratch::ast::instruction::Instruction;
+use crate::scratch::ast::instruction::control_flow::{BroadCastInstruction, ControlFlowInstruction};
+
+#[derive(Clone, Copy)]
+pub struct ScriptRef<'p, 'a> {
+    pub sprite_scripts: &'p SpriteScripts<'a>,
+    // the index into the sprite's scripts
+    pub index: usize,
+}
+
+impl<'p, 'a> ScriptRef<'p, 'a> {
+    pub fn script(&self) -> &'p Script<'a> {
+        &self.sprite_scripts.scripts()[self.index]
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Sender<'p, 'a> {
+    Script(ScriptRef<'p, 'a>),
+    Function(&'a Function<'a>),
+}
+
+#[derive(Clone, Copy)]
+pub struct Send<'p, 'a> {
+    pub sender: Sender<'p, 'a>,
+    pub broadcast: &'a BroadCast<'a>,
+    // broadcast and wait
+    pub wait: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct Receive<'p, 'a> {
+    pub broadcast: &'a BroadCast<'a>,
+    pub receiver: ScriptRef<'p, 'a>,
+}
+
+pub struct BroadcastGraph<'p, 'a> {
+    program: &'p Program<'a>,
+    sends: Vec<Send<'p, 'a>>,
+    receives: Vec<Receive<'p, 'a>>,
+}
+
+fn sends<'a>(block: &Block<'a>, sent: &mut Vec<(&'a BroadCast<'a>, bool)>) {
+    for instruction in block.instructions() {
+        if let Instruction::ControlFlow(control_flow) = instruction {
+            match control_flow {
+                ControlFlowInstruction::BroadCast(kind, broadcast) => {
+                    let wait = match kind {
+                        BroadCastInstruction::Resume => false,
+                        BroadCastInstruction::Wait => true,
+                    };
+                    sent.push((broadcast, wait));
+                }
+                ControlFlowInstruction::Branch { r#if, r#else, .. } => {
+                    sends(r#if, sent);
+                    sends(r#else, sent);
+                }
+                ControlFlowInstruction::While(_, body) | ControlFlowInstruction::Repeat(_, body) => sends(body, sent),
+                _ => {}
+            }
+        }
+    }
+}
+
+// for DOT's quoted strings
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<'p, 'a> BroadcastGraph<'p, 'a> {
+    pub fn new(program: &'p Program<'a>) -> BroadcastGraph<'p, 'a> {
+        let mut graph = BroadcastGraph {
+            program,
+            sends: Vec::new(),
+            receives: Vec::new(),
+        };
+        let mut sent = Vec::new();
+        for sprite_scripts in program.sprite_scripts() {
+            for (index, script) in sprite_scripts.scripts().iter().enumerate() {
+                let script_ref = ScriptRef { sprite_scripts, index };
+                if let Event::BroadcastReceived(broadcast) = script.trigger() {
+                    graph.receives.push(Receive {
+                        broadcast,
+                        receiver: script_ref,
+                    });
+                }
+                sends(script.block(), &mut sent);
+                graph.add_sends(Sender::Script(script_ref), &mut sent);
+            }
+        }
+        for function in program.functions() {
+            sends(function.body(), &mut sent);
+            graph.add_sends(Sender::Function(function), &mut sent);
+        }
+        graph
+    }
+
+    fn add_sends(&mut self, sender: Sender<'p, 'a>, sent: &mut Vec<(&'a BroadCast<'a>, bool)>) {
+        self.sends.extend(sent.drain(..).map(|(broadcast, wait)| Send { sender, broadcast, wait }));
+    }
+
+    pub fn sends(&self) -> &[Send<'p, 'a>] {
+        &self.sends[..]
+    }
+
+    pub fn receives(&self) -> &[Receive<'p, 'a>] {
+        &self.receives[..]
+    }
+
+    pub fn senders<'g>(&'g self, broadcast: &'a BroadCast<'a>) -> impl Iterator<Item = &'g Send<'p, 'a>> + 'g {
+        self.sends.iter().filter(move |send| std::ptr::eq(send.broadcast, broadcast))
+    }
+
+    pub fn receivers<'g>(&'g self, broadcast: &'a BroadCast<'a>) -> impl Iterator<Item = ScriptRef<'p, 'a>> + 'g {
+        self.receives
+            .iter()
+            .filter(move |receive| std::ptr::eq(receive.broadcast, broadcast))
+            .map(|receive| receive.receiver)
+    }
+
+    // messages that don't start any script
+    pub fn sent_but_not_received(&self) -> Vec<&'a BroadCast<'a>> {
+        self.program
+            .broadcasts()
+            .iter()
+            .copied()
+            .filter(|broadcast| self.senders(broadcast).next().is_some() && self.receivers(broadcast).next().is_none())
+            .collect()
+    }
+
+    // scripts that can never be started
+    pub fn received_but_not_sent(&self) -> Vec<&'a BroadCast<'a>> {
+        self.program
+            .broadcasts()
+            .iter()
+            .copied()
+            .filter(|broadcast| self.receivers(broadcast).next().is_some() && self.senders(broadcast).next().is_none())
+            .collect()
+    }
+
+    fn broadcast_node(&self, broadcast: &BroadCast) -> String {
+        format!("\"broadcast {}\"", escape(broadcast.id()))
+    }
+
+    fn script_node(&self, script: &ScriptRef) -> String {
+        format!("\"script {}/{}\"", escape(script.sprite_scripts.sprite().name()), script.index)
+    }
+
+    fn sender_node(&self, sender: &Sender) -> String {
+        match sender {
+            Sender::Script(script) => self.script_node(script),
+            Sender::Function(function) => {
+                format!("\"function {}/{}\"", escape(function.sprite().name()), escape(function.name()))
+            }
+        }
+    }
+
+    // broadcasts are boxes, filled red if they're only sent or only received,
+    // and broadcast and waits are dashed
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph broadcasts {{").unwrap();
+        let unmatched = self
+            .sent_but_not_received()
+            .into_iter()
+            .chain(self.received_but_not_sent())
+            .collect::<Vec<_>>();
+        for broadcast in self.program.broadcasts() {
+            let style = if unmatched.iter().any(|unmatched| std::ptr::eq(*unmatched, *broadcast)) {
+                ", style=filled, fillcolor=red"
+            } else {
+                ""
+            };
+            writeln!(dot, "    {} [label=\"{}\", shape=box{}];", self.broadcast_node(broadcast), escape(broadcast.name()), style).unwrap();
+        }
+        let mut senders = self.sends.iter().map(|send| send.sender).collect::<Vec<_>>();
+        senders.extend(self.receives.iter().map(|receive| Sender::Script(receive.receiver)));
+        let mut nodes = Vec::new();
+        for sender in senders {
+            let node = self.sender_node(&sender);
+            if nodes.contains(&node) {
+                continue;
+            }
+            let label = match sender {
+                Sender::Script(script) => format!("{}: script {}", script.sprite_scripts.sprite().name(), script.index + 1),
+                Sender::Function(function) => format!("{}: {}", function.sprite().name(), function.name()),
+            };
+            writeln!(dot, "    {} [label=\"{}\"];", node, escape(&label)).unwrap();
+            nodes.push(node);
+        }
+        for send in &self.sends {
+            let style = if send.wait { " [style=dashed]" } else { "" };
+            writeln!(dot, "    {} -> {}{};", self.sender_node(&send.sender), self.broadcast_node(send.broadcast), style).unwrap();
+        }
+        for receive in &self.receives {
+            writeln!(dot, "    {} -> {};", self.broadcast_node(receive.broadcast), self.script_node(&receive.receiver)).unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
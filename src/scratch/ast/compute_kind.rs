@@ -1,36 +1,117 @@
 use std::cmp::max;
+use std::collections::HashMap;
+
+use crate::scratch::ast::{Function, Variable};
 
 // order important, lower can be cast to higher
-#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash, Debug)]
 pub enum ComputeKind {
     Computational,
     Graphical,
     Reactive,
 }
 
-pub trait Computable {
-    fn get_compute_kind(&self) -> ComputeKind;
+pub trait Computable<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind;
 }
 
-impl Computable for ComputeKind {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for ComputeKind {
+    fn get_compute_kind(&self, _analysis: &mut Analysis<'a>) -> ComputeKind {
         *self
     }
 }
 
-impl<T: Computable, U: Computable> Computable for (&T, &U) {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a, T: Computable<'a>, U: Computable<'a>> Computable<'a> for (&T, &U) {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         let (a, b) = self;
-        max(a.get_compute_kind(), b.get_compute_kind())
+        max(a.get_compute_kind(analysis), b.get_compute_kind(analysis))
     }
 }
 
 // can't impl on an Iterator b/c (_, _) is an Iterator and can't specialize Traits
-impl<T> Computable for &[T] where T: Computable {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a, T> Computable<'a> for &[T] where T: Computable<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         self.iter()
-            .map(Computable::get_compute_kind)
+            .map(|t| t.get_compute_kind(analysis))
             .max()
             .unwrap_or(ComputeKind::Computational)
     }
 }
+
+// a custom block called with the compute kinds of its args
+type Call<'a> = (*const Function<'a>, Vec<ComputeKind>);
+
+// the params of the custom block being analyzed, bound to the compute kinds of its args
+struct Frame<'a> {
+    params: Vec<(*const Variable<'a>, ComputeKind)>,
+}
+
+// computes compute kinds with custom block params substituted by their args,
+// so the AST is never mutated and each call site gets its own answer
+#[derive(Default)]
+pub struct Analysis<'a> {
+    frames: Vec<Frame<'a>>,
+    // finished calls, in the order they finished
+    memo: HashMap<Call<'a>, ComputeKind>,
+    finished: Vec<Call<'a>>,
+    // the current guess for calls still being analyzed, which only recursion can see
+    guesses: HashMap<Call<'a>, ComputeKind>,
+}
+
+impl<'a> Analysis<'a> {
+    pub fn new() -> Analysis<'a> {
+        Analysis::default()
+    }
+
+    // the arg's compute kind if the variable is a param of the custom block being analyzed
+    pub fn param(&self, variable: &Variable<'a>) -> Option<ComputeKind> {
+        let frame = self.frames.last()?;
+        frame.params
+            .iter()
+            .find(|(param, _)| std::ptr::eq(*param, variable))
+            .map(|(_, kind)| *kind)
+    }
+
+    // a custom block on its own, with params that are plain values
+    pub fn function(&mut self, function: &'a Function<'a>) -> ComputeKind {
+        let args = vec![ComputeKind::Computational; function.params.len()];
+        self.call(function, args)
+    }
+
+    pub fn call(&mut self, function: &'a Function<'a>, args: Vec<ComputeKind>) -> ComputeKind {
+        let call = (function as *const _, args);
+        if let Some(&kind) = self.memo.get(&call) {
+            return kind;
+        }
+        if let Some(&kind) = self.guesses.get(&call) {
+            return kind;
+        }
+        // recursive calls start at the bottom of the lattice and are raised until nothing changes,
+        // which terminates since the lattice is finite and compute kinds only grow
+        let start = self.finished.len();
+        let mut guess = ComputeKind::Computational;
+        loop {
+            self.guesses.insert(call.clone(), guess);
+            // anything finished during the last round depended on a wrong guess
+            for stale in self.finished.drain(start..) {
+                self.memo.remove(&stale);
+            }
+            let params = function.params
+                .iter()
+                .zip(call.1.iter())
+                .map(|(param, kind)| (&param.variable as *const _, *kind))
+                .collect();
+            self.frames.push(Frame { params });
+            let kind = function.body().get_compute_kind(self);
+            self.frames.pop();
+            if kind == guess {
+                break;
+            }
+            guess = kind;
+        }
+        self.guesses.remove(&call);
+        self.memo.insert(call.clone(), guess);
+        self.finished.push(call);
+        guess
+    }
+}
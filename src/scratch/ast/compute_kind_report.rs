@@ -0,0 +1,352 @@
+// classifies every script and custom block by compute kind,
+// and finds the instructions that keep them from being purely computational
+
+use serde_json::json;
+
+use crate::scratch::ast::{Block, Event, Function, Key, Program, Script, Sprite, SpriteScripts};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
+use crate::scratch::ast::instruction::{Instruction, ReadWriteInstruction};
+use crate::scratch::ast::instruction::control_flow::{BroadCastInstruction, ControlFlowInstruction, StopInstruction};
+use crate::scratch::ast::instruction::function_call::CallInstruction;
+use crate::scratch::ast::instruction::property::PropertyInstruction;
+use crate::scratch::ast::instruction::render::{RenderInstruction, SayOrThink, SoundInstruction};
+
+// an instruction that forces its block up the lattice
+pub struct Cause<'p, 'a> {
+    pub instruction: &'p Instruction<'a>,
+    pub kind: ComputeKind,
+    pub description: String,
+}
+
+pub struct BlockReport<'p, 'a> {
+    pub kind: ComputeKind,
+    // in the order they appear, including those in nested blocks
+    pub causes: Vec<Cause<'p, 'a>>,
+}
+
+pub struct ScriptReport<'p, 'a> {
+    pub script: &'p Script<'a>,
+    // the index into the sprite's scripts
+    pub index: usize,
+    pub block: BlockReport<'p, 'a>,
+}
+
+pub struct FunctionReport<'p, 'a> {
+    pub function: &'a Function<'a>,
+    pub block: BlockReport<'p, 'a>,
+}
+
+pub struct SpriteReport<'p, 'a> {
+    pub sprite_scripts: &'p SpriteScripts<'a>,
+    // the highest of its scripts and custom blocks
+    pub kind: ComputeKind,
+    pub scripts: Vec<ScriptReport<'p, 'a>>,
+    pub functions: Vec<FunctionReport<'p, 'a>>,
+}
+
+pub struct ComputeKindReport<'p, 'a> {
+    pub sprites: Vec<SpriteReport<'p, 'a>>,
+}
+
+fn kind_name(kind: ComputeKind) -> &'static str {
+    match kind {
+        ComputeKind::Computational => "computational",
+        ComputeKind::Graphical => "graphical",
+        ComputeKind::Reactive => "reactive",
+    }
+}
+
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Char(c) => c.to_string(),
+        Key::Space => "space".to_string(),
+        Key::Enter => "enter".to_string(),
+        Key::Up => "up arrow".to_string(),
+        Key::Down => "down arrow".to_string(),
+        Key::Left => "left arrow".to_string(),
+        Key::Right => "right arrow".to_string(),
+        Key::Any => "any".to_string(),
+    }
+}
+
+fn describe_trigger(trigger: &Event, is_stage: bool) -> String {
+    match trigger {
+        Event::FlagClicked => "when flag clicked".to_string(),
+        Event::KeyPressed(key) => format!("when {} key pressed", key_name(*key)),
+        Event::Clicked if is_stage => "when stage clicked".to_string(),
+        Event::Clicked => "when this sprite clicked".to_string(),
+        Event::BackdropSwitchesTo(backdrop) => format!("when backdrop switches to \"{}\"", backdrop),
+        Event::GreaterThan(_, _) => "when greater than".to_string(),
+        Event::BroadcastReceived(broadcast) => format!("when I receive \"{}\"", broadcast.name()),
+        Event::StartAsClone => "when I start as a clone".to_string(),
+    }
+}
+
+fn describe_property(property: &PropertyInstruction) -> &'static str {
+    match property {
+        PropertyInstruction::Visibility(_) => "visibility",
+        PropertyInstruction::Position() => "position",
+        PropertyInstruction::MousePointer() => "mouse pointer",
+        PropertyInstruction::Direction() => "direction",
+        PropertyInstruction::Size() => "size",
+        PropertyInstruction::Volume() => "volume",
+        PropertyInstruction::Loudness() => "loudness",
+        PropertyInstruction::Timer() => "timer",
+        PropertyInstruction::CurrentTime(_) => "current time",
+        PropertyInstruction::Answer() => "answer",
+        PropertyInstruction::UserName() => "username",
+        PropertyInstruction::Named(_, _) => "costume or backdrop",
+        PropertyInstruction::RotationStyle(_) => "rotation style",
+        PropertyInstruction::Draggable() => "draggable",
+        PropertyInstruction::Layer() => "layer",
+        PropertyInstruction::Stage(_) => "stage property",
+        PropertyInstruction::MouseDown() => "mouse down",
+        PropertyInstruction::KeyPressed(_) => "key pressed",
+        PropertyInstruction::VisualEffect(_) => "graphic effect",
+        PropertyInstruction::AudioEffect(_) => "sound effect",
+        PropertyInstruction::Touching(_) => "touching",
+        PropertyInstruction::TouchingColor(_) => "touching color",
+        PropertyInstruction::ColorIsTouchingColor(_, _) => "color is touching color",
+    }
+}
+
+fn describe_read_write(read_write: &ReadWriteInstruction) -> String {
+    match read_write {
+        ReadWriteInstruction::Variable(variable) => format!("variable \"{}\"", variable.name()),
+        ReadWriteInstruction::List(list) => format!("list \"{}\"", list.name()),
+        ReadWriteInstruction::Property(property) => describe_property(property).to_string(),
+    }
+}
+
+fn describe(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::Op(CallInstruction::Function(call)) => format!("call \"{}\"", call.function().name()),
+        Instruction::Op(_) => "operator".to_string(),
+        Instruction::Get(_) => "reporter".to_string(),
+        Instruction::Set(set) => format!("set {}", describe_read_write(set.property())),
+        Instruction::List(_, _) => "list operation".to_string(),
+        Instruction::ControlFlow(control_flow) => match control_flow {
+            ControlFlowInstruction::BroadCast(BroadCastInstruction::Resume, broadcast) => {
+                format!("broadcast \"{}\"", broadcast.name())
+            }
+            ControlFlowInstruction::BroadCast(BroadCastInstruction::Wait, broadcast) => {
+                format!("broadcast \"{}\" and wait", broadcast.name())
+            }
+            ControlFlowInstruction::Wait(_) => "wait".to_string(),
+            ControlFlowInstruction::AskAndWait(_) => "ask and wait".to_string(),
+            ControlFlowInstruction::WaitUntil(_) => "wait until".to_string(),
+            ControlFlowInstruction::Branch { .. } => "if condition".to_string(),
+            ControlFlowInstruction::While(_, _) => "loop condition".to_string(),
+            ControlFlowInstruction::Repeat(_, _) => "repeat count".to_string(),
+            ControlFlowInstruction::Stop(StopInstruction::All()) => "stop all".to_string(),
+            ControlFlowInstruction::Stop(StopInstruction::OwnScript()) => "stop this script".to_string(),
+            ControlFlowInstruction::Stop(StopInstruction::OtherScriptsInSprite(_)) => {
+                "stop other scripts in sprite".to_string()
+            }
+            ControlFlowInstruction::CreateClone(sprite) => format!("create clone of \"{}\"", sprite.name()),
+            ControlFlowInstruction::DeleteSelf() => "delete this clone".to_string(),
+        },
+        Instruction::Render(render) => match render {
+            RenderInstruction::MoveSteps(_) => "move".to_string(),
+            RenderInstruction::GlideTo(_, _) => "glide".to_string(),
+            RenderInstruction::IfOnEdgeBounce() => "if on edge, bounce".to_string(),
+            RenderInstruction::SayOrThink(SayOrThink::Say, _, _) => "say".to_string(),
+            RenderInstruction::SayOrThink(SayOrThink::Think, _, _) => "think".to_string(),
+            RenderInstruction::ClearVisualEffects() => "clear graphic effects".to_string(),
+            RenderInstruction::SoundInstruction(SoundInstruction::Play, sound) => {
+                format!("play sound \"{}\" until done", sound.name())
+            }
+            RenderInstruction::SoundInstruction(SoundInstruction::Start, sound) => {
+                format!("start sound \"{}\"", sound.name())
+            }
+            RenderInstruction::StopAllSounds() => "stop all sounds".to_string(),
+            RenderInstruction::ClearAudioEffects() => "clear sound effects".to_string(),
+            RenderInstruction::PenDown(true) => "pen down".to_string(),
+            RenderInstruction::PenDown(false) => "pen up".to_string(),
+            RenderInstruction::SetPen(_, _) => "set pen".to_string(),
+            RenderInstruction::ChangePen(_, _) => "change pen".to_string(),
+            RenderInstruction::SetPenColor(_) => "set pen color".to_string(),
+            RenderInstruction::Stamp() => "stamp".to_string(),
+            RenderInstruction::ClearPen() => "erase all".to_string(),
+        },
+    }
+}
+
+// a compound instruction is only a cause through its own condition,
+// since the instructions in its blocks are reported on their own
+fn causes<'p, 'a>(block: &'p Block<'a>, analysis: &mut Analysis<'a>, found: &mut Vec<Cause<'p, 'a>>) {
+    for instruction in block.instructions() {
+        let kind = match instruction {
+            Instruction::ControlFlow(ControlFlowInstruction::Branch { condition, r#if, r#else }) => {
+                let kind = condition.get_compute_kind(analysis);
+                add_cause(instruction, kind, found);
+                causes(r#if, analysis, found);
+                causes(r#else, analysis, found);
+                continue;
+            }
+            Instruction::ControlFlow(ControlFlowInstruction::While(condition, body))
+            | Instruction::ControlFlow(ControlFlowInstruction::Repeat(condition, body)) => {
+                let kind = condition.get_compute_kind(analysis);
+                add_cause(instruction, kind, found);
+                causes(body, analysis, found);
+                continue;
+            }
+            _ => instruction.get_compute_kind(analysis),
+        };
+        add_cause(instruction, kind, found);
+    }
+}
+
+fn add_cause<'p, 'a>(instruction: &'p Instruction<'a>, kind: ComputeKind, found: &mut Vec<Cause<'p, 'a>>) {
+    if kind > ComputeKind::Computational {
+        found.push(Cause {
+            instruction,
+            kind,
+            description: describe(instruction),
+        });
+    }
+}
+
+fn block_report<'p, 'a>(block: &'p Block<'a>, kind: ComputeKind, analysis: &mut Analysis<'a>) -> BlockReport<'p, 'a> {
+    let mut found = Vec::new();
+    causes(block, analysis, &mut found);
+    BlockReport { kind, causes: found }
+}
+
+fn block_json(block: &BlockReport) -> serde_json::Value {
+    let causes = block
+        .causes
+        .iter()
+        .map(|cause| {
+            json!({
+                "instruction": cause.description,
+                "kind": kind_name(cause.kind),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "kind": kind_name(block.kind),
+        "causes": causes,
+    })
+}
+
+impl<'p, 'a> ComputeKindReport<'p, 'a> {
+    pub fn new(program: &'p Program<'a>) -> ComputeKindReport<'p, 'a> {
+        let mut analysis = Analysis::new();
+        let sprites = program
+            .sprite_scripts()
+            .iter()
+            .map(|sprite_scripts| {
+                let scripts = sprite_scripts
+                    .scripts()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, script)| {
+                        let kind = script.block().get_compute_kind(&mut analysis);
+                        ScriptReport {
+                            script,
+                            index,
+                            block: block_report(script.block(), kind, &mut analysis),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let functions = program
+                    .functions()
+                    .iter()
+                    .filter(|function| std::ptr::eq(function.sprite(), sprite_scripts.sprite()))
+                    .map(|function| {
+                        let kind = analysis.function(function);
+                        FunctionReport {
+                            function,
+                            block: block_report(function.body(), kind, &mut analysis),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let kind = scripts
+                    .iter()
+                    .map(|script| script.block.kind)
+                    .chain(functions.iter().map(|function| function.block.kind))
+                    .max()
+                    .unwrap_or(ComputeKind::Computational);
+                SpriteReport {
+                    sprite_scripts,
+                    kind,
+                    scripts,
+                    functions,
+                }
+            })
+            .collect();
+        ComputeKindReport { sprites }
+    }
+
+    // how many scripts and custom blocks are of each compute kind
+    pub fn counts(&self) -> [(ComputeKind, usize); 3] {
+        let mut counts = [
+            (ComputeKind::Computational, 0),
+            (ComputeKind::Graphical, 0),
+            (ComputeKind::Reactive, 0),
+        ];
+        let blocks = self.sprites.iter().flat_map(|sprite| {
+            let scripts = sprite.scripts.iter().map(|script| &script.block);
+            let functions = sprite.functions.iter().map(|function| &function.block);
+            scripts.chain(functions)
+        });
+        for block in blocks {
+            for (kind, count) in counts.iter_mut() {
+                if *kind == block.kind {
+                    *count += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let sprites = self
+            .sprites
+            .iter()
+            .map(|sprite| {
+                let is_stage = sprite.sprite_scripts.sprite().is_stage();
+                let scripts = sprite
+                    .scripts
+                    .iter()
+                    .map(|script| {
+                        let mut json = block_json(&script.block);
+                        json["index"] = json!(script.index);
+                        json["trigger"] = json!(describe_trigger(script.script.trigger(), is_stage));
+                        json
+                    })
+                    .collect::<Vec<_>>();
+                let functions = sprite
+                    .functions
+                    .iter()
+                    .map(|function| {
+                        let mut json = block_json(&function.block);
+                        json["name"] = json!(function.function.name());
+                        json
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "name": sprite.sprite_scripts.sprite().name(),
+                    "kind": kind_name(sprite.kind),
+                    "scripts": scripts,
+                    "functions": functions,
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut counts = serde_json::Map::new();
+        for (kind, count) in self.counts().iter() {
+            counts.insert(kind_name(*kind).to_string(), json!(count));
+        }
+        json!({
+            "sprites": sprites,
+            "counts": counts,
+        })
+    }
+}
+
+impl<'p, 'a> SpriteReport<'p, 'a> {
+    pub fn sprite(&self) -> &'a Sprite<'a> {
+        self.sprite_scripts.sprite()
+    }
+}
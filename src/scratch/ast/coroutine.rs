@@ -0,0 +1,224 @@
+// turns a block into a resumable state machine, so a script can stop where Scratch yields
+// and pick up from there later, which both the interpreter and the LLVM backend run
+//
+// each op is a state and control flow jumps between them, with repeat counters in numbered slots,
+// but instructions that can't yield are left whole, so only the control flow around yields is flattened
+//
+// outside warp mode, Scratch yields at the end of every loop iteration, so every loop is flattened, even though
+// it only redraws, letting time pass, once something's changed on screen or the frame's used up,
+// and in warp mode only waiting yields. anything that takes time waits for it a frame at a time either way
+
+use crate::scratch::ast::{Block, BroadCast, Function};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
+use crate::scratch::ast::instruction::{Instruction, Value};
+use crate::scratch::ast::instruction::control_flow::{BroadCastInstruction, ControlFlowInstruction, StopInstruction};
+use crate::scratch::ast::instruction::function_call::{CallInstruction, FunctionCallInstruction};
+use crate::scratch::ast::instruction::render::{RenderInstruction, SoundInstruction};
+
+// an index into the ops, where the ops' length is the end
+pub type State = usize;
+
+pub enum Op<'c, 'a> {
+    // runs to completion, since it can't yield
+    Exec(&'c Instruction<'a>),
+    // goes to the state unless the condition is true
+    Unless(&'c Value<'a>, State),
+    Goto(State),
+    // sets the slot to the rounded number of times
+    Times(usize, &'c Value<'a>),
+    // counts the slot down, or goes to the state once it's run out
+    CountDown(usize, State),
+    // the end of a loop iteration
+    Yield,
+    Wait(&'c Value<'a>),
+    // yields every frame until the condition is true
+    WaitUntil(&'c Value<'a>),
+    AskAndWait(&'c Value<'a>),
+    BroadcastAndWait(&'a BroadCast<'a>),
+    // a glide, a say or think for some seconds, or a sound played until it's done, which takes that long
+    Timed(&'c RenderInstruction<'a>),
+    // a custom block that yields, which runs as its own coroutine
+    Call(&'c FunctionCallInstruction<'a>),
+    // "stop this script"
+    Stop,
+}
+
+// the render instructions that take time, and wait until they're done
+fn is_timed(render: &RenderInstruction) -> bool {
+    matches!(
+        render,
+        RenderInstruction::GlideTo(_, _)
+            | RenderInstruction::SayOrThink(_, _, Some(_))
+            | RenderInstruction::SoundInstruction(SoundInstruction::Play, _)
+    )
+}
+
+// whether it can't just be run to completion, since it has something timed, or outside warp mode a loop,
+// whose iterations each yield, including in the custom blocks it calls
+fn must_flatten<'a>(instruction: &Instruction<'a>, warp: bool, calling: &mut Vec<*const Function<'a>>) -> bool {
+    match instruction {
+        Instruction::Render(render) => is_timed(render),
+        Instruction::ControlFlow(ControlFlowInstruction::While(_, body))
+        | Instruction::ControlFlow(ControlFlowInstruction::Repeat(_, body)) => !warp || block_must_flatten(body, warp, calling),
+        Instruction::ControlFlow(ControlFlowInstruction::Branch { r#if, r#else, .. }) => {
+            block_must_flatten(r#if, warp, calling) || block_must_flatten(r#else, warp, calling)
+        }
+        Instruction::Op(CallInstruction::Function(call)) => {
+            let function = call.function();
+            // a recursive call has to be flattened only if the first one does
+            if calling.contains(&(function as *const _)) {
+                return false;
+            }
+            calling.push(function);
+            let flatten = block_must_flatten(function.body(), warp || function.warp(), calling);
+            calling.pop();
+            flatten
+        }
+        _ => false,
+    }
+}
+
+fn block_must_flatten<'a>(block: &Block<'a>, warp: bool, calling: &mut Vec<*const Function<'a>>) -> bool {
+    block.instructions().iter().any(|instruction| must_flatten(instruction, warp, calling))
+}
+
+pub struct Coroutine<'c, 'a> {
+    ops: Vec<Op<'c, 'a>>,
+    slots: usize,
+    warp: bool,
+}
+
+impl<'c, 'a> Coroutine<'c, 'a> {
+    pub fn new(block: &'c Block<'a>, warp: bool, analysis: &mut Analysis<'a>) -> Coroutine<'c, 'a> {
+        let mut coroutine = Coroutine {
+            ops: Vec::new(),
+            slots: 0,
+            warp,
+        };
+        coroutine.block(block, analysis);
+        coroutine
+    }
+
+    pub fn ops(&self) -> &[Op<'c, 'a>] {
+        &self.ops[..]
+    }
+
+    // the number of repeat counters
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    // the custom blocks it calls are in warp mode too
+    pub fn warp(&self) -> bool {
+        self.warp
+    }
+
+    // whether it ever yields, or could just be run straight through
+    pub fn yields(&self) -> bool {
+        self.ops.iter().any(|op| {
+            !matches!(op, Op::Exec(_) | Op::Unless(_, _) | Op::Goto(_) | Op::Times(_, _) | Op::CountDown(_, _) | Op::Stop)
+        })
+    }
+
+    fn push(&mut self, op: Op<'c, 'a>) -> State {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    // points a forward jump at the next op
+    fn patch(&mut self, jump: State) {
+        let end = self.ops.len();
+        match &mut self.ops[jump] {
+            Op::Unless(_, target) | Op::Goto(target) | Op::CountDown(_, target) => *target = end,
+            _ => unreachable!(),
+        }
+    }
+
+    fn block(&mut self, block: &'c Block<'a>, analysis: &mut Analysis<'a>) {
+        for instruction in block.instructions() {
+            self.instruction(instruction, analysis);
+        }
+    }
+
+    fn instruction(&mut self, instruction: &'c Instruction<'a>, analysis: &mut Analysis<'a>) {
+        let straight = match instruction.get_compute_kind(analysis) {
+            ComputeKind::Computational => true,
+            ComputeKind::Graphical => self.warp,
+            ComputeKind::Reactive => false,
+        };
+        if straight && !must_flatten(instruction, self.warp, &mut Vec::new()) {
+            self.push(Op::Exec(instruction));
+            return;
+        }
+        match instruction {
+            Instruction::ControlFlow(control_flow) => self.control_flow(instruction, control_flow, analysis),
+            Instruction::Op(CallInstruction::Function(call)) => {
+                self.push(Op::Call(call));
+            }
+            Instruction::Render(render) if is_timed(render) => {
+                self.push(Op::Timed(render));
+            }
+            _ => {
+                self.push(Op::Exec(instruction));
+            }
+        }
+    }
+
+    fn end_iteration(&mut self, top: State) {
+        if !self.warp {
+            self.push(Op::Yield);
+        }
+        self.push(Op::Goto(top));
+    }
+
+    fn control_flow(
+        &mut self,
+        instruction: &'c Instruction<'a>,
+        control_flow: &'c ControlFlowInstruction<'a>,
+        analysis: &mut Analysis<'a>,
+    ) {
+        match control_flow {
+            ControlFlowInstruction::Branch { condition, r#if, r#else } => {
+                let unless = self.push(Op::Unless(condition, 0));
+                self.block(r#if, analysis);
+                let goto = self.push(Op::Goto(0));
+                self.patch(unless);
+                self.block(r#else, analysis);
+                self.patch(goto);
+            }
+            ControlFlowInstruction::While(condition, body) => {
+                let top = self.push(Op::Unless(condition, 0));
+                self.block(body, analysis);
+                self.end_iteration(top);
+                self.patch(top);
+            }
+            ControlFlowInstruction::Repeat(times, body) => {
+                let slot = self.slots;
+                self.slots += 1;
+                self.push(Op::Times(slot, times));
+                let top = self.push(Op::CountDown(slot, 0));
+                self.block(body, analysis);
+                self.end_iteration(top);
+                self.patch(top);
+            }
+            ControlFlowInstruction::Wait(duration) => {
+                self.push(Op::Wait(duration));
+            }
+            ControlFlowInstruction::WaitUntil(condition) => {
+                self.push(Op::WaitUntil(condition));
+            }
+            ControlFlowInstruction::AskAndWait(question) => {
+                self.push(Op::AskAndWait(question));
+            }
+            ControlFlowInstruction::BroadCast(BroadCastInstruction::Wait, broadcast) => {
+                self.push(Op::BroadcastAndWait(broadcast));
+            }
+            ControlFlowInstruction::Stop(StopInstruction::OwnScript()) => {
+                self.push(Op::Stop);
+            }
+            _ => {
+                self.push(Op::Exec(instruction));
+            }
+        }
+    }
+}
@@ -1,26 +1,32 @@
 use crate::scratch::ast::instruction::Value;
-use crate::scratch::ast::{Block, SpriteScripts, BroadCast, Script};
-use crate::scratch::ast::compute_kind::{Computable, ComputeKind};
+use crate::scratch::ast::{Block, Sprite, BroadCast};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
 
 pub enum ControlFlowInstruction<'a> {
-    BroadCast(BroadCastInstruction, &'a BroadCast),
+    BroadCast(BroadCastInstruction, &'a BroadCast<'a>),
     Wait(Value<'a>),
     AskAndWait(Value<'a>),
-    Branch {r#if: Block<'a>, r#else: Block<'a>},
-    While(Block<'a>),
+    WaitUntil(Value<'a>),
+    Branch {condition: Value<'a>, r#if: Block<'a>, r#else: Block<'a>},
+    While(Value<'a>, Block<'a>),
+    Repeat(Value<'a>, Block<'a>),
     Stop(StopInstruction<'a>),
-    CreateClone(&'a SpriteScripts<'a>),
+    CreateClone(&'a Sprite<'a>),
     DeleteSelf(),
 }
 
-impl Computable for ControlFlowInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for ControlFlowInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
             ControlFlowInstruction::BroadCast(_, _) => ComputeKind::Reactive,
             ControlFlowInstruction::Wait(_) => ComputeKind::Reactive,
             ControlFlowInstruction::AskAndWait(_) => ComputeKind::Reactive,
-            ControlFlowInstruction::Branch { r#if, r#else } => (r#if, r#else).get_compute_kind(),
-            ControlFlowInstruction::While(block) => block.get_compute_kind(),
+            ControlFlowInstruction::WaitUntil(_) => ComputeKind::Reactive,
+            ControlFlowInstruction::Branch { condition, r#if, r#else } => {
+                (condition, &(r#if, r#else).get_compute_kind(analysis)).get_compute_kind(analysis)
+            }
+            ControlFlowInstruction::While(condition, block) => (condition, block).get_compute_kind(analysis),
+            ControlFlowInstruction::Repeat(times, block) => (times, block).get_compute_kind(analysis),
             // TODO are these last three right?
             ControlFlowInstruction::Stop(_) => ComputeKind::Reactive,
             ControlFlowInstruction::CreateClone(_) => ComputeKind::Reactive,
@@ -35,6 +41,9 @@ pub enum BroadCastInstruction {
 }
 
 pub enum StopInstruction<'a> {
-    OwnScript(&'a Script<'a>),
-    OtherScriptsInSprite(&'a SpriteScripts<'a>),
+    // every script, like the stop sign
+    All(),
+    // in a custom block, this stops the script that called it too
+    OwnScript(),
+    OtherScriptsInSprite(&'a Sprite<'a>),
 }
@@ -1,4 +1,6 @@
-use crate::scratch::ast::compute_kind::{Computable, ComputeKind};
+use std::cmp::max;
+
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
 use crate::scratch::ast::Function;
 use crate::scratch::ast::instruction::{GetInstruction, Value};
 
@@ -12,18 +14,18 @@ pub enum CallInstruction<'a> {
     Function(FunctionCallInstruction<'a>),
 }
 
-impl Computable for CallInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for CallInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         // own compute kind is computational, so only sub compute kinds matter
         match self {
             CallInstruction::Id(get)
-            => get.get_compute_kind(),
+            => get.get_compute_kind(analysis),
             CallInstruction::UnaryOp { op: _, value }
-            => value.get_compute_kind(),
+            => value.get_compute_kind(analysis),
             CallInstruction::BinaryOp { op: _, left, right }
-            => (*left, *right).get_compute_kind(),
+            => (*left, *right).get_compute_kind(analysis),
             CallInstruction::Function(function)
-            => function.get_compute_kind(),
+            => function.get_compute_kind(analysis),
         }
     }
 }
@@ -33,6 +35,7 @@ pub enum UnaryOp {
     VectorIndex(u8),
     Not(),
     Abs(),
+    Length(),
     FloatToInt(FloatToIntOp),
     FloatToFloat(FloatToFloatOp),
 }
@@ -44,6 +47,7 @@ pub enum FloatToIntOp {
     Ceiling,
 }
 
+// the trigonometric ops are in degrees, like Scratch's
 #[derive(Clone, Copy)]
 pub enum FloatToFloatOp {
     Sqrt,
@@ -63,6 +67,9 @@ pub enum BinaryOp {
     Math(MathOp),
     Comparison(ComparisonOp),
     Logic(LogicOp),
+    String(StringOp),
+    // makes a position out of x and y
+    Vector(),
 }
 
 impl BinaryOp {
@@ -108,6 +115,14 @@ pub enum LogicOp {
     Or,
 }
 
+// LetterOf's left is the 1-based index, and Contains' left is the string searched in
+#[derive(Clone, Copy)]
+pub enum StringOp {
+    Join,
+    LetterOf,
+    Contains,
+}
+
 #[derive(Clone, Copy)]
 pub struct FunctionCallInstruction<'a> {
     function: &'a Function<'a>,
@@ -115,14 +130,28 @@ pub struct FunctionCallInstruction<'a> {
 }
 
 impl<'a> FunctionCallInstruction<'a> {
-    fn new(function: &'a Function<'a>, args: &'a [Value<'a>]) -> FunctionCallInstruction<'a> {
+    pub fn new(function: &'a Function<'a>, args: &'a [Value<'a>]) -> FunctionCallInstruction<'a> {
         assert_eq!(function.params.len(), args.len());
         FunctionCallInstruction { function, args }
     }
+    
+    pub fn function(&self) -> &'a Function<'a> {
+        self.function
+    }
+    
+    pub fn args(&self) -> &'a [Value<'a>] {
+        self.args
+    }
 }
 
-impl Computable for FunctionCallInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
-        self.function.get_compute_kind(self.args)
+impl<'a> Computable<'a> for FunctionCallInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
+        // the args are evaluated by the caller, then substituted for the params in the body
+        let args = self.args
+            .iter()
+            .map(|arg| arg.get_compute_kind(analysis))
+            .collect::<Vec<_>>();
+        let body = analysis.call(self.function, args.clone());
+        args.into_iter().fold(body, max)
     }
 }
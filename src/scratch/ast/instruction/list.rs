@@ -1,16 +1,16 @@
 use crate::scratch::ast::instruction::Value;
-use crate::scratch::ast::compute_kind::{Computable, ComputeKind};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
 
 pub enum ListInstruction<'a> {
     Void(VoidListInstruction<'a>),
     Returning(ReturningListInstruction<'a>),
 }
 
-impl Computable for ListInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for ListInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
-            ListInstruction::Void(op) => op.get_compute_kind(),
-            ListInstruction::Returning(op) => op.get_compute_kind(),
+            ListInstruction::Void(op) => op.get_compute_kind(analysis),
+            ListInstruction::Returning(op) => op.get_compute_kind(analysis),
         }
     }
 }
@@ -24,13 +24,13 @@ pub enum VoidListInstruction<'a> {
     Clear(),
 }
 
-impl Computable for VoidListInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for VoidListInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
-            VoidListInstruction::Set(index, value) => (index, value).get_compute_kind(),
-            VoidListInstruction::Insert(index, value) => (index, value).get_compute_kind(),
-            VoidListInstruction::Remove(value) => value.get_compute_kind(),
-            VoidListInstruction::Push(value, _) => value.get_compute_kind(),
+            VoidListInstruction::Set(index, value) => (index, value).get_compute_kind(analysis),
+            VoidListInstruction::Insert(index, value) => (index, value).get_compute_kind(analysis),
+            VoidListInstruction::Remove(value) => value.get_compute_kind(analysis),
+            VoidListInstruction::Push(value, _) => value.get_compute_kind(analysis),
             VoidListInstruction::Clear() => ComputeKind::Computational,
         }
     }
@@ -46,16 +46,16 @@ pub enum ReturningListInstruction<'a> {
     Concat(&'a Value<'a>),
 }
 
-impl Computable for ReturningListInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for ReturningListInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
             ReturningListInstruction::Length() => ComputeKind::Computational,
-            ReturningListInstruction::Get(index) => index.get_compute_kind(),
+            ReturningListInstruction::Get(index) => index.get_compute_kind(analysis),
             ReturningListInstruction::Pop(_) => ComputeKind::Computational,
-            ReturningListInstruction::Find(value) => value.get_compute_kind(),
-            ReturningListInstruction::Contains(value) => value.get_compute_kind(),
-            ReturningListInstruction::ContainsSeq(value) => value.get_compute_kind(),
-            ReturningListInstruction::Concat(value) => value.get_compute_kind(),
+            ReturningListInstruction::Find(value) => value.get_compute_kind(analysis),
+            ReturningListInstruction::Contains(value) => value.get_compute_kind(analysis),
+            ReturningListInstruction::ContainsSeq(value) => value.get_compute_kind(analysis),
+            ReturningListInstruction::Concat(value) => value.get_compute_kind(analysis),
         }
     }
 }
@@ -67,6 +67,21 @@ pub struct PushPop {
     // 0 is the normal behavior (e.x. pop the 0th element)
 }
 
+impl PushPop {
+    pub fn new(side: PushPopSide, index: u8) -> PushPop {
+        PushPop { side, index }
+    }
+    
+    pub fn side(&self) -> PushPopSide {
+        self.side
+    }
+    
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum PushPopSide {
     Left,
     Right,
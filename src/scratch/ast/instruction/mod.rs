@@ -1,9 +1,9 @@
-use crate::scratch::ast::{Constant, List, Variable};
-use crate::scratch::ast::compute_kind::{Computable, ComputeKind};
+use crate::scratch::ast::{Access, Constant, List, Variable};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
 use crate::scratch::ast::instruction::control_flow::ControlFlowInstruction;
 use crate::scratch::ast::instruction::function_call::CallInstruction;
 use crate::scratch::ast::instruction::list::{ListInstruction, ReturningListInstruction};
-use crate::scratch::ast::instruction::property::PropertyInstruction;
+use crate::scratch::ast::instruction::property::{PropertyInstruction, StageProperty};
 use crate::scratch::ast::instruction::render::RenderInstruction;
 
 pub mod function_call;
@@ -21,15 +21,15 @@ pub enum Instruction<'a> {
     Render(RenderInstruction<'a>),
 }
 
-impl Computable for Instruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for Instruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
-            Instruction::Op(op) => op.get_compute_kind(),
-            Instruction::Get(get) => get.get_compute_kind(),
-            Instruction::Set(set) => set.get_compute_kind(),
-            Instruction::List(op, list) => (op, *list).get_compute_kind(),
-            Instruction::ControlFlow(inst) => inst.get_compute_kind(),
-            Instruction::Render(inst) => inst.get_compute_kind(),
+            Instruction::Op(op) => op.get_compute_kind(analysis),
+            Instruction::Get(get) => get.get_compute_kind(analysis),
+            Instruction::Set(set) => set.get_compute_kind(analysis),
+            Instruction::List(op, list) => (op, *list).get_compute_kind(analysis),
+            Instruction::ControlFlow(inst) => inst.get_compute_kind(analysis),
+            Instruction::Render(inst) => inst.get_compute_kind(analysis),
         }
     }
 }
@@ -41,23 +41,38 @@ pub enum ReadWriteInstruction<'a> {
     Property(PropertyInstruction<'a>),
 }
 
-impl Computable for ReadWriteInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for ReadWriteInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
             // global variables and list are computational,
             // but variable that are custom block args are really values
-            ReadWriteInstruction::Variable(variable) => variable.get_compute_kind(),
-            ReadWriteInstruction::List(list) => list.get_compute_kind(),
-            ReadWriteInstruction::Property(property) => property.get_compute_kind(),
+            ReadWriteInstruction::Variable(variable) => variable.get_compute_kind(analysis),
+            ReadWriteInstruction::List(list) => list.get_compute_kind(analysis),
+            ReadWriteInstruction::Property(property) => property.get_compute_kind(analysis),
         }
     }
 }
 
 impl<'a> ReadWriteInstruction<'a> {
-    fn add_write(&mut self) {
-//        match self {
-//             TODO
-//        }
+    pub fn add_read(&self, access: Access<'a>) {
+        match self {
+            ReadWriteInstruction::Variable(variable) => variable.add_read(access),
+            ReadWriteInstruction::List(list) => list.variable().add_read(access),
+            // reading another target's variable, like the stage's through sensing_of
+            ReadWriteInstruction::Property(PropertyInstruction::Stage(StageProperty::Variable(variable))) => {
+                variable.add_read(access)
+            }
+            ReadWriteInstruction::Property(_) => {}
+        }
+    }
+    
+    // showing or hiding a variable doesn't write to it, since that's a property of its monitor
+    pub fn add_write(&self, access: Access<'a>) {
+        match self {
+            ReadWriteInstruction::Variable(variable) => variable.add_write(access),
+            ReadWriteInstruction::List(list) => list.variable().add_write(access),
+            ReadWriteInstruction::Property(_) => {}
+        }
     }
 }
 
@@ -67,12 +82,12 @@ pub enum GetInstruction<'a> {
     Element(ReturningListInstruction<'a>, &'a Value<'a>),
 }
 
-impl Computable for GetInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for GetInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
-            GetInstruction::Constant(constant) => constant.get_compute_kind(),
-            GetInstruction::ReadWrite(property) => property.get_compute_kind(),
-            GetInstruction::Element(op, list) => (op, *list).get_compute_kind(),
+            GetInstruction::Constant(constant) => constant.get_compute_kind(analysis),
+            GetInstruction::ReadWrite(property) => property.get_compute_kind(analysis),
+            GetInstruction::Element(op, list) => (op, *list).get_compute_kind(analysis),
         }
     }
 }
@@ -83,18 +98,26 @@ pub struct SetInstruction<'a> {
 }
 
 impl<'a> SetInstruction<'a> {
+    // the AST builder registers the write, since it knows where it's from
     pub fn new(property: ReadWriteInstruction<'a>, value: Value<'a>) -> SetInstruction<'a> {
-        // TODO property.add_write();
         SetInstruction {
             property,
             value,
         }
     }
+    
+    pub fn property(&self) -> &ReadWriteInstruction<'a> {
+        &self.property
+    }
+    
+    pub fn value(&self) -> &Value<'a> {
+        &self.value
+    }
 }
 
-impl Computable for SetInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
-        (&self.property, &self.value).get_compute_kind()
+impl<'a> Computable<'a> for SetInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
+        (&self.property, &self.value).get_compute_kind(analysis)
     }
 }
 
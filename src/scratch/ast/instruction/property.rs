@@ -1,6 +1,6 @@
 use crate::scratch::ast::instruction::Value;
-use crate::scratch::ast::{Variable, SpriteScripts, List};
-use crate::scratch::ast::compute_kind::{Computable, ComputeKind};
+use crate::scratch::ast::{Key, Variable, Sprite, List};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
 
 #[derive(Clone, Copy)]
 pub enum PropertyInstruction<'a> {
@@ -21,19 +21,20 @@ pub enum PropertyInstruction<'a> {
     Layer(),
     Stage(StageProperty<'a>),
     MouseDown(),
-    KeyPressed(char),
+    KeyPressed(Key),
     VisualEffect(VisualEffectType),
     AudioEffect(AudioEffectType),
+    Touching(TouchingObject<'a>),
     TouchingColor(Value<'a>),
     ColorIsTouchingColor(Value<'a>, Value<'a>),
 }
 
-impl Computable for PropertyInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for PropertyInstruction<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
             PropertyInstruction::UserName() => ComputeKind::Computational,
             PropertyInstruction::Named(_, _) => ComputeKind::Computational,
-            PropertyInstruction::Stage(property) => property.get_compute_kind(),
+            PropertyInstruction::Stage(property) => property.get_compute_kind(analysis),
             _ => ComputeKind::Graphical,
         }
     }
@@ -41,11 +42,19 @@ impl Computable for PropertyInstruction<'_> {
 
 #[derive(Clone, Copy)]
 pub enum VisibleThing<'a> {
-    Sprite(&'a SpriteScripts<'a>),
+    Sprite(&'a Sprite<'a>),
     Variable(&'a Variable<'a>),
     List(&'a List<'a>),
 }
 
+#[derive(Clone, Copy)]
+pub enum TouchingObject<'a> {
+    MousePointer,
+    Edge,
+    // or any of its clones
+    Sprite(&'a Sprite<'a>),
+}
+
 #[derive(Clone, Copy)]
 pub enum TimeUnit {
     Second,
@@ -84,8 +93,8 @@ pub enum StageProperty<'a> {
     Variable(&'a Variable<'a>),
 }
 
-impl Computable for StageProperty<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for StageProperty<'a> {
+    fn get_compute_kind(&self, _analysis: &mut Analysis<'a>) -> ComputeKind {
         match self {
             StageProperty::Backdrop(_) => ComputeKind::Computational,
             StageProperty::Volume() => ComputeKind::Graphical,
@@ -1,18 +1,29 @@
 use crate::scratch::ast::instruction::Value;
 use crate::scratch::ast::Sound;
-use crate::scratch::ast::compute_kind::{Computable, ComputeKind};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
 
 pub enum RenderInstruction<'a> {
-    GlideTo(Value<'a>),
+    // steps along the direction, so the steps are only evaluated once
+    MoveSteps(Value<'a>),
+    // seconds, then position
+    GlideTo(Value<'a>, Value<'a>),
+    IfOnEdgeBounce(),
     SayOrThink(SayOrThink, Value<'a>, Option<Value<'a>>),
     ClearVisualEffects(),
-    SoundInstruction(SoundInstruction, &'a Sound),
+    SoundInstruction(SoundInstruction, &'a Sound<'a>),
     StopAllSounds(),
     ClearAudioEffects(),
+    // the pen extension's
+    PenDown(bool),
+    SetPen(PenParam, Value<'a>),
+    ChangePen(PenParam, Value<'a>),
+    SetPenColor(Value<'a>),
+    Stamp(),
+    ClearPen(),
 }
 
-impl Computable for RenderInstruction<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for RenderInstruction<'a> {
+    fn get_compute_kind(&self, _analysis: &mut Analysis<'a>) -> ComputeKind {
         ComputeKind::Graphical
     }
 }
@@ -26,3 +37,15 @@ pub enum SoundInstruction {
     Play,
     Start,
 }
+
+#[derive(Clone, Copy)]
+pub enum PenParam {
+    Color,
+    Saturation,
+    Brightness,
+    Transparency,
+    Size,
+    // Scratch 2's, which set the color from a hue from 0 to 200 and a shade
+    Hue,
+    Shade,
+}
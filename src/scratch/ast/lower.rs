@@ -0,0 +1,1365 @@
+// lowers the blocks of an sb3 project into a Program,
+// reporting the opcodes and inputs that can't be lowered rather than silently dropping them
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+
+use crate::scratch::ast::{AST, Access, Block, BroadCast, Constant, Event, Function, GreaterThanProperty, Key, List, Number, Param, ParamType, Program, Scope, Script, Sound, Sprite, SpriteScripts, Variable};
+use crate::scratch::ast::instruction::{Instruction, ReadWriteInstruction, GetInstruction, Value};
+use crate::scratch::ast::instruction::control_flow::{BroadCastInstruction, ControlFlowInstruction, StopInstruction};
+use crate::scratch::ast::instruction::function_call::{BinaryOp, CallInstruction, ComparisonOp, FloatToFloatOp, FloatToIntOp, FunctionCallInstruction, LogicOp, MathOp, StringOp, UnaryOp};
+use crate::scratch::ast::instruction::list::{ListInstruction, PushPop, PushPopSide, ReturningListInstruction, VoidListInstruction};
+use crate::scratch::ast::instruction::property::{AudioEffectType, NamedProperty, NamedType, PropertyInstruction, RotationStyle, StageProperty, TimeUnit, TouchingObject, VisibleThing, VisualEffectType};
+use crate::scratch::ast::instruction::render::{PenParam, RenderInstruction, SayOrThink, SoundInstruction};
+use crate::scratch::parse::project::link::{self, InputLink, LinkedBlock, LinkedTarget};
+use crate::scratch::parse::project::opcode::{ControlOpCode, DataOpCode, EventOpCode, LooksOpCode, MotionOpCode, OperatorOpCode, PenOpCode, ProcedureOpCode, SensingOpCode, SoundOpCode};
+use crate::scratch::parse::project::sb3::{self, BlockId, CategoryOpCode, NumberOrString, OpCode, Primitive, ScalarValue, VariableType};
+
+use Lowering::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Link { target: String, errors: Vec<link::Error> },
+    UnsupportedOpCode { target: String, block: BlockId, op_code: String },
+    // an input or field that can't be lowered, like a reporter dropped in a menu that only takes constants
+    UnsupportedInput { target: String, block: BlockId, input: String },
+    // a variable, list, sound, or sprite that doesn't exist
+    UnknownReference { target: String, block: BlockId, name: String },
+}
+
+pub struct Lowered<'a> {
+    pub program: Program<'a>,
+    pub errors: Vec<Error>,
+}
+
+fn number(number: &sb3::Number) -> Number {
+    match *number {
+        sb3::Number::UInt(n) => Number::UInt(n),
+        sb3::Number::Int(n) => Number::Int(n),
+        sb3::Number::Float(n) => Number::Float(n),
+    }
+}
+
+fn scalar_string(value: &ScalarValue) -> String {
+    match value {
+        ScalarValue::Bool(b) => b.to_string(),
+        ScalarValue::Number(sb3::Number::UInt(n)) => n.to_string(),
+        ScalarValue::Number(sb3::Number::Int(n)) => n.to_string(),
+        ScalarValue::Number(sb3::Number::Float(n)) => n.to_string(),
+        ScalarValue::String(s) => s.clone(),
+    }
+}
+
+// Scratch joins a list's items with spaces, unless they're all single characters
+fn list_contents(items: &[ScalarValue]) -> String {
+    let items = items.iter().map(scalar_string).collect::<Vec<_>>();
+    let separator = if items.iter().all(|item| item.chars().count() == 1) { "" } else { " " };
+    items.join(separator)
+}
+
+fn is_hat(op_code: &OpCode) -> bool {
+    match op_code.op_code {
+        CategoryOpCode::Event(op) => matches!(
+            op,
+            EventOpCode::WhenFlagClicked
+                | EventOpCode::WhenKeyPressed
+                | EventOpCode::WhenThisSpriteClicked
+                | EventOpCode::WhenStageClicked
+                | EventOpCode::WhenBackdropSwitchesTo
+                | EventOpCode::WhenGreaterThan
+                | EventOpCode::WhenBroadcastReceived
+                | EventOpCode::WhenTouchingObject
+        ),
+        CategoryOpCode::Control(ControlOpCode::StartAsClone) => true,
+        _ => false,
+    }
+}
+
+fn visual_effect(name: &str) -> Option<VisualEffectType> {
+    Some(match name.to_ascii_uppercase().as_str() {
+        "COLOR" => VisualEffectType::Color,
+        "FISHEYE" => VisualEffectType::Fisheye,
+        "WHIRL" => VisualEffectType::Whirl,
+        "PIXELATE" => VisualEffectType::Pixelate,
+        "MOSAIC" => VisualEffectType::Mosaic,
+        "BRIGHTNESS" => VisualEffectType::Brightness,
+        "GHOST" => VisualEffectType::Ghost,
+        _ => return None,
+    })
+}
+
+fn pen_color_param(name: &str) -> Option<PenParam> {
+    Some(match name {
+        "color" => PenParam::Color,
+        "saturation" => PenParam::Saturation,
+        "brightness" => PenParam::Brightness,
+        "transparency" => PenParam::Transparency,
+        _ => return None,
+    })
+}
+
+fn audio_effect(name: &str) -> Option<AudioEffectType> {
+    Some(match name.to_ascii_uppercase().as_str() {
+        "PITCH" => AudioEffectType::Pitch,
+        "PAN" => AudioEffectType::PanLeftRight,
+        _ => return None,
+    })
+}
+
+fn rotation_style(name: &str) -> Option<RotationStyle> {
+    Some(match name {
+        "left-right" => RotationStyle::LeftRight,
+        "don't rotate" => RotationStyle::DontRotate,
+        "all around" => RotationStyle::AllAround,
+        _ => return None,
+    })
+}
+
+fn named_property(name: &str) -> Option<NamedProperty> {
+    Some(match name {
+        "number" => NamedProperty::Number,
+        "name" => NamedProperty::Name,
+        _ => return None,
+    })
+}
+
+// sb2 menus have spaces, like "day of week"
+fn time_unit(name: &str) -> Option<TimeUnit> {
+    Some(match name.to_ascii_uppercase().replace(' ', "").as_str() {
+        "SECOND" => TimeUnit::Second,
+        "MINUTE" => TimeUnit::Minute,
+        "HOUR" => TimeUnit::Hour,
+        "DAYOFWEEK" => TimeUnit::DayOfWeek,
+        "DATE" => TimeUnit::Date,
+        "MONTH" => TimeUnit::Month,
+        "YEAR" => TimeUnit::Year,
+        _ => return None,
+    })
+}
+
+fn key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    Some(match (name, chars.next(), chars.next()) {
+        ("space", _, _) => Key::Space,
+        ("enter", _, _) => Key::Enter,
+        ("up arrow", _, _) => Key::Up,
+        ("down arrow", _, _) => Key::Down,
+        ("left arrow", _, _) => Key::Left,
+        ("right arrow", _, _) => Key::Right,
+        ("any", _, _) => Key::Any,
+        (_, Some(c), None) => Key::Char(c.to_ascii_lowercase()),
+        _ => return None,
+    })
+}
+
+fn greater_than_property(name: &str) -> Option<GreaterThanProperty> {
+    Some(match name.to_ascii_uppercase().as_str() {
+        "LOUDNESS" => GreaterThanProperty::Loudness,
+        "TIMER" => GreaterThanProperty::Timer,
+        _ => return None,
+    })
+}
+
+// variables and lists are looked up by ID, then by name since Scratch does that too
+struct Lookup<'a, T> {
+    ids: HashMap<&'a str, &'a T>,
+    names: HashMap<&'a str, &'a T>,
+}
+
+impl<'a, T> Lookup<'a, T> {
+    fn new(entries: impl Iterator<Item = (&'a sb3::Variable, &'a T)>) -> Lookup<'a, T> {
+        let mut lookup = Lookup {
+            ids: HashMap::new(),
+            names: HashMap::new(),
+        };
+        for (variable, value) in entries {
+            lookup.ids.insert(variable.id.as_str(), value);
+            lookup.names.insert(variable.name.as_str(), value);
+        }
+        lookup
+    }
+
+    fn find(local: &Self, global: &Self, id: Option<&str>, name: &str) -> Option<&'a T> {
+        id.and_then(|id| local.ids.get(id).or_else(|| global.ids.get(id)))
+            .or_else(|| local.names.get(name).or_else(|| global.names.get(name)))
+            .copied()
+    }
+}
+
+struct Names<'a> {
+    scope: &'a Scope<'a>,
+    variables: Lookup<'a, Variable<'a>>,
+    lists: Lookup<'a, List<'a>>,
+}
+
+impl<'a> Names<'a> {
+    // broadcasts are only used by name, so they aren't in a Scope
+    fn new(ast: AST<'a>, variables: &'a [sb3::Variable]) -> Names<'a> {
+        let mut scope = Scope {
+            variables: ast.vec(),
+            lists: ast.vec(),
+        };
+        let mut scalars = Vec::new();
+        let mut lists = Vec::new();
+        for sb3_variable in variables {
+            match &sb3_variable.value {
+                sb3::Value::Scalar(value) => {
+                    let value = ast.constant(ast.scalar(value));
+                    scope.variables.push(ast.variable(&sb3_variable.name, value));
+                    scalars.push(sb3_variable);
+                }
+                sb3::Value::List(items) => {
+                    let contents = ast.constant(Constant::String(ast.str(&list_contents(items))));
+                    scope.lists.push(List {
+                        variable: ast.variable(&sb3_variable.name, contents),
+                        items: BumpVec::from_iter_in(items.iter().map(|item| ast.scalar(item)), ast.bump),
+                        element_reads: ast.vec(),
+                        element_writes: ast.vec(),
+                    });
+                    lists.push(sb3_variable);
+                }
+                sb3::Value::Broadcast(_) => {}
+            }
+        }
+        let scope: &'a Scope<'a> = ast.alloc(scope);
+        Names {
+            scope,
+            variables: Lookup::new(scalars.into_iter().zip(scope.variables.iter())),
+            lists: Lookup::new(lists.into_iter().zip(scope.lists.iter())),
+        }
+    }
+}
+
+impl<'a> AST<'a> {
+    fn variable(&self, name: &str, value: Value<'a>) -> Variable<'a> {
+        Variable {
+            name: self.string(name),
+            value,
+            reads: RefCell::new(self.vec()),
+            writes: RefCell::new(self.vec()),
+        }
+    }
+
+    // what Scratch passes for an argument that's left empty
+    fn param_default(&self, r#type: ParamType) -> Value<'a> {
+        self.constant(match r#type {
+            ParamType::StringOrNumber => Constant::String(""),
+            ParamType::Boolean => Constant::Bool(false),
+        })
+    }
+
+    fn scalar(&self, value: &ScalarValue) -> Constant<'a> {
+        match value {
+            ScalarValue::Bool(b) => Constant::Bool(*b),
+            ScalarValue::Number(n) => Constant::Number(number(n)),
+            ScalarValue::String(s) => Constant::String(self.str(s)),
+        }
+    }
+
+    fn empty_block(&self) -> Block<'a> {
+        Block {
+            instructions: self.vec(),
+        }
+    }
+}
+
+enum Lowering<'a> {
+    Statement(Instruction<'a>),
+    Reporter(Value<'a>),
+    // the opcode isn't supported
+    Unsupported,
+    // an error has already been reported for one of the block's inputs or fields
+    Invalid,
+}
+
+struct Lowerer<'a> {
+    ast: AST<'a>,
+    errors: Vec<Error>,
+    globals: Names<'a>,
+    sprites: HashMap<&'a str, &'a Sprite<'a>>,
+    broadcasts: BumpVec<'a, &'a BroadCast<'a>>,
+    all_functions: BumpVec<'a, &'a Function<'a>>,
+    // the target currently being lowered
+    target: &'a str,
+    sprite: &'a Sprite<'a>,
+    locals: Names<'a>,
+    // the target's custom blocks by proccode, with their argument IDs
+    functions: HashMap<&'a str, (&'a Function<'a>, &'a [String])>,
+    // the custom block whose body is being lowered
+    function: Option<&'a Function<'a>>,
+}
+
+impl<'a> Lowerer<'a> {
+    fn error(&mut self, block: &LinkedBlock, error: impl FnOnce(String, BlockId) -> Error) {
+        let error = error(self.target.to_owned(), block.id.to_owned());
+        self.errors.push(error);
+    }
+
+    fn unsupported_input(&mut self, block: &LinkedBlock, input: &str) -> Lowering<'a> {
+        self.error(block, |target, block| Error::UnsupportedInput {
+            target,
+            block,
+            input: input.to_owned(),
+        });
+        Invalid
+    }
+
+    fn unknown(&mut self, block: &LinkedBlock, name: &str) {
+        self.error(block, |target, block| Error::UnknownReference {
+            target,
+            block,
+            name: name.to_owned(),
+        });
+    }
+
+    fn empty(&self) -> Value<'a> {
+        self.ast.constant(Constant::String(""))
+    }
+
+    fn number(&self, n: f64) -> Value<'a> {
+        self.ast.constant(Constant::Number(Number::Float(n)))
+    }
+
+    fn bool(&self, b: bool) -> Value<'a> {
+        self.ast.constant(Constant::Bool(b))
+    }
+
+    fn number_or_string(&self, value: &'a NumberOrString) -> Value<'a> {
+        self.ast.constant(match value {
+            NumberOrString::Number(n) => Constant::Number(number(n)),
+            NumberOrString::String(s) => Constant::String(s),
+        })
+    }
+
+    fn variable(&mut self, block: &LinkedBlock, id: Option<&str>, name: &str) -> Option<&'a Variable<'a>> {
+        let variable = Lookup::find(&self.locals.variables, &self.globals.variables, id, name);
+        if variable.is_none() {
+            self.unknown(block, name);
+        }
+        variable
+    }
+
+    fn list(&mut self, block: &LinkedBlock, id: Option<&str>, name: &str) -> Option<&'a List<'a>> {
+        let list = Lookup::find(&self.locals.lists, &self.globals.lists, id, name);
+        if list.is_none() {
+            self.unknown(block, name);
+        }
+        list
+    }
+
+    fn field_variable(&mut self, block: &'a LinkedBlock<'a>) -> Option<&'a Variable<'a>> {
+        let field = block.block.fields.get("VARIABLE")?;
+        self.variable(block, field.id.as_deref(), &field.value)
+    }
+
+    fn field_list(&mut self, block: &'a LinkedBlock<'a>) -> Option<&'a List<'a>> {
+        let field = block.block.fields.get("LIST")?;
+        self.list(block, field.id.as_deref(), &field.value)
+    }
+
+    fn primitive(&mut self, block: &LinkedBlock, primitive: &'a Primitive) -> Value<'a> {
+        match primitive {
+            Primitive::Num(primitive) => self.number_or_string(&primitive.value),
+            Primitive::Text(text) => self.number_or_string(text),
+            Primitive::Color(color) => {
+                let rgb = u64::from(color.r) << 16 | u64::from(color.g) << 8 | u64::from(color.b);
+                self.ast.constant(Constant::Number(Number::UInt(rgb)))
+            }
+            Primitive::Index(index) => self.ast.constant(Constant::Number(Number::Int(index.value.into()))),
+            Primitive::Variable(variable) => match variable.r#type {
+                VariableType::Scalar => match self.variable(block, Some(&variable.id), &variable.name) {
+                    Some(variable) => self.ast.get(ReadWriteInstruction::Variable(variable)),
+                    None => self.empty(),
+                },
+                VariableType::List => match self.list(block, Some(&variable.id), &variable.name) {
+                    Some(list) => self.ast.get(ReadWriteInstruction::List(list)),
+                    None => self.empty(),
+                },
+                VariableType::Broadcast => self.ast.constant(Constant::String(&variable.name)),
+            },
+        }
+    }
+
+    // menus are shadow blocks with a single field and no inputs
+    fn menu_block(block: &'a LinkedBlock<'a>) -> Option<&'a sb3::Field> {
+        if block.block.shadow && block.inputs().is_empty() && block.block.fields.len() == 1 {
+            block.block.fields.values().next()
+        } else {
+            None
+        }
+    }
+
+    // the constant chosen in a menu input, or none if a reporter has been dropped in it
+    fn menu(&self, block: &'a LinkedBlock<'a>, input: &str) -> Option<&'a str> {
+        match block.input_value(input)? {
+            InputLink::Block(menu) => Self::menu_block(menu).map(|field| field.value.as_str()),
+            InputLink::Primitive(Primitive::Text(NumberOrString::String(s))) => Some(s),
+            _ => None,
+        }
+    }
+
+    // a missing input is the empty string, like in Scratch
+    fn input(&mut self, block: &'a LinkedBlock<'a>, input: &str) -> Value<'a> {
+        match block.input_value(input) {
+            Some(InputLink::Primitive(primitive)) => self.primitive(block, primitive),
+            Some(InputLink::Block(value)) => match Self::menu_block(value) {
+                Some(field) => self.ast.constant(Constant::String(&field.value)),
+                None => self.reporter(value),
+            },
+            Some(InputLink::Stack(_)) | None => self.empty(),
+        }
+    }
+
+    fn substack(&mut self, block: &'a LinkedBlock<'a>, input: &str) -> Block<'a> {
+        self.block(block.substack(input))
+    }
+
+    fn property(&self, property: PropertyInstruction<'a>) -> Value<'a> {
+        self.ast.get(ReadWriteInstruction::Property(property))
+    }
+
+    fn set_property(&self, property: PropertyInstruction<'a>, value: Value<'a>) -> Lowering<'a> {
+        Statement(Instruction::Set(self.ast.set(ReadWriteInstruction::Property(property), value)))
+    }
+
+    fn change_property(&self, property: PropertyInstruction<'a>, delta: Value<'a>) -> Lowering<'a> {
+        Statement(Instruction::Set(self.ast.change_by(ReadWriteInstruction::Property(property), delta)))
+    }
+
+    fn x(&self) -> Value<'a> {
+        self.ast.op1(UnaryOp::VectorIndex(0), self.property(PropertyInstruction::Position()))
+    }
+
+    fn y(&self) -> Value<'a> {
+        self.ast.op1(UnaryOp::VectorIndex(1), self.property(PropertyInstruction::Position()))
+    }
+
+    fn set_position(&self, x: Value<'a>, y: Value<'a>) -> Lowering<'a> {
+        self.set_property(PropertyInstruction::Position(), self.ast.op2(BinaryOp::Vector(), x, y))
+    }
+
+    fn random(&self, from: f64, to: f64) -> Value<'a> {
+        self.ast.op2(BinaryOp::Math(MathOp::Random), self.number(from), self.number(to))
+    }
+
+    // the position chosen in a goto or glide menu
+    fn menu_position(&self, block: &'a LinkedBlock<'a>, input: &str) -> Option<Value<'a>> {
+        match self.menu(block, input)? {
+            "_mouse_" => Some(self.property(PropertyInstruction::MousePointer())),
+            "_random_" => Some(self.ast.op2(BinaryOp::Vector(), self.random(-240., 240.), self.random(-180., 180.))),
+            // other sprites' positions aren't modeled yet
+            _ => None,
+        }
+    }
+
+    fn control(&self, instruction: ControlFlowInstruction<'a>) -> Lowering<'a> {
+        Statement(Instruction::ControlFlow(instruction))
+    }
+
+    fn render(&self, instruction: RenderInstruction<'a>) -> Lowering<'a> {
+        Statement(Instruction::Render(instruction))
+    }
+
+    fn motion(&mut self, block: &'a LinkedBlock<'a>, op: MotionOpCode) -> Lowering<'a> {
+        let ast = self.ast;
+        match op {
+            MotionOpCode::MoveSteps => {
+                let steps = self.input(block, "STEPS");
+                self.render(RenderInstruction::MoveSteps(steps))
+            }
+            MotionOpCode::TurnRight => {
+                let degrees = self.input(block, "DEGREES");
+                self.change_property(PropertyInstruction::Direction(), degrees)
+            }
+            MotionOpCode::TurnLeft => {
+                let degrees = self.input(block, "DEGREES");
+                let direction = ReadWriteInstruction::Property(PropertyInstruction::Direction());
+                Statement(Instruction::Set(ast.change_using(BinaryOp::subtract(), direction, degrees)))
+            }
+            MotionOpCode::GoTo => match self.menu_position(block, "TO") {
+                Some(position) => self.set_property(PropertyInstruction::Position(), position),
+                None => self.unsupported_input(block, "TO"),
+            },
+            MotionOpCode::GoToXY => {
+                let x = self.input(block, "X");
+                let y = self.input(block, "Y");
+                self.set_position(x, y)
+            }
+            MotionOpCode::GlideTo => {
+                let secs = self.input(block, "SECS");
+                match self.menu_position(block, "TO") {
+                    Some(position) => self.render(RenderInstruction::GlideTo(secs, position)),
+                    None => self.unsupported_input(block, "TO"),
+                }
+            }
+            MotionOpCode::GlideSecsToXY => {
+                let secs = self.input(block, "SECS");
+                let x = self.input(block, "X");
+                let y = self.input(block, "Y");
+                self.render(RenderInstruction::GlideTo(secs, ast.op2(BinaryOp::Vector(), x, y)))
+            }
+            MotionOpCode::PointInDirection => {
+                let direction = self.input(block, "DIRECTION");
+                self.set_property(PropertyInstruction::Direction(), direction)
+            }
+            MotionOpCode::ChangeXBy => {
+                let dx = self.input(block, "DX");
+                self.set_position(ast.op2(BinaryOp::add(), self.x(), dx), self.y())
+            }
+            MotionOpCode::SetX => {
+                let x = self.input(block, "X");
+                self.set_position(x, self.y())
+            }
+            MotionOpCode::ChangeYBy => {
+                let dy = self.input(block, "DY");
+                self.set_position(self.x(), ast.op2(BinaryOp::add(), self.y(), dy))
+            }
+            MotionOpCode::SetY => {
+                let y = self.input(block, "Y");
+                self.set_position(self.x(), y)
+            }
+            MotionOpCode::IfOnEdgeBounce => self.render(RenderInstruction::IfOnEdgeBounce()),
+            // the property is set to true for the chosen style, like Visibility
+            MotionOpCode::SetRotationStyle => match block.field("STYLE").and_then(rotation_style) {
+                Some(style) => self.set_property(PropertyInstruction::RotationStyle(style), self.bool(true)),
+                None => self.unsupported_input(block, "STYLE"),
+            },
+            MotionOpCode::XPosition => Reporter(self.x()),
+            MotionOpCode::YPosition => Reporter(self.y()),
+            MotionOpCode::Direction => Reporter(self.property(PropertyInstruction::Direction())),
+            _ => Unsupported,
+        }
+    }
+
+    fn looks(&mut self, block: &'a LinkedBlock<'a>, op: LooksOpCode) -> Lowering<'a> {
+        let ast = self.ast;
+        let costume = |property| PropertyInstruction::Named(NamedType::Costume, property);
+        let backdrop = |property| PropertyInstruction::Named(NamedType::Backdrop, property);
+        match op {
+            LooksOpCode::SayForSecs | LooksOpCode::ThinkForSecs => {
+                let message = self.input(block, "MESSAGE");
+                let secs = self.input(block, "SECS");
+                let kind = if op == LooksOpCode::SayForSecs { SayOrThink::Say } else { SayOrThink::Think };
+                self.render(RenderInstruction::SayOrThink(kind, message, Some(secs)))
+            }
+            LooksOpCode::Say | LooksOpCode::Think => {
+                let message = self.input(block, "MESSAGE");
+                let kind = if op == LooksOpCode::Say { SayOrThink::Say } else { SayOrThink::Think };
+                self.render(RenderInstruction::SayOrThink(kind, message, None))
+            }
+            LooksOpCode::SwitchCostumeTo => {
+                let value = self.input(block, "COSTUME");
+                self.set_property(costume(NamedProperty::Name), value)
+            }
+            LooksOpCode::NextCostume => self.change_property(costume(NamedProperty::Number), self.number(1.)),
+            LooksOpCode::SwitchBackdropTo => {
+                let value = self.input(block, "BACKDROP");
+                self.set_property(backdrop(NamedProperty::Name), value)
+            }
+            LooksOpCode::NextBackdrop => self.change_property(backdrop(NamedProperty::Number), self.number(1.)),
+            LooksOpCode::ChangeSizeBy => {
+                let change = self.input(block, "CHANGE");
+                self.change_property(PropertyInstruction::Size(), change)
+            }
+            LooksOpCode::SetSizeTo => {
+                let size = self.input(block, "SIZE");
+                self.set_property(PropertyInstruction::Size(), size)
+            }
+            LooksOpCode::ChangeEffectBy | LooksOpCode::SetEffectTo => {
+                let effect = match block.field("EFFECT").and_then(visual_effect) {
+                    Some(effect) => PropertyInstruction::VisualEffect(effect),
+                    None => return self.unsupported_input(block, "EFFECT"),
+                };
+                if op == LooksOpCode::ChangeEffectBy {
+                    let change = self.input(block, "CHANGE");
+                    self.change_property(effect, change)
+                } else {
+                    let value = self.input(block, "VALUE");
+                    self.set_property(effect, value)
+                }
+            }
+            LooksOpCode::ClearGraphicEffects => self.render(RenderInstruction::ClearVisualEffects()),
+            LooksOpCode::Show | LooksOpCode::Hide => {
+                let visibility = PropertyInstruction::Visibility(VisibleThing::Sprite(self.sprite));
+                self.set_property(visibility, self.bool(op == LooksOpCode::Show))
+            }
+            // layers are clamped, so the front is the highest layer and the back is the one just above the stage
+            LooksOpCode::GoToFrontBack => match block.field("FRONT_BACK") {
+                Some("front") => self.set_property(PropertyInstruction::Layer(), self.number(f64::INFINITY)),
+                Some("back") => self.set_property(PropertyInstruction::Layer(), self.number(1.)),
+                _ => self.unsupported_input(block, "FRONT_BACK"),
+            },
+            LooksOpCode::GoForwardBackwardLayers => {
+                let layers = self.input(block, "NUM");
+                let layer = ReadWriteInstruction::Property(PropertyInstruction::Layer());
+                match block.field("FORWARD_BACKWARD") {
+                    Some("forward") => Statement(Instruction::Set(ast.change_by(layer, layers))),
+                    Some("backward") => Statement(Instruction::Set(ast.change_using(BinaryOp::subtract(), layer, layers))),
+                    _ => self.unsupported_input(block, "FORWARD_BACKWARD"),
+                }
+            }
+            LooksOpCode::CostumeNumberName | LooksOpCode::BackdropNumberName => {
+                match block.field("NUMBER_NAME").and_then(named_property) {
+                    Some(property) if op == LooksOpCode::CostumeNumberName => Reporter(self.property(costume(property))),
+                    Some(property) => Reporter(self.property(backdrop(property))),
+                    None => self.unsupported_input(block, "NUMBER_NAME"),
+                }
+            }
+            LooksOpCode::Size => Reporter(self.property(PropertyInstruction::Size())),
+            _ => Unsupported,
+        }
+    }
+
+    fn sound(&mut self, block: &'a LinkedBlock<'a>, op: SoundOpCode) -> Lowering<'a> {
+        match op {
+            SoundOpCode::PlayUntilDone | SoundOpCode::Play => {
+                let name = match self.menu(block, "SOUND_MENU") {
+                    Some(name) => name,
+                    None => return self.unsupported_input(block, "SOUND_MENU"),
+                };
+                let sound: &'a Sound<'a> = match self.sprite.sounds().iter().find(|sound| sound.name() == name) {
+                    Some(sound) => sound,
+                    None => {
+                        self.unknown(block, name);
+                        return Invalid;
+                    }
+                };
+                let kind = if op == SoundOpCode::Play { SoundInstruction::Start } else { SoundInstruction::Play };
+                self.render(RenderInstruction::SoundInstruction(kind, sound))
+            }
+            SoundOpCode::StopAllSounds => self.render(RenderInstruction::StopAllSounds()),
+            SoundOpCode::ChangeEffectBy | SoundOpCode::SetEffectTo => {
+                let effect = match block.field("EFFECT").and_then(audio_effect) {
+                    Some(effect) => PropertyInstruction::AudioEffect(effect),
+                    None => return self.unsupported_input(block, "EFFECT"),
+                };
+                let value = self.input(block, "VALUE");
+                if op == SoundOpCode::ChangeEffectBy {
+                    self.change_property(effect, value)
+                } else {
+                    self.set_property(effect, value)
+                }
+            }
+            SoundOpCode::ClearEffects => self.render(RenderInstruction::ClearAudioEffects()),
+            SoundOpCode::ChangeVolumeBy => {
+                let volume = self.input(block, "VOLUME");
+                self.change_property(PropertyInstruction::Volume(), volume)
+            }
+            SoundOpCode::SetVolumeTo => {
+                let volume = self.input(block, "VOLUME");
+                self.set_property(PropertyInstruction::Volume(), volume)
+            }
+            SoundOpCode::Volume => Reporter(self.property(PropertyInstruction::Volume())),
+            _ => Unsupported,
+        }
+    }
+
+    fn pen(&mut self, block: &'a LinkedBlock<'a>, op: PenOpCode) -> Lowering<'a> {
+        let (param, input) = match op {
+            PenOpCode::Clear => return self.render(RenderInstruction::ClearPen()),
+            PenOpCode::Stamp => return self.render(RenderInstruction::Stamp()),
+            PenOpCode::PenDown | PenOpCode::PenUp => {
+                return self.render(RenderInstruction::PenDown(op == PenOpCode::PenDown));
+            }
+            PenOpCode::SetPenColorToColor => {
+                let color = self.input(block, "COLOR");
+                return self.render(RenderInstruction::SetPenColor(color));
+            }
+            PenOpCode::ChangePenColorParamBy | PenOpCode::SetPenColorParamTo => {
+                match self.menu(block, "COLOR_PARAM").and_then(pen_color_param) {
+                    Some(param) => (param, "VALUE"),
+                    None => return self.unsupported_input(block, "COLOR_PARAM"),
+                }
+            }
+            PenOpCode::ChangePenSizeBy | PenOpCode::SetPenSizeTo => (PenParam::Size, "SIZE"),
+            PenOpCode::SetPenShadeToNumber | PenOpCode::ChangePenShadeBy => (PenParam::Shade, "SHADE"),
+            PenOpCode::SetPenHueToNumber | PenOpCode::ChangePenHueBy => (PenParam::Hue, "HUE"),
+            PenOpCode::ColorParamMenu => return Unsupported,
+        };
+        let value = self.input(block, input);
+        let change = matches!(
+            op,
+            PenOpCode::ChangePenColorParamBy
+                | PenOpCode::ChangePenSizeBy
+                | PenOpCode::ChangePenShadeBy
+                | PenOpCode::ChangePenHueBy
+        );
+        if change {
+            self.render(RenderInstruction::ChangePen(param, value))
+        } else {
+            self.render(RenderInstruction::SetPen(param, value))
+        }
+    }
+
+    // like Scratch, broadcasts are looked up by ID, then case-insensitively by name,
+    // and ones that aren't in the stage's broadcasts are added to them
+    fn broadcast_named(&mut self, id: Option<&str>, name: &str) -> &'a BroadCast<'a> {
+        let found = id
+            .and_then(|id| self.broadcasts.iter().find(|broadcast| broadcast.id() == id))
+            .or_else(|| self.broadcasts.iter().find(|broadcast| broadcast.name().eq_ignore_ascii_case(name)));
+        if let Some(broadcast) = found {
+            return broadcast;
+        }
+        let broadcast: &'a BroadCast<'a> = self.ast.alloc(BroadCast {
+            name: self.ast.string(name),
+            id: self.ast.string(id.unwrap_or(name)),
+        });
+        self.broadcasts.push(broadcast);
+        broadcast
+    }
+
+    fn broadcast_field(&mut self, field: &sb3::Field) -> &'a BroadCast<'a> {
+        self.broadcast_named(field.id.as_deref(), &field.value)
+    }
+
+    fn broadcast(&mut self, block: &'a LinkedBlock<'a>) -> Option<&'a BroadCast<'a>> {
+        match block.input_value("BROADCAST_INPUT")? {
+            InputLink::Primitive(Primitive::Variable(variable)) if variable.r#type == VariableType::Broadcast => {
+                Some(self.broadcast_named(Some(&variable.id), &variable.name))
+            }
+            InputLink::Block(menu) => Some(self.broadcast_field(Self::menu_block(menu)?)),
+            _ => None,
+        }
+    }
+
+    fn event(&mut self, block: &'a LinkedBlock<'a>, op: EventOpCode) -> Lowering<'a> {
+        match op {
+            EventOpCode::Broadcast | EventOpCode::BroadcastAndWait => match self.broadcast(block) {
+                Some(broadcast) => {
+                    let kind = if op == EventOpCode::Broadcast {
+                        BroadCastInstruction::Resume
+                    } else {
+                        BroadCastInstruction::Wait
+                    };
+                    self.control(ControlFlowInstruction::BroadCast(kind, broadcast))
+                }
+                None => self.unsupported_input(block, "BROADCAST_INPUT"),
+            },
+            _ => Unsupported,
+        }
+    }
+
+    fn control_op(&mut self, block: &'a LinkedBlock<'a>, op: ControlOpCode) -> Lowering<'a> {
+        let ast = self.ast;
+        match op {
+            ControlOpCode::Wait => {
+                let duration = self.input(block, "DURATION");
+                self.control(ControlFlowInstruction::Wait(duration))
+            }
+            ControlOpCode::Repeat => {
+                let times = self.input(block, "TIMES");
+                let body = self.substack(block, "SUBSTACK");
+                self.control(ControlFlowInstruction::Repeat(times, body))
+            }
+            ControlOpCode::Forever => {
+                let body = self.substack(block, "SUBSTACK");
+                self.control(ControlFlowInstruction::While(self.bool(true), body))
+            }
+            ControlOpCode::If | ControlOpCode::IfElse => {
+                let condition = self.input(block, "CONDITION");
+                let r#if = self.substack(block, "SUBSTACK");
+                let r#else = if op == ControlOpCode::IfElse {
+                    self.substack(block, "SUBSTACK2")
+                } else {
+                    ast.empty_block()
+                };
+                self.control(ControlFlowInstruction::Branch { condition, r#if, r#else })
+            }
+            ControlOpCode::WaitUntil => {
+                let condition = self.input(block, "CONDITION");
+                self.control(ControlFlowInstruction::WaitUntil(condition))
+            }
+            ControlOpCode::RepeatUntil => {
+                let condition = self.input(block, "CONDITION");
+                let body = self.substack(block, "SUBSTACK");
+                self.control(ControlFlowInstruction::While(ast.op1(UnaryOp::Not(), condition), body))
+            }
+            ControlOpCode::While => {
+                let condition = self.input(block, "CONDITION");
+                let body = self.substack(block, "SUBSTACK");
+                self.control(ControlFlowInstruction::While(condition, body))
+            }
+            ControlOpCode::Stop => match block.field("STOP_OPTION") {
+                Some("all") => self.control(ControlFlowInstruction::Stop(StopInstruction::All())),
+                Some("this script") => self.control(ControlFlowInstruction::Stop(StopInstruction::OwnScript())),
+                Some("other scripts in sprite") | Some("other scripts in stage") => {
+                    let stop = StopInstruction::OtherScriptsInSprite(self.sprite);
+                    self.control(ControlFlowInstruction::Stop(stop))
+                }
+                _ => self.unsupported_input(block, "STOP_OPTION"),
+            },
+            ControlOpCode::CreateCloneOf => {
+                let sprite = match self.menu(block, "CLONE_OPTION") {
+                    Some("_myself_") => self.sprite,
+                    Some(name) => match self.sprites.get(name) {
+                        Some(sprite) => sprite,
+                        None => {
+                            self.unknown(block, name);
+                            return Invalid;
+                        }
+                    },
+                    None => return self.unsupported_input(block, "CLONE_OPTION"),
+                };
+                self.control(ControlFlowInstruction::CreateClone(sprite))
+            }
+            ControlOpCode::DeleteThisClone => self.control(ControlFlowInstruction::DeleteSelf()),
+            _ => Unsupported,
+        }
+    }
+
+    fn sensing(&mut self, block: &'a LinkedBlock<'a>, op: SensingOpCode) -> Lowering<'a> {
+        let ast = self.ast;
+        match op {
+            SensingOpCode::TouchingObject => {
+                let object = match self.menu(block, "TOUCHINGOBJECTMENU") {
+                    Some("_mouse_") => TouchingObject::MousePointer,
+                    Some("_edge_") => TouchingObject::Edge,
+                    Some(name) => match self.sprites.get(name) {
+                        Some(sprite) => TouchingObject::Sprite(sprite),
+                        None => {
+                            self.unknown(block, name);
+                            return Invalid;
+                        }
+                    },
+                    None => return self.unsupported_input(block, "TOUCHINGOBJECTMENU"),
+                };
+                Reporter(self.property(PropertyInstruction::Touching(object)))
+            }
+            SensingOpCode::TouchingColor => {
+                let color = self.input(block, "COLOR");
+                Reporter(self.property(PropertyInstruction::TouchingColor(color)))
+            }
+            SensingOpCode::ColorIsTouchingColor => {
+                let color = self.input(block, "COLOR");
+                let touching = self.input(block, "COLOR2");
+                Reporter(self.property(PropertyInstruction::ColorIsTouchingColor(color, touching)))
+            }
+            SensingOpCode::DistanceTo => match self.menu(block, "DISTANCETOMENU") {
+                Some("_mouse_") => Reporter(ast.op2(
+                    BinaryOp::Math(MathOp::Distance),
+                    self.property(PropertyInstruction::Position()),
+                    self.property(PropertyInstruction::MousePointer()),
+                )),
+                _ => self.unsupported_input(block, "DISTANCETOMENU"),
+            },
+            SensingOpCode::AskAndWait => {
+                let question = self.input(block, "QUESTION");
+                self.control(ControlFlowInstruction::AskAndWait(question))
+            }
+            SensingOpCode::Answer => Reporter(self.property(PropertyInstruction::Answer())),
+            SensingOpCode::KeyPressed => match self.menu(block, "KEY_OPTION").and_then(key) {
+                Some(key) => Reporter(self.property(PropertyInstruction::KeyPressed(key))),
+                None => self.unsupported_input(block, "KEY_OPTION"),
+            },
+            SensingOpCode::MouseDown => Reporter(self.property(PropertyInstruction::MouseDown())),
+            SensingOpCode::MouseX | SensingOpCode::MouseY => {
+                let index = if op == SensingOpCode::MouseX { 0 } else { 1 };
+                Reporter(ast.op1(UnaryOp::VectorIndex(index), self.property(PropertyInstruction::MousePointer())))
+            }
+            SensingOpCode::SetDragMode => match block.field("DRAG_MODE") {
+                Some("draggable") => self.set_property(PropertyInstruction::Draggable(), self.bool(true)),
+                Some("not draggable") => self.set_property(PropertyInstruction::Draggable(), self.bool(false)),
+                _ => self.unsupported_input(block, "DRAG_MODE"),
+            },
+            SensingOpCode::Loudness => Reporter(self.property(PropertyInstruction::Loudness())),
+            SensingOpCode::Timer => Reporter(self.property(PropertyInstruction::Timer())),
+            SensingOpCode::ResetTimer => self.set_property(PropertyInstruction::Timer(), self.number(0.)),
+            // only the stage's properties can be read so far
+            SensingOpCode::Of => {
+                if self.menu(block, "OBJECT") != Some("_stage_") {
+                    return self.unsupported_input(block, "OBJECT");
+                }
+                let property = match block.field("PROPERTY") {
+                    Some("backdrop #") => StageProperty::Backdrop(NamedProperty::Number),
+                    Some("backdrop name") => StageProperty::Backdrop(NamedProperty::Name),
+                    Some("volume") => StageProperty::Volume(),
+                    Some(name) => match Lookup::find(&self.globals.variables, &self.globals.variables, None, name) {
+                        Some(variable) => StageProperty::Variable(variable),
+                        None => {
+                            self.unknown(block, name);
+                            return Invalid;
+                        }
+                    },
+                    None => return self.unsupported_input(block, "PROPERTY"),
+                };
+                Reporter(self.property(PropertyInstruction::Stage(property)))
+            }
+            SensingOpCode::Current => match block.field("CURRENTMENU").and_then(time_unit) {
+                Some(unit) => Reporter(self.property(PropertyInstruction::CurrentTime(unit))),
+                None => self.unsupported_input(block, "CURRENTMENU"),
+            },
+            SensingOpCode::DaysSince2000 => {
+                Reporter(self.property(PropertyInstruction::CurrentTime(TimeUnit::DaysSince2000)))
+            }
+            SensingOpCode::UserName => Reporter(self.property(PropertyInstruction::UserName())),
+            _ => Unsupported,
+        }
+    }
+
+    fn binary(&mut self, block: &'a LinkedBlock<'a>, op: BinaryOp, left: &str, right: &str) -> Lowering<'a> {
+        let left = self.input(block, left);
+        let right = self.input(block, right);
+        Reporter(self.ast.op2(op, left, right))
+    }
+
+    fn operator(&mut self, block: &'a LinkedBlock<'a>, op: OperatorOpCode) -> Lowering<'a> {
+        let ast = self.ast;
+        match op {
+            OperatorOpCode::Add => self.binary(block, BinaryOp::add(), "NUM1", "NUM2"),
+            OperatorOpCode::Subtract => self.binary(block, BinaryOp::subtract(), "NUM1", "NUM2"),
+            OperatorOpCode::Multiply => self.binary(block, BinaryOp::multiply(), "NUM1", "NUM2"),
+            OperatorOpCode::Divide => self.binary(block, BinaryOp::divide(), "NUM1", "NUM2"),
+            OperatorOpCode::Mod => self.binary(block, BinaryOp::modulo(), "NUM1", "NUM2"),
+            OperatorOpCode::Random => self.binary(block, BinaryOp::Math(MathOp::Random), "FROM", "TO"),
+            OperatorOpCode::GreaterThan => {
+                self.binary(block, BinaryOp::Comparison(ComparisonOp::GreaterThan), "OPERAND1", "OPERAND2")
+            }
+            OperatorOpCode::LessThan => {
+                self.binary(block, BinaryOp::Comparison(ComparisonOp::LessThan), "OPERAND1", "OPERAND2")
+            }
+            OperatorOpCode::Equals => {
+                self.binary(block, BinaryOp::Comparison(ComparisonOp::Equals), "OPERAND1", "OPERAND2")
+            }
+            OperatorOpCode::And => self.binary(block, BinaryOp::Logic(LogicOp::And), "OPERAND1", "OPERAND2"),
+            OperatorOpCode::Or => self.binary(block, BinaryOp::Logic(LogicOp::Or), "OPERAND1", "OPERAND2"),
+            OperatorOpCode::Not => {
+                let operand = self.input(block, "OPERAND");
+                Reporter(ast.op1(UnaryOp::Not(), operand))
+            }
+            OperatorOpCode::Join => self.binary(block, BinaryOp::String(StringOp::Join), "STRING1", "STRING2"),
+            OperatorOpCode::LetterOf => self.binary(block, BinaryOp::String(StringOp::LetterOf), "LETTER", "STRING"),
+            OperatorOpCode::Contains => self.binary(block, BinaryOp::String(StringOp::Contains), "STRING1", "STRING2"),
+            OperatorOpCode::Length => {
+                let string = self.input(block, "STRING");
+                Reporter(ast.op1(UnaryOp::Length(), string))
+            }
+            OperatorOpCode::Round => {
+                let num = self.input(block, "NUM");
+                Reporter(ast.op1(UnaryOp::FloatToInt(FloatToIntOp::Round), num))
+            }
+            OperatorOpCode::MathOp => {
+                let num = self.input(block, "NUM");
+                let op = match block.field("OPERATOR").unwrap_or("") {
+                    "abs" => UnaryOp::Abs(),
+                    "floor" => UnaryOp::FloatToInt(FloatToIntOp::Floor),
+                    "ceiling" => UnaryOp::FloatToInt(FloatToIntOp::Ceiling),
+                    "sqrt" => UnaryOp::FloatToFloat(FloatToFloatOp::Sqrt),
+                    "sin" => UnaryOp::FloatToFloat(FloatToFloatOp::Sin),
+                    "cos" => UnaryOp::FloatToFloat(FloatToFloatOp::Cos),
+                    "tan" => UnaryOp::FloatToFloat(FloatToFloatOp::Tan),
+                    "asin" => UnaryOp::FloatToFloat(FloatToFloatOp::ASin),
+                    "acos" => UnaryOp::FloatToFloat(FloatToFloatOp::ACos),
+                    "atan" => UnaryOp::FloatToFloat(FloatToFloatOp::ATan),
+                    "ln" => UnaryOp::FloatToFloat(FloatToFloatOp::Ln),
+                    "log" => UnaryOp::FloatToFloat(FloatToFloatOp::Log),
+                    "e ^" => UnaryOp::FloatToFloat(FloatToFloatOp::Exp),
+                    "10 ^" => return Reporter(ast.op2(BinaryOp::Math(MathOp::Pow), self.number(10.), num)),
+                    _ => return self.unsupported_input(block, "OPERATOR"),
+                };
+                Reporter(ast.op1(op, num))
+            }
+        }
+    }
+
+    fn list_instruction(&self, list: &'a List<'a>, instruction: VoidListInstruction<'a>) -> Lowering<'a> {
+        Statement(Instruction::List(ListInstruction::Void(instruction), self.ast.list(list, true)))
+    }
+
+    fn list_element(&self, list: &'a List<'a>, instruction: ReturningListInstruction<'a>) -> Lowering<'a> {
+        Reporter(self.ast.id(GetInstruction::Element(instruction, self.ast.list(list, false))))
+    }
+
+    fn data(&mut self, block: &'a LinkedBlock<'a>, op: DataOpCode) -> Lowering<'a> {
+        let ast = self.ast;
+        match op {
+            DataOpCode::Variable
+            | DataOpCode::SetVariableTo
+            | DataOpCode::ChangeVariableBy
+            | DataOpCode::ShowVariable
+            | DataOpCode::HideVariable => {
+                let variable = match self.field_variable(block) {
+                    Some(variable) => variable,
+                    None => return Invalid,
+                };
+                let readable = ReadWriteInstruction::Variable(variable);
+                match op {
+                    DataOpCode::Variable => Reporter(ast.get(readable)),
+                    DataOpCode::SetVariableTo => {
+                        let value = self.input(block, "VALUE");
+                        Statement(Instruction::Set(ast.set(readable, value)))
+                    }
+                    DataOpCode::ChangeVariableBy => {
+                        let value = self.input(block, "VALUE");
+                        Statement(Instruction::Set(ast.change_by(readable, value)))
+                    }
+                    _ => {
+                        let visibility = PropertyInstruction::Visibility(VisibleThing::Variable(variable));
+                        self.set_property(visibility, self.bool(op == DataOpCode::ShowVariable))
+                    }
+                }
+            }
+            _ => {
+                let list = match self.field_list(block) {
+                    Some(list) => list,
+                    None => return Invalid,
+                };
+                match op {
+                    DataOpCode::ListContents => Reporter(ast.get(ReadWriteInstruction::List(list))),
+                    DataOpCode::AddToList => {
+                        let item = self.input(block, "ITEM");
+                        let push = VoidListInstruction::Push(item, PushPop::new(PushPopSide::Right, 0));
+                        self.list_instruction(list, push)
+                    }
+                    DataOpCode::DeleteOfList => {
+                        let index = self.input(block, "INDEX");
+                        self.list_instruction(list, VoidListInstruction::Remove(index))
+                    }
+                    DataOpCode::DeleteAllOfList => self.list_instruction(list, VoidListInstruction::Clear()),
+                    DataOpCode::InsertAtList => {
+                        let index = self.input(block, "INDEX");
+                        let item = self.input(block, "ITEM");
+                        self.list_instruction(list, VoidListInstruction::Insert(index, item))
+                    }
+                    DataOpCode::ReplaceItemOfList => {
+                        let index = self.input(block, "INDEX");
+                        let item = self.input(block, "ITEM");
+                        self.list_instruction(list, VoidListInstruction::Set(index, item))
+                    }
+                    DataOpCode::ItemOfList => {
+                        let index = self.input(block, "INDEX");
+                        self.list_element(list, ReturningListInstruction::Get(index))
+                    }
+                    DataOpCode::ItemNumOfList => {
+                        let item = self.input(block, "ITEM");
+                        self.list_element(list, ReturningListInstruction::Find(item))
+                    }
+                    DataOpCode::LengthOfList => self.list_element(list, ReturningListInstruction::Length()),
+                    DataOpCode::ListContainsItem => {
+                        let item = self.input(block, "ITEM");
+                        self.list_element(list, ReturningListInstruction::Contains(item))
+                    }
+                    _ => {
+                        let visibility = PropertyInstruction::Visibility(VisibleThing::List(list));
+                        self.set_property(visibility, self.bool(op == DataOpCode::ShowList))
+                    }
+                }
+            }
+        }
+    }
+
+    fn procedure(&mut self, block: &'a LinkedBlock<'a>, op: ProcedureOpCode) -> Lowering<'a> {
+        match op {
+            // the arguments are looked up by the definition's argument IDs, like Scratch does
+            ProcedureOpCode::Call => {
+                let proc_code = block.block.mutation.as_ref().and_then(|mutation| mutation.proc_code.as_ref());
+                let (function, ids) = match proc_code.and_then(|proc_code| self.functions.get(proc_code.as_str())) {
+                    Some(&function) => function,
+                    None => {
+                        self.unknown(block, proc_code.map_or("", String::as_str));
+                        return Invalid;
+                    }
+                };
+                let mut args = self.ast.vec();
+                for (param, id) in function.params().iter().zip(ids) {
+                    let arg = match block.input_value(id) {
+                        Some(_) => self.input(block, id),
+                        None => self.ast.param_default(param.r#type()),
+                    };
+                    args.push(arg);
+                }
+                let call = FunctionCallInstruction::new(function, args.into_bump_slice());
+                Statement(Instruction::Op(CallInstruction::Function(call)))
+            }
+            // outside of their custom block's definition, Scratch reports the argument's default
+            ProcedureOpCode::ArgumentReporterStringNumber | ProcedureOpCode::ArgumentReporterBoolean => {
+                let name = block.field("VALUE").unwrap_or("");
+                let param = self
+                    .function
+                    .and_then(|function| function.params().iter().find(|param| param.variable().name() == name));
+                match param {
+                    Some(param) => Reporter(self.ast.get(ReadWriteInstruction::Variable(param.variable()))),
+                    None if op == ProcedureOpCode::ArgumentReporterBoolean => Reporter(self.bool(false)),
+                    None => Reporter(self.ast.constant(Constant::Number(Number::Int(0)))),
+                }
+            }
+            _ => Unsupported,
+        }
+    }
+
+    fn lower_block(&mut self, block: &'a LinkedBlock<'a>) -> Lowering<'a> {
+        match &block.block.op_code.op_code {
+            CategoryOpCode::Motion(op) => self.motion(block, *op),
+            CategoryOpCode::Look(op) => self.looks(block, *op),
+            CategoryOpCode::Sound(op) => self.sound(block, *op),
+            CategoryOpCode::Event(op) => self.event(block, *op),
+            CategoryOpCode::Control(op) => self.control_op(block, *op),
+            CategoryOpCode::Sensing(op) => self.sensing(block, *op),
+            CategoryOpCode::Operator(op) => self.operator(block, *op),
+            CategoryOpCode::Variable(op) => self.data(block, *op),
+            CategoryOpCode::Block(op) => self.procedure(block, *op),
+            CategoryOpCode::Pen(op) => self.pen(block, *op),
+            CategoryOpCode::Unknown(_) => Unsupported,
+        }
+    }
+
+    fn unsupported(&mut self, block: &LinkedBlock) {
+        self.error(block, |target, id| Error::UnsupportedOpCode {
+            target,
+            block: id,
+            op_code: block.block.op_code.as_str().to_owned(),
+        });
+    }
+
+    fn instruction(&mut self, block: &'a LinkedBlock<'a>) -> Option<Instruction<'a>> {
+        match self.lower_block(block) {
+            Statement(instruction) => Some(instruction),
+            Reporter(_) | Unsupported => {
+                self.unsupported(block);
+                None
+            }
+            Invalid => None,
+        }
+    }
+
+    fn reporter(&mut self, block: &'a LinkedBlock<'a>) -> Value<'a> {
+        match self.lower_block(block) {
+            Reporter(value) => value,
+            Statement(_) | Unsupported => {
+                self.unsupported(block);
+                self.empty()
+            }
+            Invalid => self.empty(),
+        }
+    }
+
+    fn block(&mut self, stack: &'a [&'a LinkedBlock<'a>]) -> Block<'a> {
+        let mut instructions = self.ast.vec();
+        for block in stack {
+            if let Some(instruction) = self.instruction(block) {
+                instructions.push(instruction);
+            }
+        }
+        Block { instructions }
+    }
+
+    fn trigger(&mut self, hat: &'a LinkedBlock<'a>, op: &CategoryOpCode) -> Option<Event<'a>> {
+        let event = match op {
+            CategoryOpCode::Event(EventOpCode::WhenFlagClicked) => Event::FlagClicked,
+            CategoryOpCode::Event(EventOpCode::WhenKeyPressed) => match hat.field("KEY_OPTION").and_then(key) {
+                Some(key) => Event::KeyPressed(key),
+                None => {
+                    self.unsupported_input(hat, "KEY_OPTION");
+                    return None;
+                }
+            },
+            CategoryOpCode::Event(EventOpCode::WhenThisSpriteClicked)
+            | CategoryOpCode::Event(EventOpCode::WhenStageClicked) => Event::Clicked,
+            CategoryOpCode::Event(EventOpCode::WhenBackdropSwitchesTo) => {
+                Event::BackdropSwitchesTo(hat.field("BACKDROP").unwrap_or(""))
+            }
+            CategoryOpCode::Event(EventOpCode::WhenGreaterThan) => {
+                match hat.field("WHENGREATERTHANMENU").and_then(greater_than_property) {
+                    Some(property) => Event::GreaterThan(property, self.input(hat, "VALUE")),
+                    None => {
+                        self.unsupported_input(hat, "WHENGREATERTHANMENU");
+                        return None;
+                    }
+                }
+            }
+            CategoryOpCode::Event(EventOpCode::WhenBroadcastReceived) => {
+                let field = match hat.block.fields.get("BROADCAST_OPTION") {
+                    Some(field) => field,
+                    None => {
+                        self.unsupported_input(hat, "BROADCAST_OPTION");
+                        return None;
+                    }
+                };
+                Event::BroadcastReceived(self.broadcast_field(field))
+            }
+            CategoryOpCode::Control(ControlOpCode::StartAsClone) => Event::StartAsClone,
+            _ => {
+                self.unsupported(hat);
+                return None;
+            }
+        };
+        Some(event)
+    }
+
+    // the param types come from the prototype's argument reporters
+    fn declare_function(&mut self, definition: &'a LinkedBlock<'a>) -> Option<(&'a str, &'a Function<'a>, &'a [String])> {
+        let prototype = match definition.input_value("custom_block") {
+            Some(InputLink::Block(prototype)) => prototype,
+            _ => {
+                self.unsupported_input(definition, "custom_block");
+                return None;
+            }
+        };
+        let mutation = prototype.block.mutation.as_ref();
+        let proc_code = match mutation.and_then(|mutation| mutation.proc_code.as_ref()) {
+            Some(proc_code) => proc_code.as_str(),
+            None => {
+                self.unsupported_input(definition, "custom_block");
+                return None;
+            }
+        };
+        let mutation = mutation.unwrap();
+        let ids = mutation.argument_ids.as_ref().map_or(&[][..], Vec::as_slice);
+        let names = mutation.argument_names.as_ref().map_or(&[][..], Vec::as_slice);
+
+        let ast = self.ast;
+        let params = ids.iter().zip(names).map(|(id, name)| {
+            let boolean = CategoryOpCode::Block(ProcedureOpCode::ArgumentReporterBoolean);
+            let r#type = match prototype.input_value(id) {
+                Some(InputLink::Block(reporter)) if reporter.block.op_code.op_code == boolean => ParamType::Boolean,
+                _ => ParamType::StringOrNumber,
+            };
+            Param {
+                variable: ast.variable(name, ast.param_default(r#type)),
+                r#type,
+            }
+        });
+        let function: &'a Function<'a> = ast.alloc(Function {
+            name: ast.string(proc_code),
+            sprite: self.sprite,
+            params: BumpVec::from_iter_in(params, ast.bump),
+            warp: mutation.warp.unwrap_or(false),
+            body: Cell::new(ast.alloc(ast.empty_block())),
+        });
+        Some((proc_code, function, ids))
+    }
+
+    // custom blocks are declared before any script is lowered, since they can be called from anywhere in the target
+    fn functions(&mut self, linked: &LinkedTarget<'a>) {
+        self.functions.clear();
+        let definition = CategoryOpCode::Block(ProcedureOpCode::Definition);
+        let mut definitions = Vec::new();
+        for stack in linked.scripts() {
+            if stack[0].block.op_code.op_code != definition {
+                continue;
+            }
+            // Scratch only ever calls the first definition of a proccode
+            if let Some((proc_code, function, ids)) = self.declare_function(stack[0]) {
+                if !self.functions.contains_key(proc_code) {
+                    self.functions.insert(proc_code, (function, ids));
+                    definitions.push((function, stack));
+                }
+            }
+        }
+        for (function, stack) in definitions {
+            self.function = Some(function);
+            self.ast.set_accessor(Some(Access {
+                sprite: self.sprite,
+                function: Some(function),
+            }));
+            let body = self.block(&stack[1..]);
+            function.body.set(self.ast.alloc(body));
+            self.function = None;
+            self.ast.set_accessor(Some(Access {
+                sprite: self.sprite,
+                function: None,
+            }));
+            self.all_functions.push(function);
+        }
+    }
+
+    // stacks without a hat block never run, so they're skipped
+    fn scripts(&mut self, linked: &LinkedTarget<'a>) -> BumpVec<'a, Script<'a>> {
+        self.functions(linked);
+        let mut scripts = self.ast.vec();
+        for stack in linked.scripts() {
+            let hat = stack[0];
+            if is_hat(&hat.block.op_code) {
+                if let Some(trigger) = self.trigger(hat, &hat.block.op_code.op_code) {
+                    let block = self.block(&stack[1..]);
+                    scripts.push(Script { trigger, block });
+                }
+            } else if !hat.block.op_code.is_known() {
+                // an extension's hat can't be lowered yet
+                self.unsupported(hat);
+            }
+        }
+        scripts
+    }
+}
+
+pub fn lower<'a>(bump: &'a Bump, project: &'a sb3::Project) -> Lowered<'a> {
+    let ast = AST::new(bump);
+    let stage = &project.targets.stage;
+    let targets = std::iter::once(("Stage", true, &stage.target))
+        .chain(project.targets.sprites.iter().map(|sprite| (sprite.name.as_str(), false, &sprite.target)))
+        .collect::<Vec<_>>();
+    let sprites = targets
+        .iter()
+        .map(|&(name, is_stage, target)| {
+            let sounds = target.sounds.iter().map(|sound| Sound {
+                name: ast.string(&sound.asset.name),
+            });
+            let sprite: &'a Sprite<'a> = ast.alloc(Sprite {
+                name: ast.string(name),
+                is_stage,
+                sounds: BumpVec::from_iter_in(sounds, bump),
+            });
+            sprite
+        })
+        .collect::<Vec<_>>();
+
+    let mut lowerer = Lowerer {
+        ast,
+        errors: Vec::new(),
+        globals: Names::new(ast, &stage.target.variables),
+        sprites: targets
+            .iter()
+            .zip(&sprites)
+            .filter(|((_, is_stage, _), _)| !is_stage)
+            .map(|(&(name, _, _), &sprite)| (name, sprite))
+            .collect(),
+        broadcasts: ast.vec(),
+        all_functions: ast.vec(),
+        target: "Stage",
+        sprite: sprites[0],
+        locals: Names::new(ast, &[]),
+        functions: HashMap::new(),
+        function: None,
+    };
+    for variable in &stage.target.variables {
+        if let sb3::Value::Broadcast(broadcast) = &variable.value {
+            lowerer.broadcast_named(Some(&variable.id), &broadcast.name);
+        }
+    }
+    let mut sprite_scripts = ast.vec();
+    for (&(name, is_stage, target), &sprite) in targets.iter().zip(&sprites) {
+        lowerer.target = name;
+        lowerer.sprite = sprite;
+        ast.set_accessor(Some(Access { sprite, function: None }));
+        // the stage's variables are the globals
+        lowerer.locals = Names::new(ast, if is_stage { &[] } else { &target.variables });
+        let scripts = match link::link(bump, target) {
+            Ok(linked) => lowerer.scripts(&linked),
+            Err(errors) => {
+                lowerer.errors.push(Error::Link {
+                    target: name.to_owned(),
+                    errors,
+                });
+                ast.vec()
+            }
+        };
+        sprite_scripts.push(SpriteScripts {
+            sprite,
+            locals: lowerer.locals.scope,
+            scripts,
+        });
+    }
+
+    Lowered {
+        program: Program {
+            globals: lowerer.globals.scope,
+            broadcasts: lowerer.broadcasts,
+            sprite_scripts,
+            functions: lowerer.all_functions,
+        },
+        errors: lowerer.errors,
+    }
+}
@@ -1,14 +1,19 @@
-use std::ptr::slice_from_raw_parts_mut;
+use std::cell::{Cell, Ref, RefCell};
 
 use bumpalo::Bump;
 use bumpalo::collections::{String, Vec};
 
-use crate::scratch::ast::compute_kind::{Computable, ComputeKind};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
 use crate::scratch::ast::instruction::{GetInstruction, Instruction, ReadWriteInstruction, SetInstruction, Value};
 use crate::scratch::ast::instruction::function_call::{BinaryOp, CallInstruction, UnaryOp};
 
 pub mod instruction;
 pub mod compute_kind;
+pub mod coroutine;
+pub mod compute_kind_report;
+pub mod lower;
+pub mod broadcast_graph;
+pub mod variable_usage;
 
 pub enum Number {
     UInt(u64),
@@ -22,82 +27,289 @@ pub enum Constant<'a> {
     String(&'a str),
 }
 
-impl Computable for Constant<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
+impl<'a> Computable<'a> for Constant<'_> {
+    fn get_compute_kind(&self, _analysis: &mut Analysis<'a>) -> ComputeKind {
         ComputeKind::Computational
     }
 }
 
-pub struct Event {}
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    // letters are lowercase, since Scratch doesn't distinguish them
+    Char(char),
+    Space,
+    Enter,
+    Up,
+    Down,
+    Left,
+    Right,
+    Any,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum GreaterThanProperty {
+    Loudness,
+    Timer,
+}
+
+// what triggers a script: its hat block
+#[derive(Clone, Copy)]
+pub enum Event<'a> {
+    FlagClicked,
+    KeyPressed(Key),
+    // this sprite, or the stage for the stage's scripts
+    Clicked,
+    BackdropSwitchesTo(&'a str),
+    // triggered when the property goes from not greater than the value to greater than it
+    GreaterThan(GreaterThanProperty, Value<'a>),
+    BroadcastReceived(&'a BroadCast<'a>),
+    StartAsClone,
+}
 
-pub struct BroadCast {}
+impl<'a> Event<'a> {
+    // whether a script with this as its trigger should start when the event happens,
+    // where the event's value is ignored for GreaterThan since the scheduler has to evaluate each script's
+    pub fn is_triggered_by(&self, event: &Event<'a>) -> bool {
+        match (self, event) {
+            (Event::FlagClicked, Event::FlagClicked) => true,
+            (Event::KeyPressed(key), Event::KeyPressed(pressed)) => *key == Key::Any || key == pressed,
+            (Event::Clicked, Event::Clicked) => true,
+            (Event::BackdropSwitchesTo(backdrop), Event::BackdropSwitchesTo(switched)) => backdrop == switched,
+            (Event::GreaterThan(property, _), Event::GreaterThan(other, _)) => property == other,
+            (Event::BroadcastReceived(broadcast), Event::BroadcastReceived(sent)) => std::ptr::eq(*broadcast, *sent),
+            (Event::StartAsClone, Event::StartAsClone) => true,
+            _ => false,
+        }
+    }
+}
 
-pub struct Sound {}
+// a message from the stage's broadcasts, all of which are global
+pub struct BroadCast<'a> {
+    name: String<'a>,
+    id: String<'a>,
+}
 
-pub struct Sprite {}
+impl BroadCast<'_> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+pub struct Sound<'a> {
+    name: String<'a>,
+}
+
+impl Sound<'_> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// the stage is a sprite too, since it can have scripts and sounds
+pub struct Sprite<'a> {
+    name: String<'a>,
+    is_stage: bool,
+    sounds: Vec<'a, Sound<'a>>,
+}
+
+impl<'a> Sprite<'a> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    pub fn is_stage(&self) -> bool {
+        self.is_stage
+    }
+    
+    pub fn sounds(&self) -> &[Sound<'a>] {
+        &self.sounds[..]
+    }
+}
 
 pub struct Block<'a> {
     instructions: Vec<'a, Instruction<'a>>,
 }
 
-impl Computable for Block<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
-        (&self.instructions[..]).get_compute_kind()
+impl<'a> Computable<'a> for Block<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
+        (&self.instructions[..]).get_compute_kind(analysis)
+    }
+}
+
+impl<'a> Block<'a> {
+    pub fn instructions(&self) -> &[Instruction<'a>] {
+        &self.instructions[..]
     }
 }
 
 pub struct Script<'a> {
-    trigger: Event,
+    trigger: Event<'a>,
     block: Block<'a>,
 }
 
+impl<'a> Script<'a> {
+    pub fn trigger(&self) -> &Event<'a> {
+        &self.trigger
+    }
+    
+    pub fn block(&self) -> &Block<'a> {
+        &self.block
+    }
+}
+
 pub struct Scope<'a> {
     variables: Vec<'a, Variable<'a>>,
     lists: Vec<'a, List<'a>>,
 }
 
+impl<'a> Scope<'a> {
+    pub fn variables(&self) -> &[Variable<'a>] {
+        &self.variables[..]
+    }
+    
+    pub fn lists(&self) -> &[List<'a>] {
+        &self.lists[..]
+    }
+}
+
 pub struct SpriteScripts<'a> {
-    sprite: &'a Sprite,
-    locals: Scope<'a>,
+    sprite: &'a Sprite<'a>,
+    locals: &'a Scope<'a>,
     scripts: Vec<'a, Script<'a>>,
 }
 
+impl<'a> SpriteScripts<'a> {
+    pub fn sprite(&self) -> &'a Sprite<'a> {
+        self.sprite
+    }
+    
+    pub fn locals(&self) -> &'a Scope<'a> {
+        self.locals
+    }
+    
+    pub fn scripts(&self) -> &[Script<'a>] {
+        &self.scripts[..]
+    }
+    
+    pub fn scripts_triggered_by<'s>(&'s self, event: &'s Event<'a>) -> impl Iterator<Item = &'s Script<'a>> + 's {
+        self.scripts.iter().filter(move |script| script.trigger.is_triggered_by(event))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    StringOrNumber,
+    Boolean,
+}
+
+pub struct Param<'a> {
+    variable: Variable<'a>,
+    r#type: ParamType,
+}
+
+impl<'a> Param<'a> {
+    pub fn variable(&self) -> &Variable<'a> {
+        &self.variable
+    }
+    
+    pub fn r#type(&self) -> ParamType {
+        self.r#type
+    }
+}
+
+// a custom block
 pub struct Function<'a> {
+    // the proccode, like "move %s steps"
     name: String<'a>,
-    params: Vec<'a, Variable<'a>>,
-    body: Block<'a>,
+    sprite: &'a Sprite<'a>,
+    params: Vec<'a, Param<'a>>,
+    // run without screen refresh
+    warp: bool,
+    // set once the body's been lowered, since it can call the function recursively
+    body: Cell<&'a Block<'a>>,
 }
 
 impl<'a> Function<'a> {
-    pub fn params(&self) -> &[Variable<'a>] {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    pub fn sprite(&self) -> &'a Sprite<'a> {
+        self.sprite
+    }
+    
+    pub fn params(&self) -> &[Param<'a>] {
         &self.params[..]
     }
     
-    fn params_mut(&self) -> &mut [Variable<'a>] {
-        let params = self.params();
-        let ptr = params.as_ptr();
-        let mut_ptr = ptr as *mut Variable<'a>;
-        let slice = slice_from_raw_parts_mut(mut_ptr, params.len());
-        unsafe {
-            &mut *slice
-        }
+    pub fn warp(&self) -> bool {
+        self.warp
     }
     
-    fn get_compute_kind(&'a self, args: &'a [Value<'a>]) -> ComputeKind {
-        // copy args into params, then evaluate body's compute kind
-        let params = self.params_mut();
-        params
-            .iter_mut()
-            .zip(args.iter())
-            .for_each(|(param, arg)| param.value = *arg);
-        self.body.get_compute_kind()
+    pub fn body(&self) -> &'a Block<'a> {
+        self.body.get()
     }
 }
 
 pub struct Program<'a> {
-    globals: Scope<'a>,
+    globals: &'a Scope<'a>,
+    broadcasts: Vec<'a, &'a BroadCast<'a>>,
+    // the stage's scripts come first
     sprite_scripts: Vec<'a, SpriteScripts<'a>>,
-    functions: Vec<'a, Function<'a>>,
+    functions: Vec<'a, &'a Function<'a>>,
+}
+
+impl<'a> Program<'a> {
+    pub fn globals(&self) -> &'a Scope<'a> {
+        self.globals
+    }
+    
+    pub fn broadcasts(&self) -> &[&'a BroadCast<'a>] {
+        &self.broadcasts[..]
+    }
+    
+    pub fn sprite_scripts(&self) -> &[SpriteScripts<'a>] {
+        &self.sprite_scripts[..]
+    }
+    
+    pub fn functions(&self) -> &[&'a Function<'a>] {
+        &self.functions[..]
+    }
+    
+    // the index of the sprite's scripts, which is what clones of it run
+    pub fn sprite_scripts_of(&self, sprite: &Sprite<'a>) -> Option<usize> {
+        self.sprite_scripts.iter().position(|sprite_scripts| std::ptr::eq(sprite_scripts.sprite, sprite))
+    }
+    
+    // every script, along with the sprite it belongs to
+    pub fn scripts(&self) -> impl Iterator<Item = (&SpriteScripts<'a>, &Script<'a>)> {
+        self.sprite_scripts
+            .iter()
+            .flat_map(|sprite_scripts| sprite_scripts.scripts().iter().map(move |script| (sprite_scripts, script)))
+    }
+    
+    pub fn scripts_triggered_by<'p>(&'p self, event: &'p Event<'a>) -> impl Iterator<Item = (&'p SpriteScripts<'a>, &'p Script<'a>)> + 'p {
+        self.scripts().filter(move |(_, script)| script.trigger.is_triggered_by(event))
+    }
+    
+    pub fn scripts_receiving<'p>(&'p self, broadcast: &'a BroadCast<'a>) -> impl Iterator<Item = (&'p SpriteScripts<'a>, &'p Script<'a>)> + 'p {
+        self.scripts().filter(move |(_, script)| match script.trigger {
+            Event::BroadcastReceived(received) => std::ptr::eq(received, broadcast),
+            _ => false,
+        })
+    }
+}
+
+// where a variable or list is read or written from
+#[derive(Clone, Copy)]
+pub struct Access<'a> {
+    pub sprite: &'a Sprite<'a>,
+    // the custom block it's in, if any
+    pub function: Option<&'a Function<'a>>,
 }
 
 pub struct Variable<'a> {
@@ -105,51 +317,126 @@ pub struct Variable<'a> {
     value: Value<'a>,
     // TODO should this be Option?
     // set to initial value for global, set to arg for function param
-    reads: Vec<'a, &'a ReadWriteInstruction<'a>>,
-    writes: Vec<'a, &'a ReadWriteInstruction<'a>>,
+    // registered by the AST builder, after the variable is shared
+    reads: RefCell<Vec<'a, Access<'a>>>,
+    writes: RefCell<Vec<'a, Access<'a>>>,
 }
 
-impl Computable for Variable<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
-        self.value.get_compute_kind()
+impl<'a> Variable<'a> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    pub fn reads(&self) -> Ref<'_, [Access<'a>]> {
+        Ref::map(self.reads.borrow(), |reads| &reads[..])
+    }
+    
+    pub fn writes(&self) -> Ref<'_, [Access<'a>]> {
+        Ref::map(self.writes.borrow(), |writes| &writes[..])
+    }
+    
+    fn add_read(&self, access: Access<'a>) {
+        self.reads.borrow_mut().push(access);
+    }
+    
+    fn add_write(&self, access: Access<'a>) {
+        self.writes.borrow_mut().push(access);
+    }
+}
+
+impl<'a> Computable<'a> for Variable<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
+        // a custom block param is whatever its arg is
+        match analysis.param(self) {
+            Some(kind) => kind,
+            None => self.value.get_compute_kind(analysis),
+        }
     }
 }
 
 pub struct List<'a> {
+    // its value is the list's contents as Scratch reports them when used as a string
     variable: Variable<'a>,
+    items: Vec<'a, Constant<'a>>,
     // should be empty as Scratch allows it, except for strings
     element_reads: Vec<'a, &'a GetInstruction<'a>>,
     element_writes: Vec<'a, &'a Value<'a>>,
 }
 
-impl Computable for List<'_> {
-    fn get_compute_kind(&self) -> ComputeKind {
-        self.variable.get_compute_kind()
+impl<'a> List<'a> {
+    pub fn name(&self) -> &str {
+        self.variable.name()
+    }
+    
+    // the reads and writes of the whole list are registered on this
+    pub fn variable(&self) -> &Variable<'a> {
+        &self.variable
+    }
+    
+    pub fn items(&self) -> &[Constant<'a>] {
+        &self.items[..]
+    }
+}
+
+impl<'a> Computable<'a> for List<'a> {
+    fn get_compute_kind(&self, analysis: &mut Analysis<'a>) -> ComputeKind {
+        self.variable.get_compute_kind(analysis)
     }
 }
 
+// allocates AST nodes in the bump arena they borrow from,
+// registering the reads and writes of variables and lists as it goes
+#[derive(Clone, Copy)]
 pub struct AST<'a> {
-    bump: Bump,
-    program: Program<'a>,
+    bump: &'a Bump,
+    // where the instructions being built are, which the reads and writes are attributed to
+    accessor: &'a Cell<Option<Access<'a>>>,
 }
 
 impl<'a> AST<'a> {
-    fn alloc<T>(&self, value: T) -> &T {
+    pub fn new(bump: &'a Bump) -> AST<'a> {
+        AST {
+            bump,
+            accessor: bump.alloc(Cell::new(None)),
+        }
+    }
+    
+    pub fn set_accessor(&self, accessor: Option<Access<'a>>) {
+        self.accessor.set(accessor);
+    }
+    
+    fn alloc<T>(&self, value: T) -> &'a T {
         self.bump.alloc(value)
     }
     
-    fn id(&'a self, get: GetInstruction<'a>) -> Value<'a> {
+    fn str(&self, s: &str) -> &'a str {
+        String::from_str_in(s, self.bump).into_bump_str()
+    }
+    
+    fn string(&self, s: &str) -> String<'a> {
+        String::from_str_in(s, self.bump)
+    }
+    
+    fn vec<T>(&self) -> Vec<'a, T> {
+        Vec::new_in(self.bump)
+    }
+    
+    fn id(&self, get: GetInstruction<'a>) -> Value<'a> {
         CallInstruction::Id(self.alloc(get))
     }
     
-    fn op1(&'a self, op: UnaryOp, value: Value<'a>) -> Value<'a> {
+    fn constant(&self, constant: Constant<'a>) -> Value<'a> {
+        self.id(GetInstruction::Constant(constant))
+    }
+    
+    fn op1(&self, op: UnaryOp, value: Value<'a>) -> Value<'a> {
         CallInstruction::UnaryOp {
             op,
             value: self.alloc(value),
         }
     }
     
-    fn op2(&'a self, op: BinaryOp, left: Value<'a>, right: Value<'a>) -> Value<'a> {
+    fn op2(&self, op: BinaryOp, left: Value<'a>, right: Value<'a>) -> Value<'a> {
         CallInstruction::BinaryOp {
             op,
             left: self.alloc(left),
@@ -159,15 +446,33 @@ impl<'a> AST<'a> {
     
     //    fn call(&'a self, func: &'a Function<'a>)
     
-    fn get(&'a self, readable: ReadWriteInstruction<'a>) -> Value<'a> {
+    fn get(&self, readable: ReadWriteInstruction<'a>) -> Value<'a> {
+        if let Some(access) = self.accessor.get() {
+            readable.add_read(access);
+        }
         self.id(GetInstruction::ReadWrite(readable))
     }
     
-    fn set(&'a self, writable: ReadWriteInstruction<'a>, value: Value<'a>) -> SetInstruction<'a> {
+    fn set(&self, writable: ReadWriteInstruction<'a>, value: Value<'a>) -> SetInstruction<'a> {
+        if let Some(access) = self.accessor.get() {
+            writable.add_write(access);
+        }
         SetInstruction::new(writable, value)
     }
     
-    fn change_using(&'a self, op: BinaryOp, property: ReadWriteInstruction<'a>, change: Value<'a>) -> SetInstruction<'a> {
+    // the list as the operand of a list instruction, which writes to it if it's void
+    fn list(&self, list: &'a List<'a>, write: bool) -> &'a Value<'a> {
+        if let Some(access) = self.accessor.get() {
+            if write {
+                list.variable.add_write(access);
+            } else {
+                list.variable.add_read(access);
+            }
+        }
+        self.alloc(self.id(GetInstruction::ReadWrite(ReadWriteInstruction::List(list))))
+    }
+    
+    fn change_using(&self, op: BinaryOp, property: ReadWriteInstruction<'a>, change: Value<'a>) -> SetInstruction<'a> {
         self.set(
             property,
             self.op2(op,
@@ -177,7 +482,7 @@ impl<'a> AST<'a> {
         )
     }
     
-    fn change_by(&'a self, property: ReadWriteInstruction<'a>, delta: Value<'a>) -> SetInstruction<'a> {
+    fn change_by(&self, property: ReadWriteInstruction<'a>, delta: Value<'a>) -> SetInstruction<'a> {
         self.change_using(BinaryOp::add(), property, delta)
     }
 }
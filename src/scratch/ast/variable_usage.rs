@@ -0,0 +1,77 @@
+// linting queries over the reads and writes registered on variables and lists
+
+use crate::scratch::ast::{Program, Scope, Sprite, Variable};
+
+#[derive(Clone, Copy)]
+pub struct ScopedVariable<'a> {
+    // none for globals
+    pub owner: Option<&'a Sprite<'a>>,
+    pub variable: &'a Variable<'a>,
+    pub is_list: bool,
+}
+
+impl ScopedVariable<'_> {
+    pub fn is_global(&self) -> bool {
+        self.owner.is_none()
+    }
+}
+
+fn scoped<'a>(owner: Option<&'a Sprite<'a>>, scope: &'a Scope<'a>) -> impl Iterator<Item = ScopedVariable<'a>> {
+    let variables = scope.variables().iter().map(move |variable| ScopedVariable {
+        owner,
+        variable,
+        is_list: false,
+    });
+    let lists = scope.lists().iter().map(move |list| ScopedVariable {
+        owner,
+        variable: list.variable(),
+        is_list: true,
+    });
+    variables.chain(lists)
+}
+
+impl<'a> Program<'a> {
+    // custom block params aren't included, since they're only ever read
+    pub fn variables(&self) -> Vec<ScopedVariable<'a>> {
+        let globals = scoped(None, self.globals());
+        let locals = self
+            .sprite_scripts()
+            .iter()
+            .flat_map(|sprite_scripts| scoped(Some(sprite_scripts.sprite()), sprite_scripts.locals()));
+        globals.chain(locals).collect()
+    }
+
+    pub fn unused_variables(&self) -> Vec<ScopedVariable<'a>> {
+        self.variables()
+            .into_iter()
+            .filter(|scoped| scoped.variable.reads().is_empty() && scoped.variable.writes().is_empty())
+            .collect()
+    }
+
+    pub fn write_only_variables(&self) -> Vec<ScopedVariable<'a>> {
+        self.variables()
+            .into_iter()
+            .filter(|scoped| scoped.variable.reads().is_empty() && !scoped.variable.writes().is_empty())
+            .collect()
+    }
+
+    // only globals can be, and all clones of a sprite count as that sprite
+    pub fn variables_written_by_multiple_sprites(&self) -> Vec<(ScopedVariable<'a>, Vec<&'a Sprite<'a>>)> {
+        self.variables()
+            .into_iter()
+            .filter_map(|scoped| {
+                let mut writers: Vec<&'a Sprite<'a>> = Vec::new();
+                for write in scoped.variable.writes().iter() {
+                    if !writers.iter().any(|writer| std::ptr::eq(*writer, write.sprite)) {
+                        writers.push(write.sprite);
+                    }
+                }
+                if writers.len() > 1 {
+                    Some((scoped, writers))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,200 @@
+// compiles a project ahead of time into an executable, which is its compiled code and its project.json
+// linked with the runtime library, whose main runs the project headless like `run --jit` does
+//
+// the runtime finds what it needs through these constant globals:
+// scratch_project and scratch_project_len, the project.json,
+// scratch_project_version, its Scratch version,
+// scratch_function_count, scratch_function_ids and scratch_function_entries,
+// the compiled custom blocks by their index in the program's functions,
+// scratch_script_count, scratch_script_ids and scratch_script_entries,
+// the compiled scripts by sprite scripts index and script index, in pairs,
+// and scratch_coroutine_count, scratch_coroutine_ids and scratch_coroutine_entries, the same for coroutines
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::support::LLVMString;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine};
+use inkwell::types::BasicType;
+use inkwell::values::{BasicValue, FunctionValue};
+use inkwell::{AddressSpace, OptimizationLevel};
+
+use crate::scratch::ast::Program;
+use crate::scratch::compile::llvm::codegen::{self, Compiled};
+
+// the runtime library, as built by the runtime crate
+pub const RUNTIME: &str = "libscratch_runtime.a";
+
+// what Rust's std needs from the system, from `rustc --print native-static-libs` for the target this is
+// built for, which is the one the runtime's built for and the one objects are written for
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+const NATIVE_LIBS: &[&str] = &["-lgcc_s", "-lutil", "-lrt", "-lpthread", "-lm", "-ldl", "-lc"];
+#[cfg(all(target_os = "linux", target_env = "musl"))]
+const NATIVE_LIBS: &[&str] = &["-lc"];
+#[cfg(target_os = "macos")]
+const NATIVE_LIBS: &[&str] = &["-lSystem", "-lresolv", "-lc", "-lm", "-liconv"];
+#[cfg(target_os = "freebsd")]
+const NATIVE_LIBS: &[&str] = &["-lexecinfo", "-lpthread", "-lgcc_s", "-lc", "-lm", "-lrt", "-lutil"];
+// anywhere else, $SCRATCH_NATIVE_LIBS has to say
+#[cfg(not(any(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")), target_os = "macos", target_os = "freebsd")))]
+const NATIVE_LIBS: &[&str] = &[];
+
+#[derive(Debug)]
+pub enum Error {
+    Llvm(String),
+    Io(io::Error),
+    // the linker's
+    Link(ExitStatus),
+}
+
+impl From<LLVMString> for Error {
+    fn from(e: LLVMString) -> Self {
+        Error::Llvm(e.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn constant<T: BasicType>(module: &Module, ty: T, name: &str, value: &dyn BasicValue) {
+    let global = module.add_global(ty, None, name);
+    global.set_initializer(value);
+    global.set_constant(true);
+}
+
+fn entries(context: &Context, module: &Module, name: &str, entries: &[FunctionValue]) {
+    // the entries all have the same type, and any type will do for none
+    let ty = match entries.first() {
+        Some(entry) => entry.get_type().ptr_type(AddressSpace::Generic),
+        None => context.i8_type().ptr_type(AddressSpace::Generic),
+    };
+    let pointers = entries
+        .iter()
+        .map(|entry| entry.as_global_value().as_pointer_value())
+        .collect::<Vec<_>>();
+    constant(module, ty.array_type(entries.len() as u32), name, &ty.const_array(&pointers));
+}
+
+fn ids(context: &Context, module: &Module, name: &str, ids: &[u64]) {
+    let i64_type = context.i64_type();
+    let values = ids.iter().map(|&id| i64_type.const_int(id, false)).collect::<Vec<_>>();
+    constant(module, i64_type.array_type(ids.len() as u32), name, &i64_type.const_array(&values));
+}
+
+// adds the globals the runtime reads to the compiled module
+pub fn embed<'a>(context: &Context, program: &Program<'a>, compiled: &Compiled<'a>, json: &[u8], version: u32) {
+    let module = &compiled.module;
+    let i8_type = context.i8_type();
+    let i64_type = context.i64_type();
+    let bytes = json.iter().map(|&byte| i8_type.const_int(byte as u64, false)).collect::<Vec<_>>();
+    constant(module, i8_type.array_type(json.len() as u32), "scratch_project", &i8_type.const_array(&bytes));
+    constant(module, i64_type, "scratch_project_len", &i64_type.const_int(json.len() as u64, false));
+    let i32_type = context.i32_type();
+    constant(module, i32_type, "scratch_project_version", &i32_type.const_int(version as u64, false));
+
+    let function_ids = compiled
+        .functions
+        .iter()
+        .map(|compiled| {
+            program
+                .functions()
+                .iter()
+                .position(|&function| std::ptr::eq(function, compiled.function))
+                .unwrap() as u64
+        })
+        .collect::<Vec<_>>();
+    let function_entries = compiled.functions.iter().map(|compiled| compiled.entry).collect::<Vec<_>>();
+    constant(module, i64_type, "scratch_function_count", &i64_type.const_int(function_ids.len() as u64, false));
+    ids(context, module, "scratch_function_ids", &function_ids);
+    entries(context, module, "scratch_function_entries", &function_entries);
+
+    scripts(context, module, "scratch_script", &compiled.scripts);
+    scripts(context, module, "scratch_coroutine", &compiled.coroutines);
+}
+
+// the count, ids and entries of scripts by sprite scripts index and script index
+fn scripts(context: &Context, module: &Module, prefix: &str, scripts: &[((usize, usize), FunctionValue)]) {
+    let i64_type = context.i64_type();
+    let script_ids = scripts
+        .iter()
+        .flat_map(|&((sprite, script), _)| vec![sprite as u64, script as u64])
+        .collect::<Vec<_>>();
+    let script_entries = scripts.iter().map(|&(_, entry)| entry).collect::<Vec<_>>();
+    let count = i64_type.const_int(script_entries.len() as u64, false);
+    constant(module, i64_type, &format!("{}_count", prefix), &count);
+    ids(context, module, &format!("{}_ids", prefix), &script_ids);
+    entries(context, module, &format!("{}_entries", prefix), &script_entries);
+}
+
+// for the machine this runs on, and position independent since linkers make PIEs by default now
+pub fn write_object(module: &Module, path: &Path) -> Result<()> {
+    Target::initialize_native(&InitializationConfig::default()).map_err(Error::Llvm)?;
+    let triple = TargetMachine::get_default_triple().to_string();
+    let target = Target::from_triple(&triple)?;
+    let machine = target
+        .create_target_machine(&triple, "generic", "", OptimizationLevel::Default, RelocMode::PIC, CodeModel::Default)
+        .ok_or_else(|| Error::Llvm(format!("can't target {}", triple)))?;
+    module.set_triple(&triple);
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+    machine.write_to_file(module, FileType::Object, path)?;
+    Ok(())
+}
+
+// $SCRATCH_NATIVE_LIBS, split on whitespace, or the target's
+fn native_libs() -> Vec<String> {
+    match std::env::var("SCRATCH_NATIVE_LIBS") {
+        Ok(libs) => libs.split_whitespace().map(String::from).collect(),
+        Err(_) => NATIVE_LIBS.iter().map(|lib| lib.to_string()).collect(),
+    }
+}
+
+// with $CC, or cc
+pub fn link(object: &Path, runtime: &Path, output: &Path) -> Result<()> {
+    let linker = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(linker)
+        .arg(object)
+        .arg(runtime)
+        .args(native_libs())
+        .arg("-o")
+        .arg(output)
+        .status()?;
+    if !status.success() {
+        return Err(Error::Link(status));
+    }
+    Ok(())
+}
+
+// $SCRATCH_RUNTIME, or next to this executable, where cargo puts it when building the workspace
+pub fn default_runtime() -> PathBuf {
+    if let Some(path) = std::env::var_os("SCRATCH_RUNTIME") {
+        return PathBuf::from(path);
+    }
+    let exe = std::env::current_exe().unwrap_or_default();
+    exe.with_file_name(RUNTIME)
+}
+
+// returns what couldn't be compiled, which is left to the runtime's interpreter
+pub fn build(
+    program: &Program,
+    json: &[u8],
+    version: u32,
+    runtime: &Path,
+    output: &Path,
+) -> Result<Vec<codegen::Error>> {
+    let context = Context::create();
+    let compiled = codegen::compile(&context, program);
+    embed(&context, program, &compiled, json, version);
+    let object = output.with_extension("o");
+    write_object(&compiled.module, &object)?;
+    link(&object, runtime, output)?;
+    std::fs::remove_file(&object)?;
+    Ok(compiled.errors)
+}
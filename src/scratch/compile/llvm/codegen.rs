@@ -0,0 +1,756 @@
+// turns Computational scripts and custom blocks into LLVM functions,
+// since they never wait or touch the stage, so they can run straight through at native speed,
+// and Reactive scripts that only wait into resumable ones, from their ast::coroutine::Coroutine
+//
+// every value is a double or a bool, and variables live in a globals struct passed to every function,
+// with lists as pointers the runtime helpers work on, so anything needing strings is left to the interpreter
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::module::Module;
+use inkwell::types::{BasicTypeEnum, FloatType, PointerType, StructType};
+use inkwell::values::{BasicValueEnum, FloatValue, FunctionValue, IntValue, PointerValue};
+use inkwell::{AddressSpace, FloatPredicate};
+
+use crate::scratch::ast::{Block, Constant, Function, List, Number, Program, Variable};
+use crate::scratch::ast::compute_kind::{Analysis, Computable, ComputeKind};
+use crate::scratch::ast::coroutine::{Coroutine, Op};
+use crate::scratch::ast::instruction::{GetInstruction, Instruction, ReadWriteInstruction};
+use crate::scratch::ast::instruction::control_flow::ControlFlowInstruction;
+use crate::scratch::ast::instruction::function_call::{BinaryOp, CallInstruction, ComparisonOp, FloatToFloatOp, FloatToIntOp, FunctionCallInstruction, LogicOp, MathOp, StringOp, UnaryOp};
+use crate::scratch::ast::instruction::list::{ListInstruction, PushPopSide, ReturningListInstruction, VoidListInstruction};
+use crate::scratch::runtime::native::{globals, lists, DONE};
+use crate::scratch::runtime::value;
+
+// runtime helpers the compiled code calls, which whoever runs it has to provide
+pub const RANDOM: &str = "scratch_random";
+pub const TAN: &str = "scratch_tan";
+// the string ops on numbers
+pub const LENGTH: &str = "scratch_length";
+pub const CONTAINS: &str = "scratch_contains";
+// take the list first, and indices are 1-based
+pub const LIST_LENGTH: &str = "scratch_list_length";
+pub const LIST_ITEM: &str = "scratch_list_item";
+pub const LIST_FIND: &str = "scratch_list_find";
+pub const LIST_CONTAINS: &str = "scratch_list_contains";
+pub const LIST_SET: &str = "scratch_list_set";
+pub const LIST_INSERT: &str = "scratch_list_insert";
+pub const LIST_DELETE: &str = "scratch_list_delete";
+pub const LIST_PUSH: &str = "scratch_list_push";
+pub const LIST_CLEAR: &str = "scratch_list_clear";
+
+// the libm functions the compiled code calls
+pub const LIBM: [&str; 3] = ["asin", "acos", "atan"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // the LLVM function that couldn't be compiled, and why
+    Unsupported { function: String, reason: &'static str },
+}
+
+pub struct CompiledFunction<'a> {
+    pub function: &'a Function<'a>,
+    // takes a pointer to the globals and then its args as doubles
+    pub value: FunctionValue,
+    // takes a pointer to the globals and a pointer to its args, so it can be called without knowing its arity
+    pub entry: FunctionValue,
+}
+
+pub struct Compiled<'a> {
+    pub module: Module,
+    // the globals struct has a double for each variable and then a pointer for each list, in this order
+    pub globals: Vec<&'a Variable<'a>>,
+    pub lists: Vec<&'a List<'a>>,
+    pub functions: Vec<CompiledFunction<'a>>,
+    // by sprite scripts index and script index, and each takes a pointer to the globals
+    pub scripts: Vec<((usize, usize), FunctionValue)>,
+    // the same, but each also takes pointers to its state and slots, and returns how long to wait
+    // or sets its state to DONE
+    pub coroutines: Vec<((usize, usize), FunctionValue)>,
+    // the scripts and custom blocks that weren't compiled
+    pub errors: Vec<Error>,
+}
+
+type Result<T> = std::result::Result<T, &'static str>;
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    Number(FloatValue),
+    Bool(IntValue),
+}
+
+struct Codegen<'c, 'a> {
+    context: &'c Context,
+    module: Module,
+    builder: Builder,
+    number: FloatType,
+    pointer: PointerType,
+    globals_type: StructType,
+    fields: HashMap<*const Variable<'a>, u32>,
+    list_fields: HashMap<*const List<'a>, u32>,
+    functions: HashMap<*const Function<'a>, FunctionValue>,
+    // the function being compiled, its globals param, and the custom block params bound to its other params
+    function: Option<FunctionValue>,
+    globals: Option<PointerValue>,
+    params: Vec<(*const Variable<'a>, FloatValue)>,
+}
+
+impl<'c, 'a> Codegen<'c, 'a> {
+    fn new(context: &'c Context, globals: &[&'a Variable<'a>], lists: &[&'a List<'a>]) -> Codegen<'c, 'a> {
+        let module = context.create_module("scratch");
+        let number = context.f64_type();
+        let pointer = context.i8_type().ptr_type(AddressSpace::Generic);
+        let globals_type = context.opaque_struct_type("globals");
+        let mut field_types = vec![BasicTypeEnum::from(number); globals.len()];
+        field_types.extend(vec![BasicTypeEnum::from(pointer); lists.len()]);
+        globals_type.set_body(&field_types, false);
+        Codegen {
+            context,
+            module,
+            builder: context.create_builder(),
+            number,
+            pointer,
+            globals_type,
+            fields: globals
+                .iter()
+                .enumerate()
+                .map(|(index, &variable)| (variable as *const _, index as u32))
+                .collect(),
+            list_fields: lists
+                .iter()
+                .enumerate()
+                .map(|(index, &list)| (list as *const _, (globals.len() + index) as u32))
+                .collect(),
+            functions: HashMap::new(),
+            function: None,
+            globals: None,
+            params: Vec::new(),
+        }
+    }
+
+    fn declare(&self, name: &str, params: usize) -> FunctionValue {
+        let mut param_types = vec![self.globals_type.ptr_type(AddressSpace::Generic).into()];
+        param_types.extend(vec![BasicTypeEnum::from(self.number); params]);
+        let fn_type = self.context.void_type().fn_type(&param_types, false);
+        self.module.add_function(name, fn_type, None)
+    }
+
+    fn declare_coroutine(&self, name: &str) -> FunctionValue {
+        let param_types = [
+            self.globals_type.ptr_type(AddressSpace::Generic).into(),
+            self.context.i64_type().ptr_type(AddressSpace::Generic).into(),
+            self.number.ptr_type(AddressSpace::Generic).into(),
+        ];
+        self.module.add_function(name, self.number.fn_type(&param_types, false), None)
+    }
+
+    fn declare_function(&mut self, function: &'a Function<'a>) {
+        let name = format!("{}.{}", function.sprite().name(), function.name());
+        let value = self.declare(&name, function.params().len());
+        self.functions.insert(function, value);
+    }
+
+    // compiles the body of a declared function
+    fn define(&mut self, value: FunctionValue, params: &[*const Variable<'a>], block: &Block<'a>) -> Result<()> {
+        let entry = self.context.append_basic_block(&value, "entry");
+        self.builder.position_at_end(&entry);
+        self.function = Some(value);
+        self.globals = value.get_nth_param(0).map(BasicValueEnum::into_pointer_value);
+        self.params = params
+            .iter()
+            .enumerate()
+            .map(|(index, &param)| (param, value.get_nth_param(index as u32 + 1).unwrap().into_float_value()))
+            .collect();
+        self.block(block)?;
+        self.builder.build_return(None);
+        Ok(())
+    }
+
+    fn define_function(&mut self, function: &'a Function<'a>) -> Result<()> {
+        let params = function
+            .params()
+            .iter()
+            .map(|param| param.variable() as *const _)
+            .collect::<Vec<_>>();
+        self.define(self.functions[&(function as *const _)], &params, function.body())
+    }
+
+    // every state gets a basic block, and resuming switches to the one it stopped at
+    fn define_coroutine(&mut self, value: FunctionValue, coroutine: &Coroutine<'_, 'a>) -> Result<()> {
+        let entry = self.context.append_basic_block(&value, "entry");
+        self.function = Some(value);
+        self.globals = value.get_nth_param(0).map(BasicValueEnum::into_pointer_value);
+        self.params = Vec::new();
+        let state = value.get_nth_param(1).unwrap().into_pointer_value();
+        let slots = value.get_nth_param(2).unwrap().into_pointer_value();
+        let i64_type = self.context.i64_type();
+        let ops = coroutine.ops();
+        let blocks = (0..=ops.len())
+            .map(|_| self.context.append_basic_block(&value, "state"))
+            .collect::<Vec<_>>();
+        self.builder.position_at_end(&entry);
+        let resumed = self.builder.build_load(state, "state").into_int_value();
+        let cases = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (i64_type.const_int(index as u64, false), block))
+            .collect::<Vec<_>>();
+        self.builder.build_switch(resumed, &blocks[ops.len()], &cases);
+        for (index, op) in ops.iter().enumerate() {
+            self.builder.position_at_end(&blocks[index]);
+            let next = &blocks[index + 1];
+            match op {
+                Op::Exec(instruction) => {
+                    self.instruction(instruction)?;
+                    self.builder.build_unconditional_branch(next);
+                }
+                Op::Unless(condition, target) => {
+                    let condition = self.condition(condition)?;
+                    self.builder.build_conditional_branch(condition, next, &blocks[*target]);
+                }
+                Op::Goto(target) => {
+                    self.builder.build_unconditional_branch(&blocks[*target]);
+                }
+                Op::Times(index, times) => {
+                    let times = self.number(times)?;
+                    self.builder.build_store(self.slot(slots, *index), self.round(times));
+                    self.builder.build_unconditional_branch(next);
+                }
+                // NaN times is none
+                Op::CountDown(index, target) => {
+                    let count = self.builder.build_load(self.slot(slots, *index), "count").into_float_value();
+                    let more = self.builder.build_float_compare(FloatPredicate::OGT, count, self.constant(0.), "more");
+                    let count_down = self.context.append_basic_block(&value, "count_down");
+                    self.builder.build_conditional_branch(more, &count_down, &blocks[*target]);
+                    self.builder.position_at_end(&count_down);
+                    let count = self.builder.build_float_sub(count, self.constant(1.), "count");
+                    self.builder.build_store(self.slot(slots, *index), count);
+                    self.builder.build_unconditional_branch(next);
+                }
+                Op::Yield => self.suspend(state, index + 1, self.constant(0.)),
+                // no less than no time, like the interpreter
+                Op::Wait(duration) => {
+                    let duration = self.number(duration)?;
+                    let duration = self.apply("llvm.maxnum.f64", &[duration, self.constant(0.)]);
+                    self.suspend(state, index + 1, duration);
+                }
+                // waits here for a frame until it's true
+                Op::WaitUntil(condition) => {
+                    let condition = self.condition(condition)?;
+                    let poll = self.context.append_basic_block(&value, "poll");
+                    self.builder.build_conditional_branch(condition, next, &poll);
+                    self.builder.position_at_end(&poll);
+                    self.suspend(state, index, self.constant(0.));
+                }
+                Op::Stop => self.finish(state),
+                Op::AskAndWait(_) | Op::BroadcastAndWait(_) | Op::Timed(_) | Op::Call(_) => {
+                    return Err("only waiting and yielding can be compiled");
+                }
+            }
+        }
+        self.builder.position_at_end(&blocks[ops.len()]);
+        self.finish(state);
+        Ok(())
+    }
+
+    fn slot(&self, slots: PointerValue, index: usize) -> PointerValue {
+        let index = self.context.i32_type().const_int(index as u64, false);
+        unsafe { self.builder.build_in_bounds_gep(slots, &[index], "slot") }
+    }
+
+    // stops at the state, returning how long to wait
+    fn suspend(&self, state: PointerValue, at: usize, secs: FloatValue) {
+        self.builder.build_store(state, self.context.i64_type().const_int(at as u64, false));
+        self.builder.build_return(Some(&secs));
+    }
+
+    // stops for good, which only the state says
+    fn finish(&self, state: PointerValue) {
+        self.builder.build_store(state, self.context.i64_type().const_int(DONE as u64, true));
+        self.builder.build_return(Some(&self.constant(0.)));
+    }
+
+    // calls the function with its args loaded from an array
+    fn define_entry(&self, function: &'a Function<'a>) -> FunctionValue {
+        let value = self.functions[&(function as *const _)];
+        let param_types = [
+            self.globals_type.ptr_type(AddressSpace::Generic).into(),
+            self.number.ptr_type(AddressSpace::Generic).into(),
+        ];
+        let fn_type = self.context.void_type().fn_type(&param_types, false);
+        let entry = self.module.add_function(&format!("{}.entry", Codegen::name(value)), fn_type, None);
+        let block = self.context.append_basic_block(&entry, "entry");
+        self.builder.position_at_end(&block);
+        let array = entry.get_nth_param(1).unwrap().into_pointer_value();
+        let mut args = vec![entry.get_nth_param(0).unwrap()];
+        for index in 0..function.params().len() {
+            let index = self.context.i32_type().const_int(index as u64, false);
+            let arg = unsafe { self.builder.build_in_bounds_gep(array, &[index], "arg") };
+            args.push(self.builder.build_load(arg, "arg"));
+        }
+        self.builder.build_call(value, &args, "");
+        self.builder.build_return(None);
+        entry
+    }
+
+    fn name(value: FunctionValue) -> String {
+        value.get_name().to_string_lossy().into_owned()
+    }
+
+    // declared the first time it's used, with the types of the args it's first called with,
+    // and returns a double if it returns anything
+    fn helper(&self, name: &str, args: &[BasicValueEnum], returns: bool) -> Option<FloatValue> {
+        let function = self.module.get_function(name).unwrap_or_else(|| {
+            let params = args.iter().map(BasicValueEnum::get_type).collect::<Vec<_>>();
+            let fn_type = if returns {
+                self.number.fn_type(&params, false)
+            } else {
+                self.context.void_type().fn_type(&params, false)
+            };
+            self.module.add_function(name, fn_type, None)
+        });
+        // void calls can't be named
+        let call = self.builder.build_call(function, args, if returns { name } else { "" });
+        call.try_as_basic_value().left().map(BasicValueEnum::into_float_value)
+    }
+
+    fn apply(&self, name: &str, args: &[FloatValue]) -> FloatValue {
+        let args = args.iter().map(|&arg| arg.into()).collect::<Vec<BasicValueEnum>>();
+        self.helper(name, &args, true).unwrap()
+    }
+
+    fn is_true(&self, n: FloatValue) -> IntValue {
+        // NaN is false
+        self.builder.build_float_compare(FloatPredicate::ONE, n, self.constant(0.), "bool")
+    }
+
+    fn constant(&self, n: f64) -> FloatValue {
+        self.number.const_float(n)
+    }
+
+    fn block(&mut self, block: &Block<'a>) -> Result<()> {
+        for instruction in block.instructions() {
+            self.instruction(instruction)?;
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, instruction: &Instruction<'a>) -> Result<()> {
+        match instruction {
+            Instruction::Op(CallInstruction::Function(call)) => self.call(call),
+            Instruction::Op(op) => self.value(op).map(drop),
+            // reading has no effects
+            Instruction::Get(_) => Ok(()),
+            Instruction::Set(set) => match set.property() {
+                ReadWriteInstruction::Variable(variable) => {
+                    let value = self.item(set.value())?;
+                    let ptr = self.field(variable)?;
+                    self.builder.build_store(ptr, value);
+                    Ok(())
+                }
+                _ => Err("only variables can be set"),
+            },
+            Instruction::ControlFlow(control_flow) => self.control_flow(control_flow),
+            Instruction::List(ListInstruction::Void(op), list) => self.list_instruction(op, list),
+            Instruction::List(ListInstruction::Returning(op), list) => self.list_element(op, list).map(drop),
+            Instruction::Render(_) => Err("rendering can't be compiled"),
+        }
+    }
+
+    fn list(&self, list: &CallInstruction<'a>) -> Result<BasicValueEnum> {
+        let list = match list {
+            CallInstruction::Id(GetInstruction::ReadWrite(ReadWriteInstruction::List(list))) => *list,
+            _ => return Err("only lists can be used as lists"),
+        };
+        let index = self.list_fields[&(list as *const _)];
+        let field = unsafe { self.builder.build_struct_gep(self.globals.unwrap(), index, list.name()) };
+        Ok(self.builder.build_load(field, list.name()))
+    }
+
+    fn list_instruction(&mut self, op: &VoidListInstruction<'a>, list: &CallInstruction<'a>) -> Result<()> {
+        let list = self.list(list)?;
+        let (name, args) = match op {
+            VoidListInstruction::Set(index, item) => (LIST_SET, vec![list, self.number(index)?.into(), self.item(item)?.into()]),
+            VoidListInstruction::Insert(index, item) => (LIST_INSERT, vec![list, self.number(index)?.into(), self.item(item)?.into()]),
+            VoidListInstruction::Remove(index) => (LIST_DELETE, vec![list, self.number(index)?.into()]),
+            VoidListInstruction::Push(item, push) if push.side() == PushPopSide::Right && push.index() == 0 => {
+                (LIST_PUSH, vec![list, self.item(item)?.into()])
+            }
+            VoidListInstruction::Push(_, _) => return Err("only adding to the end of a list can be compiled"),
+            VoidListInstruction::Clear() => (LIST_CLEAR, vec![list]),
+        };
+        self.helper(name, &args, false);
+        Ok(())
+    }
+
+    fn list_element(&mut self, op: &ReturningListInstruction<'a>, list: &CallInstruction<'a>) -> Result<Scalar> {
+        let list = self.list(list)?;
+        Ok(match op {
+            ReturningListInstruction::Length() => Scalar::Number(self.helper(LIST_LENGTH, &[list], true).unwrap()),
+            // items are read as numbers
+            ReturningListInstruction::Get(index) => {
+                let args = [list, self.number(index)?.into()];
+                Scalar::Number(self.helper(LIST_ITEM, &args, true).unwrap())
+            }
+            ReturningListInstruction::Find(item) => {
+                let args = [list, self.raw_number(item)?.into()];
+                Scalar::Number(self.helper(LIST_FIND, &args, true).unwrap())
+            }
+            ReturningListInstruction::Contains(item) => {
+                let args = [list, self.raw_number(item)?.into()];
+                Scalar::Bool(self.is_true(self.helper(LIST_CONTAINS, &args, true).unwrap()))
+            }
+            _ => return Err("strings can't be compiled"),
+        })
+    }
+
+    fn call(&mut self, call: &FunctionCallInstruction<'a>) -> Result<()> {
+        let function = *self
+            .functions
+            .get(&(call.function() as *const _))
+            .ok_or("calls a custom block that can't be compiled")?;
+        let mut args = vec![self.globals.unwrap().into()];
+        for arg in call.args() {
+            args.push(self.raw_number(arg)?.into());
+        }
+        self.builder.build_call(function, &args, "");
+        Ok(())
+    }
+
+    fn control_flow(&mut self, control_flow: &ControlFlowInstruction<'a>) -> Result<()> {
+        let function = self.function.unwrap();
+        match control_flow {
+            ControlFlowInstruction::Branch { condition, r#if, r#else } => {
+                let condition = self.condition(condition)?;
+                let if_block = self.context.append_basic_block(&function, "if");
+                let else_block = self.context.append_basic_block(&function, "else");
+                let end = self.context.append_basic_block(&function, "end_if");
+                self.builder.build_conditional_branch(condition, &if_block, &else_block);
+                self.builder.position_at_end(&if_block);
+                self.block(r#if)?;
+                self.builder.build_unconditional_branch(&end);
+                self.builder.position_at_end(&else_block);
+                self.block(r#else)?;
+                self.builder.build_unconditional_branch(&end);
+                self.builder.position_at_end(&end);
+            }
+            ControlFlowInstruction::While(condition, body) => {
+                let header = self.context.append_basic_block(&function, "while");
+                let body_block = self.context.append_basic_block(&function, "while_body");
+                let end = self.context.append_basic_block(&function, "end_while");
+                self.builder.build_unconditional_branch(&header);
+                self.builder.position_at_end(&header);
+                let condition = self.condition(condition)?;
+                self.builder.build_conditional_branch(condition, &body_block, &end);
+                self.builder.position_at_end(&body_block);
+                self.block(body)?;
+                self.builder.build_unconditional_branch(&header);
+                self.builder.position_at_end(&end);
+            }
+            ControlFlowInstruction::Repeat(times, body) => {
+                let times = self.number(times)?;
+                let times = self.round(times);
+                let before = self.builder.get_insert_block().unwrap();
+                let header = self.context.append_basic_block(&function, "repeat");
+                let body_block = self.context.append_basic_block(&function, "repeat_body");
+                let end = self.context.append_basic_block(&function, "end_repeat");
+                self.builder.build_unconditional_branch(&header);
+                self.builder.position_at_end(&header);
+                let i = self.builder.build_phi(self.number, "i");
+                let more = self.builder.build_float_compare(FloatPredicate::OLT, i.as_basic_value().into_float_value(), times, "more");
+                self.builder.build_conditional_branch(more, &body_block, &end);
+                self.builder.position_at_end(&body_block);
+                self.block(body)?;
+                let next = self.builder.build_float_add(i.as_basic_value().into_float_value(), self.constant(1.), "next");
+                // the body might have ended in a different block
+                let latch = self.builder.get_insert_block().unwrap();
+                self.builder.build_unconditional_branch(&header);
+                i.add_incoming(&[(&self.constant(0.), &before), (&next, &latch)]);
+                self.builder.position_at_end(&end);
+            }
+            _ => return Err("only branches and loops can be compiled"),
+        }
+        Ok(())
+    }
+
+    fn field(&self, variable: &Variable<'a>) -> Result<PointerValue> {
+        let index = *self.fields.get(&(variable as *const _)).ok_or("params can't be set")?;
+        Ok(unsafe { self.builder.build_struct_gep(self.globals.unwrap(), index, variable.name()) })
+    }
+
+    fn value(&mut self, call: &CallInstruction<'a>) -> Result<Scalar> {
+        match call {
+            CallInstruction::Id(get) => self.get(get),
+            CallInstruction::UnaryOp { op, value } => self.unary(*op, value),
+            CallInstruction::BinaryOp { op, left, right } => self.binary(*op, left, right),
+            CallInstruction::Function(_) => Err("custom blocks don't report values"),
+        }
+    }
+
+    // as it is, NaN included, which is only right for comparing and storing it
+    fn raw_number(&mut self, call: &CallInstruction<'a>) -> Result<FloatValue> {
+        Ok(match self.value(call)? {
+            Scalar::Number(n) => n,
+            Scalar::Bool(b) => self.builder.build_unsigned_int_to_float(b, self.number, "number"),
+        })
+    }
+
+    // NaN is 0 everywhere else, like Cast.toNumber
+    fn number(&mut self, call: &CallInstruction<'a>) -> Result<FloatValue> {
+        let n = self.raw_number(call)?;
+        let nan = self.is_nan(n);
+        Ok(self.builder.build_select(nan, self.constant(0.), n, "number").into_float_value())
+    }
+
+    // stored values are read back as numbers, so a bool would come back as 1 or 0 rather than true or false
+    fn item(&mut self, call: &CallInstruction<'a>) -> Result<FloatValue> {
+        match self.value(call)? {
+            Scalar::Number(n) => Ok(n),
+            Scalar::Bool(_) => Err("booleans can't be stored"),
+        }
+    }
+
+    fn is_nan(&self, n: FloatValue) -> IntValue {
+        self.builder.build_float_compare(FloatPredicate::UNO, n, n, "nan")
+    }
+
+    fn condition(&mut self, call: &CallInstruction<'a>) -> Result<IntValue> {
+        Ok(match self.value(call)? {
+            Scalar::Bool(b) => b,
+            Scalar::Number(n) => self.is_true(n),
+        })
+    }
+
+    fn get(&mut self, get: &GetInstruction<'a>) -> Result<Scalar> {
+        match get {
+            GetInstruction::Constant(Constant::Bool(b)) => Ok(Scalar::Bool(self.context.bool_type().const_int(*b as u64, false))),
+            GetInstruction::Constant(Constant::Number(n)) => Ok(Scalar::Number(self.constant(match *n {
+                Number::UInt(n) => n as f64,
+                Number::Int(n) => n as f64,
+                Number::Float(n) => n,
+            }))),
+            // strings that are numbers are numbers everywhere but comparisons with other strings,
+            // and whitespace isn't a number there
+            GetInstruction::Constant(Constant::String(s)) => match value::parse_number(s) {
+                n if n.is_nan() || s.trim().is_empty() => Err("strings can't be compiled"),
+                n => Ok(Scalar::Number(self.constant(n))),
+            },
+            GetInstruction::ReadWrite(ReadWriteInstruction::Variable(variable)) => {
+                let ptr = *variable as *const _;
+                if let Some(&(_, arg)) = self.params.iter().find(|(param, _)| *param == ptr) {
+                    return Ok(Scalar::Number(arg));
+                }
+                let field = self.field(variable)?;
+                Ok(Scalar::Number(self.builder.build_load(field, variable.name()).into_float_value()))
+            }
+            GetInstruction::Element(op, list) => self.list_element(op, list),
+            // a list's contents are a string
+            GetInstruction::ReadWrite(ReadWriteInstruction::List(_)) => Err("strings can't be compiled"),
+            GetInstruction::ReadWrite(ReadWriteInstruction::Property(_)) => Err("properties can't be compiled"),
+        }
+    }
+
+    // halves round up, like value::round
+    fn round(&self, n: FloatValue) -> FloatValue {
+        let floor = self.apply("llvm.floor.f64", &[n]);
+        let fraction = self.builder.build_float_sub(n, floor, "fraction");
+        let up = self.builder.build_float_compare(FloatPredicate::OGE, fraction, self.constant(0.5), "up");
+        let ceiling = self.builder.build_float_add(floor, self.constant(1.), "ceiling");
+        self.builder.build_select(up, ceiling, floor, "round").into_float_value()
+    }
+
+    // like value::float_to_float
+    fn trig(&self, name: &str, degrees: FloatValue) -> FloatValue {
+        let radians = self.builder.build_float_mul(degrees, self.constant(PI / 180.), "radians");
+        let result = self.apply(name, &[radians]);
+        let scaled = self.builder.build_float_mul(result, self.constant(1e10), "scaled");
+        let rounded = self.round(scaled);
+        self.builder.build_float_div(rounded, self.constant(1e10), "trig")
+    }
+
+    fn degrees(&self, name: &str, n: FloatValue) -> FloatValue {
+        let radians = self.apply(name, &[n]);
+        self.builder.build_float_mul(radians, self.constant(180. / PI), "degrees")
+    }
+
+    fn unary(&mut self, op: UnaryOp, value: &CallInstruction<'a>) -> Result<Scalar> {
+        if let UnaryOp::Not() = op {
+            let condition = self.condition(value)?;
+            return Ok(Scalar::Bool(self.builder.build_not(condition, "not")));
+        }
+        let n = match op {
+            UnaryOp::VectorIndex(_) => return Err("positions can't be compiled"),
+            _ => self.number(value)?,
+        };
+        Ok(Scalar::Number(match op {
+            UnaryOp::Length() => self.apply(LENGTH, &[n]),
+            UnaryOp::Abs() => self.apply("llvm.fabs.f64", &[n]),
+            UnaryOp::FloatToInt(FloatToIntOp::Round) => self.round(n),
+            UnaryOp::FloatToInt(FloatToIntOp::Floor) => self.apply("llvm.floor.f64", &[n]),
+            UnaryOp::FloatToInt(FloatToIntOp::Ceiling) => self.apply("llvm.ceil.f64", &[n]),
+            UnaryOp::FloatToFloat(op) => match op {
+                FloatToFloatOp::Sqrt => self.apply("llvm.sqrt.f64", &[n]),
+                FloatToFloatOp::Sin => self.trig("llvm.sin.f64", n),
+                FloatToFloatOp::Cos => self.trig("llvm.cos.f64", n),
+                // needs Scratch's special cases, and there's no intrinsic anyways
+                FloatToFloatOp::Tan => self.apply(TAN, &[n]),
+                FloatToFloatOp::ASin => self.degrees("asin", n),
+                FloatToFloatOp::ACos => self.degrees("acos", n),
+                FloatToFloatOp::ATan => self.degrees("atan", n),
+                FloatToFloatOp::Ln => self.apply("llvm.log.f64", &[n]),
+                FloatToFloatOp::Log => {
+                    let ln = self.apply("llvm.log.f64", &[n]);
+                    self.builder.build_float_div(ln, self.constant(std::f64::consts::LN_10), "log")
+                }
+                FloatToFloatOp::Exp => self.apply("llvm.exp.f64", &[n]),
+            },
+            UnaryOp::Not() | UnaryOp::VectorIndex(_) => unreachable!(),
+        }))
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: &CallInstruction<'a>, right: &CallInstruction<'a>) -> Result<Scalar> {
+        match op {
+            BinaryOp::Logic(op) => {
+                let left = self.condition(left)?;
+                let right = self.condition(right)?;
+                return Ok(Scalar::Bool(match op {
+                    LogicOp::And => self.builder.build_and(left, right, "and"),
+                    LogicOp::Or => self.builder.build_or(left, right, "or"),
+                }));
+            }
+            BinaryOp::String(StringOp::Contains) => {
+                let args = [self.raw_number(left)?, self.raw_number(right)?];
+                return Ok(Scalar::Bool(self.is_true(self.apply(CONTAINS, &args))));
+            }
+            BinaryOp::String(_) => return Err("strings can't be compiled"),
+            BinaryOp::Vector() | BinaryOp::Math(MathOp::Distance) => return Err("positions can't be compiled"),
+            BinaryOp::Comparison(op) => {
+                let left = self.raw_number(left)?;
+                let right = self.raw_number(right)?;
+                return Ok(Scalar::Bool(self.compare(op, left, right)));
+            }
+            _ => {}
+        }
+        let left = self.number(left)?;
+        let right = self.number(right)?;
+        let builder = &self.builder;
+        Ok(match op {
+            BinaryOp::Math(op) => Scalar::Number(match op {
+                MathOp::Add => builder.build_float_add(left, right, "add"),
+                MathOp::Subtract => builder.build_float_sub(left, right, "subtract"),
+                MathOp::Multiply => builder.build_float_mul(left, right, "multiply"),
+                MathOp::Divide => builder.build_float_div(left, right, "divide"),
+                // like value::modulo
+                MathOp::Modulo => {
+                    let remainder = builder.build_float_rem(left, right, "remainder");
+                    let ratio = builder.build_float_div(remainder, right, "ratio");
+                    let negative = builder.build_float_compare(FloatPredicate::OLT, ratio, self.constant(0.), "negative");
+                    let wrapped = builder.build_float_add(remainder, right, "wrapped");
+                    builder.build_select(negative, wrapped, remainder, "modulo").into_float_value()
+                }
+                MathOp::Pow => self.apply("llvm.pow.f64", &[left, right]),
+                MathOp::Random => self.apply(RANDOM, &[left, right]),
+                MathOp::Distance => unreachable!(),
+            }),
+            BinaryOp::Comparison(_) | BinaryOp::Logic(_) | BinaryOp::String(_) | BinaryOp::Vector() => unreachable!(),
+        })
+    }
+
+    // both are numbers, so they're compared as numbers, except NaN, which Scratch compares as the
+    // string "NaN". that's equal to itself and more than any other number's string
+    fn compare(&self, op: ComparisonOp, left: FloatValue, right: FloatValue) -> IntValue {
+        let builder = &self.builder;
+        let (left_nan, right_nan) = (self.is_nan(left), self.is_nan(right));
+        let (ordered, left_nan, right_nan) = match op {
+            ComparisonOp::LessThan => (FloatPredicate::OLT, builder.build_not(left_nan, "number"), right_nan),
+            ComparisonOp::GreaterThan => (FloatPredicate::OGT, left_nan, builder.build_not(right_nan, "number")),
+            ComparisonOp::Equals => (FloatPredicate::OEQ, left_nan, right_nan),
+        };
+        let unordered = builder.build_and(left_nan, right_nan, "unordered");
+        let ordered = builder.build_float_compare(ordered, left, right, "compare");
+        builder.build_or(ordered, unordered, "compare")
+    }
+}
+
+pub fn compile<'a>(context: &Context, program: &Program<'a>) -> Compiled<'a> {
+    let mut analysis = Analysis::new();
+    let mut functions = program
+        .functions()
+        .iter()
+        .copied()
+        .filter(|&function| analysis.function(function) == ComputeKind::Computational)
+        .collect::<Vec<_>>();
+    let globals = globals(program);
+    let lists = lists(program);
+    let mut errors = Vec::new();
+    // custom blocks that can't be compiled make their callers uncompilable too,
+    // so start over without them until every remaining one compiles
+    loop {
+        let mut codegen = Codegen::new(context, &globals, &lists);
+        for &function in &functions {
+            codegen.declare_function(function);
+        }
+        let mut failed = Vec::new();
+        for &function in &functions {
+            if let Err(reason) = codegen.define_function(function) {
+                let name = Codegen::name(codegen.functions[&(function as *const _)]);
+                errors.push(Error::Unsupported { function: name, reason });
+                failed.push(function as *const _);
+            }
+        }
+        if !failed.is_empty() {
+            functions.retain(|&function| !failed.contains(&(function as *const _)));
+            continue;
+        }
+
+        // nothing calls scripts, so those that fail can just be removed
+        let mut scripts = Vec::new();
+        let mut coroutines = Vec::new();
+        for (sprite_index, sprite_scripts) in program.sprite_scripts().iter().enumerate() {
+            for (script_index, script) in sprite_scripts.scripts().iter().enumerate() {
+                let name = format!("{}.script{}", sprite_scripts.sprite().name(), script_index);
+                let kind = script.block().get_compute_kind(&mut analysis);
+                if kind == ComputeKind::Graphical {
+                    continue;
+                }
+                // not in warp mode, like the interpreter runs it
+                let coroutine = Coroutine::new(script.block(), false, &mut analysis);
+                // only scripts that never yield, which can't have loops, can run all at once
+                let (value, result, compiled) = if kind == ComputeKind::Computational && !coroutine.yields() {
+                    let value = codegen.declare(&name, 0);
+                    (value, codegen.define(value, &[], script.block()), &mut scripts)
+                } else {
+                    let value = codegen.declare_coroutine(&format!("{}.resume", name));
+                    (value, codegen.define_coroutine(value, &coroutine), &mut coroutines)
+                };
+                match result {
+                    Ok(()) => compiled.push(((sprite_index, script_index), value)),
+                    Err(reason) => {
+                        errors.push(Error::Unsupported { function: Codegen::name(value), reason });
+                        unsafe { value.delete() };
+                    }
+                }
+            }
+        }
+        let functions = functions
+            .iter()
+            .map(|&function| CompiledFunction {
+                function,
+                value: codegen.functions[&(function as *const _)],
+                entry: codegen.define_entry(function),
+            })
+            .collect();
+        return Compiled {
+            module: codegen.module,
+            globals,
+            lists,
+            functions,
+            scripts,
+            coroutines,
+            errors,
+        };
+    }
+}
@@ -0,0 +1,87 @@
+// runs compiled code in this process through LLVM's JIT, with the runtime helpers mapped in
+
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Module;
+use inkwell::support::LLVMString;
+use inkwell::values::FunctionValue;
+use inkwell::OptimizationLevel;
+
+use crate::scratch::ast::Program;
+use crate::scratch::compile::llvm::codegen::{self, Compiled};
+use crate::scratch::runtime::helpers;
+use crate::scratch::runtime::native::{CoroutineEntry, Entry, Native, ScriptEntry};
+
+// libm's, which the JIT might not find on its own
+extern "C" fn asin(n: f64) -> f64 {
+    n.asin()
+}
+
+extern "C" fn acos(n: f64) -> f64 {
+    n.acos()
+}
+
+extern "C" fn atan(n: f64) -> f64 {
+    n.atan()
+}
+
+fn helpers() -> Vec<(&'static str, usize)> {
+    vec![
+        (codegen::RANDOM, helpers::scratch_random as *const () as usize),
+        (codegen::TAN, helpers::scratch_tan as *const () as usize),
+        (codegen::LENGTH, helpers::scratch_length as *const () as usize),
+        (codegen::CONTAINS, helpers::scratch_contains as *const () as usize),
+        (codegen::LIST_LENGTH, helpers::scratch_list_length as *const () as usize),
+        (codegen::LIST_ITEM, helpers::scratch_list_item as *const () as usize),
+        (codegen::LIST_FIND, helpers::scratch_list_find as *const () as usize),
+        (codegen::LIST_CONTAINS, helpers::scratch_list_contains as *const () as usize),
+        (codegen::LIST_SET, helpers::scratch_list_set as *const () as usize),
+        (codegen::LIST_INSERT, helpers::scratch_list_insert as *const () as usize),
+        (codegen::LIST_DELETE, helpers::scratch_list_delete as *const () as usize),
+        (codegen::LIST_PUSH, helpers::scratch_list_push as *const () as usize),
+        (codegen::LIST_CLEAR, helpers::scratch_list_clear as *const () as usize),
+        (codegen::LIBM[0], asin as *const () as usize),
+        (codegen::LIBM[1], acos as *const () as usize),
+        (codegen::LIBM[2], atan as *const () as usize),
+    ]
+}
+
+fn name(value: FunctionValue) -> String {
+    value.get_name().to_string_lossy().into_owned()
+}
+
+// the compiled custom blocks and scripts, callable with argument values and with readable globals
+pub fn jit<'a>(program: &Program<'a>, compiled: Compiled<'a>) -> Result<Native<'a>, LLVMString> {
+    let engine = compiled.module.create_jit_execution_engine(OptimizationLevel::Default)?;
+    for (name, address) in helpers() {
+        if let Some(function) = compiled.module.get_function(name) {
+            engine.add_global_mapping(&function, address);
+        }
+    }
+    // every function was compiled, so looking them up can't fail
+    let functions = compiled
+        .functions
+        .iter()
+        .map(|function| {
+            let entry = unsafe { engine.get_function::<Entry>(&name(function.entry)).unwrap().into_raw() };
+            (function.function, entry)
+        })
+        .collect();
+    let scripts = compiled
+        .scripts
+        .iter()
+        .map(|&(script, value)| {
+            let entry = unsafe { engine.get_function::<ScriptEntry>(&name(value)).unwrap().into_raw() };
+            (script, entry)
+        })
+        .collect();
+    let coroutines = compiled
+        .coroutines
+        .iter()
+        .map(|&(script, value)| {
+            let entry = unsafe { engine.get_function::<CoroutineEntry>(&name(value)).unwrap().into_raw() };
+            (script, entry)
+        })
+        .collect();
+    let owner: Box<(Module, ExecutionEngine)> = Box::new((compiled.module, engine));
+    Ok(Native::new(program, functions, scripts, coroutines).owned_by(owner))
+}
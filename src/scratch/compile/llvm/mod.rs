@@ -1,3 +1,7 @@
 use inkwell::values;
 pub use values::AnyValue as Value;
 pub use values::AnyValueEnum as ValueEnum;
+
+pub mod codegen;
+pub mod jit;
+pub mod aot;
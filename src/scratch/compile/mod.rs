@@ -1 +1 @@
-mod llvm;
+pub mod llvm;
@@ -1,3 +1,5 @@
 pub mod ast;
+#[cfg(feature = "llvm")]
 pub mod compile;
 pub mod parse;
+pub mod runtime;
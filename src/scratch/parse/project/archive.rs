@@ -1,11 +1,51 @@
 use super::path::ProjectPath;
+use super::sb2;
+use super::sb3;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use zip::{
     result::{ZipError, ZipResult},
     ZipArchive,
 };
 
+#[derive(Debug)]
+pub enum Error {
+    Zip(ZipError),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+    IsSprite,
+    IsProject,
+}
+
+impl From<ZipError> for Error {
+    fn from(e: ZipError) -> Self {
+        Error::Zip(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// a project.json, which is converted to the sb3 format if it's from an sb2
+pub fn parse_project(json: &[u8], version: u32) -> Result<sb3::Project> {
+    let project = match version {
+        2 => serde_json::from_slice::<sb2::Object>(json)?.into_project::<serde_json::Error>()?,
+        _ => serde_json::from_slice(json)?,
+    };
+    Ok(project)
+}
+
 pub struct ProjectArchive {
     path: ProjectPath,
     archive: ZipArchive<BufReader<File>>,
@@ -36,6 +76,78 @@ impl ProjectArchive {
         Ok(archive)
     }
 
+    pub fn path(&self) -> &ProjectPath {
+        &self.path
+    }
+
+    pub fn is_sprite(&self) -> bool {
+        self.is_sprite
+    }
+
+    pub fn load_project(&mut self) -> Result<sb3::Project> {
+        let json = self.read_project_json()?;
+        parse_project(&json, self.path.version)
+    }
+
+    // the project.json as is, for parse_project
+    pub fn read_project_json(&mut self) -> Result<Vec<u8>> {
+        if self.is_sprite {
+            return Err(Error::IsSprite);
+        }
+        let mut file = self.archive.by_name("project.json")?;
+        let mut json = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut json)?;
+        Ok(json)
+    }
+
+    // with its costumes and sounds, which only its archive has, for Project::add_sprite
+    pub fn load_sprite(&mut self) -> Result<(sb3::Sprite, sb3::Assets)> {
+        if !self.is_sprite {
+            return Err(Error::IsProject);
+        }
+        let file = BufReader::new(self.archive.by_name("sprite.json")?);
+        let mut sprite: sb3::Sprite = match self.path.version {
+            2 => serde_json::from_reader::<_, sb2::Object>(file)?.into_lone_sprite::<serde_json::Error>()?,
+            _ => serde_json::from_reader(file)?,
+        };
+        let mut assets = sb3::Assets::new();
+        for costume in &mut sprite.target.costumes {
+            self.take_asset(&mut costume.asset, &mut assets)?;
+        }
+        for sound in &mut sprite.target.sounds {
+            self.take_asset(&mut sound.asset, &mut assets)?;
+        }
+        Ok((sprite, assets))
+    }
+
+    // moves the asset's data into assets, under its md5 like in an sb3 so it can't clash with another's,
+    // since a sprite2 has them under their index. ones that are missing are left missing
+    fn take_asset<F>(&mut self, asset: &mut sb3::Asset<F>, assets: &mut sb3::Assets) -> Result<()> {
+        let bytes = match self.read_entry(&asset.md5ext) {
+            Err(Error::Zip(ZipError::FileNotFound)) => return Ok(()),
+            bytes => bytes?,
+        };
+        let extension = asset.md5ext.rsplit('.').next().unwrap_or_default();
+        asset.md5ext = format!("{}.{}", asset.asset_id.0, extension);
+        assets.insert(asset.md5ext.clone(), bytes);
+        Ok(())
+    }
+
+    // from the project's assets if it came with a sprite added to it, or else from the archive
+    pub fn read_asset<F>(&mut self, assets: &sb3::Assets, asset: &sb3::Asset<F>) -> Result<Vec<u8>> {
+        match assets.get(&asset.md5ext) {
+            Some(bytes) => Ok(bytes.clone()),
+            None => self.read_entry(&asset.md5ext),
+        }
+    }
+
+    fn read_entry(&mut self, name: &str) -> Result<Vec<u8>> {
+        let mut file = self.archive.by_name(name)?;
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
     pub fn print_entries(&mut self) {
         for i in 0..self.archive.len() {
             let file = self.archive.by_index(i).unwrap();
@@ -0,0 +1,290 @@
+// resolves the block IDs in a target's blocks (next, parent, and inputs) into references,
+// with all the linked blocks allocated in a bump arena
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+
+use super::sb3::{Block, BlockId, Input, InputValue, Primitive, Target};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    DanglingNext { block: BlockId, next: BlockId },
+    DanglingParent { block: BlockId, parent: BlockId },
+    DanglingInput { block: BlockId, input: String, target: BlockId },
+    // the blocks in the cycle, in order
+    Cycle { blocks: Vec<BlockId> },
+}
+
+#[derive(Clone, Copy)]
+pub enum InputLink<'a> {
+    Block(&'a LinkedBlock<'a>),
+    Primitive(&'a Primitive),
+    // SUBSTACK and SUBSTACK2 inputs, resolved into their whole stack of blocks
+    Stack(&'a [&'a LinkedBlock<'a>]),
+}
+
+#[derive(Clone, Copy)]
+pub struct LinkedInput<'a> {
+    pub name: &'a str,
+    pub input: &'a Input,
+    pub value: Option<InputLink<'a>>,
+    pub obscured: Option<InputLink<'a>>,
+}
+
+pub struct LinkedBlock<'a> {
+    pub id: &'a str,
+    pub block: &'a Block,
+    next: Cell<Option<&'a LinkedBlock<'a>>>,
+    parent: Cell<Option<&'a LinkedBlock<'a>>>,
+    inputs: Cell<&'a [LinkedInput<'a>]>,
+}
+
+impl<'a> LinkedBlock<'a> {
+    fn new(id: &'a str, block: &'a Block) -> LinkedBlock<'a> {
+        LinkedBlock {
+            id,
+            block,
+            next: Cell::new(None),
+            parent: Cell::new(None),
+            inputs: Cell::new(&[]),
+        }
+    }
+
+    pub fn next(&self) -> Option<&'a LinkedBlock<'a>> {
+        self.next.get()
+    }
+
+    pub fn parent(&self) -> Option<&'a LinkedBlock<'a>> {
+        self.parent.get()
+    }
+
+    // sorted by name
+    pub fn inputs(&self) -> &'a [LinkedInput<'a>] {
+        self.inputs.get()
+    }
+
+    pub fn input(&self, name: &str) -> Option<&'a LinkedInput<'a>> {
+        self.inputs().iter().find(|input| input.name == name)
+    }
+
+    // the input's value, or the shadow it's obscuring if it has none
+    pub fn input_value(&self, name: &str) -> Option<InputLink<'a>> {
+        self.input(name).and_then(|input| input.value.or(input.obscured))
+    }
+
+    // an empty substack is also represented as an empty slice
+    pub fn substack(&self, name: &str) -> &'a [&'a LinkedBlock<'a>] {
+        match self.input(name).and_then(|input| input.value) {
+            Some(InputLink::Stack(stack)) => stack,
+            _ => &[],
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&'a str> {
+        self.block.fields.get(name).map(|field| field.value.as_str())
+    }
+
+    // the blocks from this one on, following next
+    pub fn stack(&'a self) -> impl Iterator<Item = &'a LinkedBlock<'a>> {
+        std::iter::successors(Some(self), |block| block.next())
+    }
+}
+
+pub struct LinkedTarget<'a> {
+    pub target: &'a Target,
+    blocks: HashMap<&'a str, &'a LinkedBlock<'a>>,
+    scripts: Vec<&'a [&'a LinkedBlock<'a>]>,
+}
+
+impl<'a> LinkedTarget<'a> {
+    pub fn block(&self, id: &str) -> Option<&'a LinkedBlock<'a>> {
+        self.blocks.get(id).copied()
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &'a LinkedBlock<'a>> + '_ {
+        self.blocks.values().copied()
+    }
+
+    // the top level stacks, ordered by their position in the workspace
+    pub fn scripts(&self) -> impl Iterator<Item = &'a [&'a LinkedBlock<'a>]> + '_ {
+        self.scripts.iter().copied()
+    }
+}
+
+fn input_block_ids(block: &Block) -> impl Iterator<Item = (&str, &str)> {
+    block.inputs.iter().flat_map(|(name, input)| {
+        input
+            .value
+            .iter()
+            .chain(input.obscured.iter())
+            .filter_map(move |value| match value {
+                InputValue::Block(id) => Some((name.as_str(), id.as_str())),
+                InputValue::Primitive(_) => None,
+            })
+    })
+}
+
+fn children(block: &Block) -> impl Iterator<Item = &str> {
+    block
+        .next
+        .iter()
+        .map(String::as_str)
+        .chain(input_block_ids(block).map(|(_, id)| id))
+}
+
+fn dangling(target: &Target) -> Vec<Error> {
+    let mut errors = Vec::new();
+    let exists = |id: &String| target.blocks.contains_key(id);
+    let mut ids = target.blocks.keys().collect::<Vec<_>>();
+    ids.sort();
+    for id in ids {
+        let block = &target.blocks[id];
+        if let Some(next) = block.next.as_ref().filter(|next| !exists(next)) {
+            errors.push(Error::DanglingNext {
+                block: id.clone(),
+                next: next.clone(),
+            });
+        }
+        if let Some(parent) = block.parent.as_ref().filter(|parent| !exists(parent)) {
+            errors.push(Error::DanglingParent {
+                block: id.clone(),
+                parent: parent.clone(),
+            });
+        }
+        for (input, child) in input_block_ids(block) {
+            if !target.blocks.contains_key(child) {
+                errors.push(Error::DanglingInput {
+                    block: id.clone(),
+                    input: input.to_owned(),
+                    target: child.to_owned(),
+                });
+            }
+        }
+    }
+    errors
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+// a depth first search over next and input edges, which should form a forest
+fn cycles(target: &Target) -> Vec<Error> {
+    let mut errors = Vec::new();
+    let mut visits = HashMap::<&str, Visit>::new();
+    let mut ids = target.blocks.keys().map(String::as_str).collect::<Vec<_>>();
+    ids.sort();
+    for start in ids {
+        if visits.contains_key(start) {
+            continue;
+        }
+        let mut path = vec![(start, children(&target.blocks[start]))];
+        visits.insert(start, Visit::InProgress);
+        while let Some((id, edges)) = path.last_mut() {
+            let id: &str = id;
+            match edges.next() {
+                Some(child) if !target.blocks.contains_key(child) => {}
+                Some(child) => match visits.get(child) {
+                    None => {
+                        visits.insert(child, Visit::InProgress);
+                        path.push((child, children(&target.blocks[child])));
+                    }
+                    Some(Visit::InProgress) => {
+                        let start = path.iter().position(|(id, _)| *id == child).unwrap();
+                        let blocks = path[start..].iter().map(|(id, _)| (*id).to_owned()).collect();
+                        errors.push(Error::Cycle { blocks });
+                    }
+                    Some(Visit::Done) => {}
+                },
+                None => {
+                    visits.insert(id, Visit::Done);
+                    path.pop();
+                }
+            }
+        }
+    }
+    errors
+}
+
+struct Linker<'a> {
+    bump: &'a Bump,
+    target: &'a Target,
+    blocks: HashMap<&'a str, &'a LinkedBlock<'a>>,
+}
+
+impl<'a> Linker<'a> {
+    fn stack(&self, first: &'a str) -> &'a [&'a LinkedBlock<'a>] {
+        let ids = std::iter::successors(Some(first), |id| self.target.blocks[*id].next.as_deref());
+        BumpVec::from_iter_in(ids.map(|id| self.blocks[id]), self.bump).into_bump_slice()
+    }
+
+    fn link(&self, name: &str, value: &'a Option<InputValue>) -> Option<InputLink<'a>> {
+        value.as_ref().map(|value| match value {
+            InputValue::Block(id) if name.starts_with("SUBSTACK") => InputLink::Stack(self.stack(id)),
+            InputValue::Block(id) => InputLink::Block(self.blocks[id.as_str()]),
+            InputValue::Primitive(primitive) => InputLink::Primitive(primitive),
+        })
+    }
+
+    fn link_block(&self, linked: &'a LinkedBlock<'a>) {
+        let block = linked.block;
+        let lookup = |id: &'a Option<BlockId>| id.as_ref().map(|id| self.blocks[id.as_str()]);
+        linked.next.set(lookup(&block.next));
+        linked.parent.set(lookup(&block.parent));
+        let mut inputs = BumpVec::from_iter_in(
+            block.inputs.iter().map(|(name, input)| LinkedInput {
+                name,
+                input,
+                value: self.link(name, &input.value),
+                obscured: self.link(name, &input.obscured),
+            }),
+            self.bump,
+        );
+        inputs.sort_by_key(|input| input.name);
+        linked.inputs.set(inputs.into_bump_slice());
+    }
+}
+
+pub fn link<'a>(bump: &'a Bump, target: &'a Target) -> Result<LinkedTarget<'a>, Vec<Error>> {
+    let mut errors = dangling(target);
+    errors.extend(cycles(target));
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let blocks = target
+        .blocks
+        .iter()
+        .map(|(id, block)| {
+            let linked: &'a LinkedBlock<'a> = bump.alloc(LinkedBlock::new(id, block));
+            (id.as_str(), linked)
+        })
+        .collect();
+    let linker = Linker { bump, target, blocks };
+    for linked in linker.blocks.values() {
+        linker.link_block(linked);
+    }
+
+    let mut tops = linker
+        .blocks
+        .values()
+        .filter(|linked| linked.block.top_level && linked.block.parent.is_none())
+        .collect::<Vec<_>>();
+    tops.sort_by(|a, b| {
+        let (a_position, b_position) = (&a.block.position, &b.block.position);
+        (a_position.1, a_position.0, a.id)
+            .partial_cmp(&(b_position.1, b_position.0, b.id))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let scripts = tops.into_iter().map(|top| linker.stack(top.id)).collect();
+    Ok(LinkedTarget {
+        target,
+        blocks: linker.blocks,
+        scripts,
+    })
+}
@@ -0,0 +1,205 @@
+// merging sprites loaded from a sprite3 into an existing project,
+// renaming anything whose name or ID is already taken like the Scratch editor does when uploading a sprite
+
+use std::collections::{HashMap, HashSet};
+
+use super::sb3::{Assets, Field, InputValue, Primitive, Project, Sprite, Target, Value, VariablePrimitive};
+
+// Scratch's unusedName: strip any trailing digits and count up from 2
+fn unused_name(name: &str, used: &HashSet<String>) -> String {
+    if !used.contains(name) {
+        return name.to_owned();
+    }
+    let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    (2..)
+        .map(|i| format!("{}{}", base, i))
+        .find(|name| !used.contains(name))
+        .unwrap()
+}
+
+fn unused_id(id: &str, used: &HashSet<String>) -> String {
+    (1..)
+        .map(|i| format!("{}-{}", id, i))
+        .find(|id| !used.contains(id))
+        .unwrap()
+}
+
+fn target_ids(target: &Target) -> impl Iterator<Item = &String> {
+    target
+        .blocks
+        .keys()
+        .chain(target.top_level_primitives.keys())
+        .chain(target.comments.keys())
+        .chain(target.variables.iter().map(|variable| &variable.id))
+}
+
+fn same_kind(a: &Value, b: &Value) -> bool {
+    matches!(
+        (a, b),
+        (Value::Scalar(_), Value::Scalar(_))
+            | (Value::List(_), Value::List(_))
+            | (Value::Broadcast(_), Value::Broadcast(_))
+    )
+}
+
+// renamed IDs and the variable names that go with them,
+// which are duplicated in fields and primitives
+#[derive(Default)]
+struct Renames {
+    ids: HashMap<String, String>,
+    names: HashMap<String, String>,
+}
+
+impl Renames {
+    fn id(&self, id: &mut String) {
+        if let Some(new) = self.ids.get(id) {
+            *id = new.clone();
+        }
+    }
+
+    fn variable(&self, id: &mut String, name: &mut String) {
+        if let Some(new) = self.names.get(id) {
+            *name = new.clone();
+        }
+        self.id(id);
+    }
+
+    fn field(&self, field: &mut Field) {
+        if let Some(id) = &mut field.id {
+            if let Some(new) = self.names.get(id) {
+                field.value = new.clone();
+            }
+            self.id(id);
+        }
+    }
+
+    fn primitive(&self, primitive: &mut VariablePrimitive) {
+        self.variable(&mut primitive.id, &mut primitive.name);
+    }
+
+    fn input_value(&self, value: &mut InputValue) {
+        match value {
+            InputValue::Block(id) => self.id(id),
+            InputValue::Primitive(Primitive::Variable(primitive)) => self.primitive(primitive),
+            InputValue::Primitive(_) => {}
+        }
+    }
+
+    fn target(&self, target: &mut Target) {
+        let rename_keys = |keys: Vec<String>| -> Vec<String> {
+            keys.into_iter()
+                .map(|mut id| {
+                    self.id(&mut id);
+                    id
+                })
+                .collect()
+        };
+
+        let (ids, blocks): (Vec<_>, Vec<_>) = target.blocks.drain().unzip();
+        target.blocks = rename_keys(ids).into_iter().zip(blocks).collect();
+        for block in target.blocks.values_mut() {
+            block.next.iter_mut().for_each(|id| self.id(id));
+            block.parent.iter_mut().for_each(|id| self.id(id));
+            block.comment.iter_mut().for_each(|id| self.id(id));
+            for input in block.inputs.values_mut() {
+                input.value.iter_mut().for_each(|value| self.input_value(value));
+                input.obscured.iter_mut().for_each(|value| self.input_value(value));
+            }
+            block.fields.values_mut().for_each(|field| self.field(field));
+        }
+
+        let (ids, primitives): (Vec<_>, Vec<_>) = target.top_level_primitives.drain().unzip();
+        target.top_level_primitives = rename_keys(ids).into_iter().zip(primitives).collect();
+        target
+            .top_level_primitives
+            .values_mut()
+            .for_each(|primitive| self.primitive(primitive));
+
+        let (ids, comments): (Vec<_>, Vec<_>) = target.comments.drain().unzip();
+        target.comments = rename_keys(ids).into_iter().zip(comments).collect();
+        for comment in target.comments.values_mut() {
+            comment.block.iter_mut().for_each(|id| self.id(id));
+        }
+
+        for variable in &mut target.variables {
+            self.variable(&mut variable.id, &mut variable.name);
+        }
+    }
+}
+
+impl Project {
+    pub fn targets(&self) -> impl Iterator<Item = &Target> {
+        let targets = &self.targets;
+        std::iter::once(&targets.stage.target).chain(targets.sprites.iter().map(|sprite| &sprite.target))
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<&Sprite> {
+        self.targets.sprites.iter().find(|sprite| sprite.name == name)
+    }
+
+    // returns the sprite as added, which may have been renamed, where assets are its costumes and sounds
+    // from ProjectArchive::load_sprite
+    pub fn add_sprite(&mut self, mut sprite: Sprite, assets: Assets) -> &Sprite {
+        let names = self
+            .targets
+            .sprites
+            .iter()
+            .map(|sprite| sprite.name.clone())
+            .chain(std::iter::once("Stage".to_owned()))
+            .collect::<HashSet<_>>();
+        sprite.name = unused_name(&sprite.name, &names);
+
+        let project_ids = self.targets().flat_map(target_ids).cloned().collect::<HashSet<_>>();
+        let mut used_ids = project_ids.iter().chain(target_ids(&sprite.target)).cloned().collect();
+        let mut renames = Renames::default();
+        for id in target_ids(&sprite.target) {
+            if project_ids.contains(id) {
+                let new = unused_id(id, &used_ids);
+                used_ids.insert(new.clone());
+                renames.ids.insert(id.clone(), new);
+            }
+        }
+
+        // broadcasts are global, so they're either the stage's broadcast with the same name or moved to the stage.
+        // local variables with the same name as a global are renamed, since Scratch looks them up by name.
+        let stage = &mut self.targets.stage.target;
+        let mut locals = Vec::with_capacity(sprite.target.variables.len());
+        for mut variable in sprite.target.variables.drain(..) {
+            let global = stage
+                .variables
+                .iter()
+                .find(|global| global.name == variable.name && same_kind(&global.value, &variable.value));
+            match (&variable.value, global) {
+                (Value::Broadcast(_), Some(global)) => {
+                    renames.ids.insert(variable.id, global.id.clone());
+                }
+                (Value::Broadcast(_), None) => {
+                    let id = renames.ids.get(&variable.id).cloned().unwrap_or(variable.id);
+                    variable.id = id;
+                    stage.variables.push(variable);
+                }
+                (_, Some(_)) => {
+                    let name = format!("{}: {}", sprite.name, variable.name);
+                    renames.names.insert(variable.id.clone(), name);
+                    locals.push(variable);
+                }
+                (_, None) => locals.push(variable),
+            }
+        }
+        sprite.target.variables = locals;
+        renames.target(&mut sprite.target);
+
+        sprite.layer_order = self
+            .targets
+            .sprites
+            .iter()
+            .map(|sprite| sprite.layer_order)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        // they're by md5, so any that are already there are the same
+        self.assets.extend(assets);
+        self.targets.sprites.push(sprite);
+        self.targets.sprites.last().unwrap()
+    }
+}
@@ -1,3 +1,8 @@
 pub mod path;
 pub mod archive;
+pub mod sb2;
 pub mod sb3;
+mod sb3_json;
+pub mod link;
+pub mod merge;
+pub mod opcode;
@@ -0,0 +1,355 @@
+// the standard Scratch 3.0 opcodes, by category, with the names of the inputs and fields each one expects
+// shadow menu blocks (like motion_goto_menu) are included since they're blocks in project.json too
+
+use serde::{Deserialize, Deserializer};
+
+use super::sb3::{BlockCategory, CategoryOpCode, OpCode};
+
+macro_rules! op_codes {
+    ($name:ident {
+        $($variant:ident = $op_code:literal [$($input:literal),*] [$($field:literal),*],)*
+    }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant,)*];
+
+            pub fn from_op_code(op_code: &str) -> Option<$name> {
+                match op_code {
+                    $($op_code => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$variant => $op_code,)*
+                }
+            }
+
+            pub fn inputs(self) -> &'static [&'static str] {
+                match self {
+                    $($name::$variant => &[$($input),*],)*
+                }
+            }
+
+            pub fn fields(self) -> &'static [&'static str] {
+                match self {
+                    $($name::$variant => &[$($field),*],)*
+                }
+            }
+        }
+    };
+}
+
+op_codes!(MotionOpCode {
+    MoveSteps = "motion_movesteps" ["STEPS"] [],
+    TurnRight = "motion_turnright" ["DEGREES"] [],
+    TurnLeft = "motion_turnleft" ["DEGREES"] [],
+    GoTo = "motion_goto" ["TO"] [],
+    GoToMenu = "motion_goto_menu" [] ["TO"],
+    GoToXY = "motion_gotoxy" ["X", "Y"] [],
+    GlideTo = "motion_glideto" ["SECS", "TO"] [],
+    GlideToMenu = "motion_glideto_menu" [] ["TO"],
+    GlideSecsToXY = "motion_glidesecstoxy" ["SECS", "X", "Y"] [],
+    PointInDirection = "motion_pointindirection" ["DIRECTION"] [],
+    PointTowards = "motion_pointtowards" ["TOWARDS"] [],
+    PointTowardsMenu = "motion_pointtowards_menu" [] ["TOWARDS"],
+    ChangeXBy = "motion_changexby" ["DX"] [],
+    SetX = "motion_setx" ["X"] [],
+    ChangeYBy = "motion_changeyby" ["DY"] [],
+    SetY = "motion_sety" ["Y"] [],
+    IfOnEdgeBounce = "motion_ifonedgebounce" [] [],
+    SetRotationStyle = "motion_setrotationstyle" [] ["STYLE"],
+    XPosition = "motion_xposition" [] [],
+    YPosition = "motion_yposition" [] [],
+    Direction = "motion_direction" [] [],
+    ScrollRight = "motion_scroll_right" ["DISTANCE"] [],
+    ScrollUp = "motion_scroll_up" ["DISTANCE"] [],
+    AlignScene = "motion_align_scene" [] ["ALIGNMENT"],
+    XScroll = "motion_xscroll" [] [],
+    YScroll = "motion_yscroll" [] [],
+});
+
+op_codes!(LooksOpCode {
+    SayForSecs = "looks_sayforsecs" ["MESSAGE", "SECS"] [],
+    Say = "looks_say" ["MESSAGE"] [],
+    ThinkForSecs = "looks_thinkforsecs" ["MESSAGE", "SECS"] [],
+    Think = "looks_think" ["MESSAGE"] [],
+    SwitchCostumeTo = "looks_switchcostumeto" ["COSTUME"] [],
+    Costume = "looks_costume" [] ["COSTUME"],
+    NextCostume = "looks_nextcostume" [] [],
+    SwitchBackdropTo = "looks_switchbackdropto" ["BACKDROP"] [],
+    Backdrops = "looks_backdrops" [] ["BACKDROP"],
+    SwitchBackdropToAndWait = "looks_switchbackdroptoandwait" ["BACKDROP"] [],
+    NextBackdrop = "looks_nextbackdrop" [] [],
+    ChangeSizeBy = "looks_changesizeby" ["CHANGE"] [],
+    SetSizeTo = "looks_setsizeto" ["SIZE"] [],
+    ChangeEffectBy = "looks_changeeffectby" ["CHANGE"] ["EFFECT"],
+    SetEffectTo = "looks_seteffectto" ["VALUE"] ["EFFECT"],
+    ClearGraphicEffects = "looks_cleargraphiceffects" [] [],
+    Show = "looks_show" [] [],
+    Hide = "looks_hide" [] [],
+    GoToFrontBack = "looks_gotofrontback" [] ["FRONT_BACK"],
+    GoForwardBackwardLayers = "looks_goforwardbackwardlayers" ["NUM"] ["FORWARD_BACKWARD"],
+    CostumeNumberName = "looks_costumenumbername" [] ["NUMBER_NAME"],
+    BackdropNumberName = "looks_backdropnumbername" [] ["NUMBER_NAME"],
+    Size = "looks_size" [] [],
+    HideAllSprites = "looks_hideallsprites" [] [],
+    ChangeStretchBy = "looks_changestretchby" ["CHANGE"] [],
+    SetStretchTo = "looks_setstretchto" ["STRETCH"] [],
+});
+
+op_codes!(SoundOpCode {
+    PlayUntilDone = "sound_playuntildone" ["SOUND_MENU"] [],
+    Play = "sound_play" ["SOUND_MENU"] [],
+    SoundsMenu = "sound_sounds_menu" [] ["SOUND_MENU"],
+    StopAllSounds = "sound_stopallsounds" [] [],
+    ChangeEffectBy = "sound_changeeffectby" ["VALUE"] ["EFFECT"],
+    SetEffectTo = "sound_seteffectto" ["VALUE"] ["EFFECT"],
+    ClearEffects = "sound_cleareffects" [] [],
+    ChangeVolumeBy = "sound_changevolumeby" ["VOLUME"] [],
+    SetVolumeTo = "sound_setvolumeto" ["VOLUME"] [],
+    Volume = "sound_volume" [] [],
+});
+
+op_codes!(EventOpCode {
+    WhenFlagClicked = "event_whenflagclicked" [] [],
+    WhenKeyPressed = "event_whenkeypressed" [] ["KEY_OPTION"],
+    WhenThisSpriteClicked = "event_whenthisspriteclicked" [] [],
+    WhenStageClicked = "event_whenstageclicked" [] [],
+    WhenBackdropSwitchesTo = "event_whenbackdropswitchesto" [] ["BACKDROP"],
+    WhenGreaterThan = "event_whengreaterthan" ["VALUE"] ["WHENGREATERTHANMENU"],
+    WhenBroadcastReceived = "event_whenbroadcastreceived" [] ["BROADCAST_OPTION"],
+    WhenTouchingObject = "event_whentouchingobject" ["TOUCHINGOBJECTMENU"] [],
+    TouchingObjectMenu = "event_touchingobjectmenu" [] ["TOUCHINGOBJECTMENU"],
+    Broadcast = "event_broadcast" ["BROADCAST_INPUT"] [],
+    BroadcastAndWait = "event_broadcastandwait" ["BROADCAST_INPUT"] [],
+    BroadcastMenu = "event_broadcast_menu" [] ["BROADCAST_OPTION"],
+});
+
+op_codes!(ControlOpCode {
+    Wait = "control_wait" ["DURATION"] [],
+    Repeat = "control_repeat" ["TIMES", "SUBSTACK"] [],
+    Forever = "control_forever" ["SUBSTACK"] [],
+    If = "control_if" ["CONDITION", "SUBSTACK"] [],
+    IfElse = "control_if_else" ["CONDITION", "SUBSTACK", "SUBSTACK2"] [],
+    WaitUntil = "control_wait_until" ["CONDITION"] [],
+    RepeatUntil = "control_repeat_until" ["CONDITION", "SUBSTACK"] [],
+    While = "control_while" ["CONDITION", "SUBSTACK"] [],
+    ForEach = "control_for_each" ["VALUE", "SUBSTACK"] ["VARIABLE"],
+    Stop = "control_stop" [] ["STOP_OPTION"],
+    StartAsClone = "control_start_as_clone" [] [],
+    CreateCloneOf = "control_create_clone_of" ["CLONE_OPTION"] [],
+    CreateCloneOfMenu = "control_create_clone_of_menu" [] ["CLONE_OPTION"],
+    DeleteThisClone = "control_delete_this_clone" [] [],
+    GetCounter = "control_get_counter" [] [],
+    IncrCounter = "control_incr_counter" [] [],
+    ClearCounter = "control_clear_counter" [] [],
+    AllAtOnce = "control_all_at_once" ["SUBSTACK"] [],
+});
+
+op_codes!(SensingOpCode {
+    TouchingObject = "sensing_touchingobject" ["TOUCHINGOBJECTMENU"] [],
+    TouchingObjectMenu = "sensing_touchingobjectmenu" [] ["TOUCHINGOBJECTMENU"],
+    TouchingColor = "sensing_touchingcolor" ["COLOR"] [],
+    ColorIsTouchingColor = "sensing_coloristouchingcolor" ["COLOR", "COLOR2"] [],
+    DistanceTo = "sensing_distanceto" ["DISTANCETOMENU"] [],
+    DistanceToMenu = "sensing_distancetomenu" [] ["DISTANCETOMENU"],
+    AskAndWait = "sensing_askandwait" ["QUESTION"] [],
+    Answer = "sensing_answer" [] [],
+    KeyPressed = "sensing_keypressed" ["KEY_OPTION"] [],
+    KeyOptions = "sensing_keyoptions" [] ["KEY_OPTION"],
+    MouseDown = "sensing_mousedown" [] [],
+    MouseX = "sensing_mousex" [] [],
+    MouseY = "sensing_mousey" [] [],
+    SetDragMode = "sensing_setdragmode" [] ["DRAG_MODE"],
+    Loudness = "sensing_loudness" [] [],
+    Loud = "sensing_loud" [] [],
+    Timer = "sensing_timer" [] [],
+    ResetTimer = "sensing_resettimer" [] [],
+    Of = "sensing_of" ["OBJECT"] ["PROPERTY"],
+    OfObjectMenu = "sensing_of_object_menu" [] ["OBJECT"],
+    Current = "sensing_current" [] ["CURRENTMENU"],
+    DaysSince2000 = "sensing_dayssince2000" [] [],
+    UserName = "sensing_username" [] [],
+    UserId = "sensing_userid" [] [],
+});
+
+op_codes!(OperatorOpCode {
+    Add = "operator_add" ["NUM1", "NUM2"] [],
+    Subtract = "operator_subtract" ["NUM1", "NUM2"] [],
+    Multiply = "operator_multiply" ["NUM1", "NUM2"] [],
+    Divide = "operator_divide" ["NUM1", "NUM2"] [],
+    Random = "operator_random" ["FROM", "TO"] [],
+    GreaterThan = "operator_gt" ["OPERAND1", "OPERAND2"] [],
+    LessThan = "operator_lt" ["OPERAND1", "OPERAND2"] [],
+    Equals = "operator_equals" ["OPERAND1", "OPERAND2"] [],
+    And = "operator_and" ["OPERAND1", "OPERAND2"] [],
+    Or = "operator_or" ["OPERAND1", "OPERAND2"] [],
+    Not = "operator_not" ["OPERAND"] [],
+    Join = "operator_join" ["STRING1", "STRING2"] [],
+    LetterOf = "operator_letter_of" ["LETTER", "STRING"] [],
+    Length = "operator_length" ["STRING"] [],
+    Contains = "operator_contains" ["STRING1", "STRING2"] [],
+    Mod = "operator_mod" ["NUM1", "NUM2"] [],
+    Round = "operator_round" ["NUM"] [],
+    MathOp = "operator_mathop" ["NUM"] ["OPERATOR"],
+});
+
+op_codes!(DataOpCode {
+    Variable = "data_variable" [] ["VARIABLE"],
+    SetVariableTo = "data_setvariableto" ["VALUE"] ["VARIABLE"],
+    ChangeVariableBy = "data_changevariableby" ["VALUE"] ["VARIABLE"],
+    ShowVariable = "data_showvariable" [] ["VARIABLE"],
+    HideVariable = "data_hidevariable" [] ["VARIABLE"],
+    ListContents = "data_listcontents" [] ["LIST"],
+    AddToList = "data_addtolist" ["ITEM"] ["LIST"],
+    DeleteOfList = "data_deleteoflist" ["INDEX"] ["LIST"],
+    DeleteAllOfList = "data_deletealloflist" [] ["LIST"],
+    InsertAtList = "data_insertatlist" ["ITEM", "INDEX"] ["LIST"],
+    ReplaceItemOfList = "data_replaceitemoflist" ["INDEX", "ITEM"] ["LIST"],
+    ItemOfList = "data_itemoflist" ["INDEX"] ["LIST"],
+    ItemNumOfList = "data_itemnumoflist" ["ITEM"] ["LIST"],
+    LengthOfList = "data_lengthoflist" [] ["LIST"],
+    ListContainsItem = "data_listcontainsitem" ["ITEM"] ["LIST"],
+    ShowList = "data_showlist" [] ["LIST"],
+    HideList = "data_hidelist" [] ["LIST"],
+});
+
+// the inputs of prototypes and calls are the custom block's argument IDs, so they're not listed here
+op_codes!(ProcedureOpCode {
+    Definition = "procedures_definition" ["custom_block"] [],
+    Prototype = "procedures_prototype" [] [],
+    Call = "procedures_call" [] [],
+    ArgumentReporterStringNumber = "argument_reporter_string_number" [] ["VALUE"],
+    ArgumentReporterBoolean = "argument_reporter_boolean" [] ["VALUE"],
+});
+
+op_codes!(PenOpCode {
+    Clear = "pen_clear" [] [],
+    Stamp = "pen_stamp" [] [],
+    PenDown = "pen_penDown" [] [],
+    PenUp = "pen_penUp" [] [],
+    SetPenColorToColor = "pen_setPenColorToColor" ["COLOR"] [],
+    ChangePenColorParamBy = "pen_changePenColorParamBy" ["COLOR_PARAM", "VALUE"] [],
+    SetPenColorParamTo = "pen_setPenColorParamTo" ["COLOR_PARAM", "VALUE"] [],
+    ColorParamMenu = "pen_menu_colorParam" [] ["colorParam"],
+    ChangePenSizeBy = "pen_changePenSizeBy" ["SIZE"] [],
+    SetPenSizeTo = "pen_setPenSizeTo" ["SIZE"] [],
+    SetPenShadeToNumber = "pen_setPenShadeToNumber" ["SHADE"] [],
+    ChangePenShadeBy = "pen_changePenShadeBy" ["SHADE"] [],
+    SetPenHueToNumber = "pen_setPenHueToNumber" ["HUE"] [],
+    ChangePenHueBy = "pen_changePenHueBy" ["HUE"] [],
+});
+
+impl BlockCategory {
+    pub fn from_op_code(op_code: &str) -> BlockCategory {
+        let prefix = op_code.split('_').next().unwrap_or("");
+        match prefix {
+            "motion" => BlockCategory::Motion,
+            "looks" => BlockCategory::Look,
+            "sound" => BlockCategory::Sound,
+            "event" => BlockCategory::Event,
+            "control" => BlockCategory::Control,
+            "sensing" => BlockCategory::Sensing,
+            "operator" => BlockCategory::Operator,
+            "data" => BlockCategory::Variable,
+            "procedures" | "argument" => BlockCategory::Block,
+            "pen" => BlockCategory::Pen,
+            _ => BlockCategory::Extension,
+        }
+    }
+}
+
+impl CategoryOpCode {
+    fn parse(category: &BlockCategory, op_code: &str) -> Option<CategoryOpCode> {
+        Some(match category {
+            BlockCategory::Motion => CategoryOpCode::Motion(MotionOpCode::from_op_code(op_code)?),
+            BlockCategory::Look => CategoryOpCode::Look(LooksOpCode::from_op_code(op_code)?),
+            BlockCategory::Sound => CategoryOpCode::Sound(SoundOpCode::from_op_code(op_code)?),
+            BlockCategory::Event => CategoryOpCode::Event(EventOpCode::from_op_code(op_code)?),
+            BlockCategory::Control => CategoryOpCode::Control(ControlOpCode::from_op_code(op_code)?),
+            BlockCategory::Sensing => CategoryOpCode::Sensing(SensingOpCode::from_op_code(op_code)?),
+            BlockCategory::Operator => CategoryOpCode::Operator(OperatorOpCode::from_op_code(op_code)?),
+            BlockCategory::Variable => CategoryOpCode::Variable(DataOpCode::from_op_code(op_code)?),
+            BlockCategory::Block => CategoryOpCode::Block(ProcedureOpCode::from_op_code(op_code)?),
+            BlockCategory::Pen => CategoryOpCode::Pen(PenOpCode::from_op_code(op_code)?),
+            BlockCategory::Extension => return None,
+        })
+    }
+}
+
+impl OpCode {
+    // never fails, since extensions can add any opcode
+    pub fn parse(op_code: &str) -> OpCode {
+        let category = BlockCategory::from_op_code(op_code);
+        let op_code = CategoryOpCode::parse(&category, op_code)
+            .unwrap_or_else(|| CategoryOpCode::Unknown(op_code.to_owned()));
+        OpCode { category, op_code }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.op_code {
+            CategoryOpCode::Motion(op) => op.as_str(),
+            CategoryOpCode::Look(op) => op.as_str(),
+            CategoryOpCode::Sound(op) => op.as_str(),
+            CategoryOpCode::Event(op) => op.as_str(),
+            CategoryOpCode::Control(op) => op.as_str(),
+            CategoryOpCode::Sensing(op) => op.as_str(),
+            CategoryOpCode::Operator(op) => op.as_str(),
+            CategoryOpCode::Variable(op) => op.as_str(),
+            CategoryOpCode::Block(op) => op.as_str(),
+            CategoryOpCode::Pen(op) => op.as_str(),
+            CategoryOpCode::Unknown(op) => op.as_str(),
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        !matches!(self.op_code, CategoryOpCode::Unknown(_))
+    }
+
+    // the expected inputs, or none if unknown
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match &self.op_code {
+            CategoryOpCode::Motion(op) => op.inputs(),
+            CategoryOpCode::Look(op) => op.inputs(),
+            CategoryOpCode::Sound(op) => op.inputs(),
+            CategoryOpCode::Event(op) => op.inputs(),
+            CategoryOpCode::Control(op) => op.inputs(),
+            CategoryOpCode::Sensing(op) => op.inputs(),
+            CategoryOpCode::Operator(op) => op.inputs(),
+            CategoryOpCode::Variable(op) => op.inputs(),
+            CategoryOpCode::Block(op) => op.inputs(),
+            CategoryOpCode::Pen(op) => op.inputs(),
+            CategoryOpCode::Unknown(_) => &[],
+        }
+    }
+
+    // the expected fields, or none if unknown
+    pub fn fields(&self) -> &'static [&'static str] {
+        match &self.op_code {
+            CategoryOpCode::Motion(op) => op.fields(),
+            CategoryOpCode::Look(op) => op.fields(),
+            CategoryOpCode::Sound(op) => op.fields(),
+            CategoryOpCode::Event(op) => op.fields(),
+            CategoryOpCode::Control(op) => op.fields(),
+            CategoryOpCode::Sensing(op) => op.fields(),
+            CategoryOpCode::Operator(op) => op.fields(),
+            CategoryOpCode::Variable(op) => op.fields(),
+            CategoryOpCode::Block(op) => op.fields(),
+            CategoryOpCode::Pen(op) => op.fields(),
+            CategoryOpCode::Unknown(_) => &[],
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OpCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let op_code = String::deserialize(deserializer)?;
+        Ok(OpCode::parse(&op_code))
+    }
+}
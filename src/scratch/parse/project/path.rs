@@ -24,11 +24,13 @@ impl ProjectPath {
     pub fn from_path(path: &Path) -> Result {
         let ext = path.extension().ok_or(Error::NoExtension)?;
         let ext = ext.to_str().ok_or(Error::NotUtf8Extension(ext.to_owned()))?;
-        const EXT_PREFIX: &str = "sb";
-        if !ext.starts_with(EXT_PREFIX) {
-            return Err(Error::NotScratchFileExtension(ext.to_owned()));
-        }
-        let version: &str = &ext[EXT_PREFIX.len()..];
+        // .sb2/.sb3 for projects, .sprite2/.sprite3 for sprites
+        const EXT_PREFIXES: [&str; 2] = ["sb", "sprite"];
+        let prefix = EXT_PREFIXES
+            .iter()
+            .find(|prefix| ext.starts_with(*prefix))
+            .ok_or_else(|| Error::NotScratchFileExtension(ext.to_owned()))?;
+        let version: &str = &ext[prefix.len()..];
         Ok(ProjectPath {
             version: version.parse().map_err(|_| Error::InvalidScratchVersion(version.to_owned()))?,
             path: path.to_owned(),
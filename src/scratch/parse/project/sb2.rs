@@ -0,0 +1,981 @@
+// Scratch 2.0 project.json, upgraded to the sb3 model on load
+// scripts are nested arrays, [x, y, [[opcode, args...], ...]], where args are literals,
+// nested reporter blocks, or substacks (arrays of blocks), depending on the opcode.
+// the spec table below maps each sb2 opcode's args to the sb3 opcode's inputs and fields.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::de::Error;
+use serde::Deserialize;
+use serde_json::Value as Json;
+
+use super::sb3;
+use super::sb3::{
+    Asset, AssetId, AudioDataFormat, BlockId, ImageDataFormat, Input, InputValue, NumKind, NumPrimitive,
+    Number, NumberOrString, Primitive, RotationStyle, ScalarValue, SemVer, Shadow, Value, VariablePrimitive,
+    VariableType, Vec2, VideoState,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScalarVariable {
+    name: String,
+    #[serde(default)]
+    value: Option<ScalarValue>,
+    #[serde(default)]
+    is_persistent: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct List {
+    list_name: String,
+    #[serde(default)]
+    contents: Vec<ScalarValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Costume {
+    costume_name: String,
+    base_layer_id: Option<i64>,
+    base_layer_m_d5: String,
+    #[serde(default)]
+    bitmap_resolution: Option<u32>,
+    #[serde(default)]
+    rotation_center_x: f64,
+    #[serde(default)]
+    rotation_center_y: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sound {
+    sound_name: String,
+    #[serde(rename = "soundID")]
+    sound_id: Option<i64>,
+    md5: String,
+    #[serde(default)]
+    sample_count: u32,
+    #[serde(default)]
+    rate: u32,
+}
+
+// shared by the stage and sprites, and also watchers in the stage's children,
+// which are recognized by not having an objName
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct Object {
+    obj_name: Option<String>,
+    #[serde(default)]
+    variables: Vec<ScalarVariable>,
+    #[serde(default)]
+    lists: Vec<List>,
+    #[serde(default)]
+    scripts: Vec<Json>,
+    #[serde(default)]
+    script_comments: Vec<Json>,
+    #[serde(default)]
+    sounds: Vec<Sound>,
+    #[serde(default)]
+    costumes: Vec<Costume>,
+    #[serde(default)]
+    current_costume_index: f64,
+
+    // stage only
+    #[serde(default)]
+    children: Vec<Object>,
+    #[serde(rename = "tempoBPM")]
+    tempo_bpm: Option<f64>,
+    video_alpha: Option<f64>,
+    info: Option<Info>,
+
+    // sprite only
+    scratch_x: Option<f64>,
+    scratch_y: Option<f64>,
+    scale: Option<f64>,
+    direction: Option<f64>,
+    rotation_style: Option<String>,
+    #[serde(default)]
+    is_draggable: bool,
+    visible: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Info {
+    user_agent: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Arg {
+    Num(&'static str, NumKind),
+    Text(&'static str),
+    Color(&'static str),
+    Bool(&'static str),
+    Broadcast(&'static str),
+    // a shadow block of the menu's opcode with a single field
+    Menu(&'static str, &'static str, &'static str),
+    Field(&'static str),
+    // sb3 uses uppercase without spaces for some fields sb2 had as words, like "day of week" => "DAYOFWEEK"
+    UpperField(&'static str),
+    Variable(&'static str, VariableType),
+    Substack(&'static str),
+    // not in sb2, but required in sb3
+    ConstField(&'static str, &'static str),
+    ConstMenu(&'static str, &'static str, &'static str, &'static str),
+}
+
+use Arg::*;
+
+const NUM: NumKind = NumKind::Number;
+const POSITIVE: NumKind = NumKind::PositiveNumber;
+const WHOLE: NumKind = NumKind::WholeNumber;
+const INTEGER: NumKind = NumKind::Integer;
+const ANGLE: NumKind = NumKind::Angle;
+const SCALAR: VariableType = VariableType::Scalar;
+const LIST: VariableType = VariableType::List;
+const BROADCAST: VariableType = VariableType::Broadcast;
+
+// (sb2 opcode, sb3 opcode, args in sb2 order)
+// readVariable, contentsOfList:, procDef, call, and getParam are handled separately
+static SPECS: &[(&str, &str, &[Arg])] = &[
+    // motion
+    ("forward:", "motion_movesteps", &[Num("STEPS", NUM)]),
+    ("turnRight:", "motion_turnright", &[Num("DEGREES", NUM)]),
+    ("turnLeft:", "motion_turnleft", &[Num("DEGREES", NUM)]),
+    ("heading:", "motion_pointindirection", &[Num("DIRECTION", ANGLE)]),
+    ("pointTowards:", "motion_pointtowards", &[Menu("TOWARDS", "motion_pointtowards_menu", "TOWARDS")]),
+    ("gotoX:y:", "motion_gotoxy", &[Num("X", NUM), Num("Y", NUM)]),
+    ("gotoSpriteOrMouse:", "motion_goto", &[Menu("TO", "motion_goto_menu", "TO")]),
+    ("glideSecs:toX:y:elapsed:from:", "motion_glidesecstoxy", &[Num("SECS", NUM), Num("X", NUM), Num("Y", NUM)]),
+    ("changeXposBy:", "motion_changexby", &[Num("DX", NUM)]),
+    ("xpos:", "motion_setx", &[Num("X", NUM)]),
+    ("changeYposBy:", "motion_changeyby", &[Num("DY", NUM)]),
+    ("ypos:", "motion_sety", &[Num("Y", NUM)]),
+    ("bounceOffEdge", "motion_ifonedgebounce", &[]),
+    ("setRotationStyle", "motion_setrotationstyle", &[Field("STYLE")]),
+    ("xpos", "motion_xposition", &[]),
+    ("ypos", "motion_yposition", &[]),
+    ("heading", "motion_direction", &[]),
+    // looks
+    ("say:duration:elapsed:from:", "looks_sayforsecs", &[Text("MESSAGE"), Num("SECS", NUM)]),
+    ("say:", "looks_say", &[Text("MESSAGE")]),
+    ("think:duration:elapsed:from:", "looks_thinkforsecs", &[Text("MESSAGE"), Num("SECS", NUM)]),
+    ("think:", "looks_think", &[Text("MESSAGE")]),
+    ("show", "looks_show", &[]),
+    ("hide", "looks_hide", &[]),
+    ("lookLike:", "looks_switchcostumeto", &[Menu("COSTUME", "looks_costume", "COSTUME")]),
+    ("nextCostume", "looks_nextcostume", &[]),
+    ("startScene", "looks_switchbackdropto", &[Menu("BACKDROP", "looks_backdrops", "BACKDROP")]),
+    ("startSceneAndWait", "looks_switchbackdroptoandwait", &[Menu("BACKDROP", "looks_backdrops", "BACKDROP")]),
+    ("nextScene", "looks_nextbackdrop", &[]),
+    ("changeGraphicEffect:by:", "looks_changeeffectby", &[UpperField("EFFECT"), Num("CHANGE", NUM)]),
+    ("setGraphicEffect:to:", "looks_seteffectto", &[UpperField("EFFECT"), Num("VALUE", NUM)]),
+    ("filterReset", "looks_cleargraphiceffects", &[]),
+    ("changeSizeBy:", "looks_changesizeby", &[Num("CHANGE", NUM)]),
+    ("setSizeTo:", "looks_setsizeto", &[Num("SIZE", NUM)]),
+    ("comeToFront", "looks_gotofrontback", &[ConstField("FRONT_BACK", "front")]),
+    ("goBackByLayers:", "looks_goforwardbackwardlayers", &[ConstField("FORWARD_BACKWARD", "backward"), Num("NUM", INTEGER)]),
+    ("costumeIndex", "looks_costumenumbername", &[ConstField("NUMBER_NAME", "number")]),
+    ("costumeName", "looks_costumenumbername", &[ConstField("NUMBER_NAME", "name")]),
+    ("sceneName", "looks_backdropnumbername", &[ConstField("NUMBER_NAME", "name")]),
+    ("backgroundIndex", "looks_backdropnumbername", &[ConstField("NUMBER_NAME", "number")]),
+    ("scale", "looks_size", &[]),
+    // sound
+    ("playSound:", "sound_play", &[Menu("SOUND_MENU", "sound_sounds_menu", "SOUND_MENU")]),
+    ("doPlaySoundAndWait", "sound_playuntildone", &[Menu("SOUND_MENU", "sound_sounds_menu", "SOUND_MENU")]),
+    ("stopAllSounds", "sound_stopallsounds", &[]),
+    ("changeVolumeBy:", "sound_changevolumeby", &[Num("VOLUME", NUM)]),
+    ("setVolumeTo:", "sound_setvolumeto", &[Num("VOLUME", NUM)]),
+    ("volume", "sound_volume", &[]),
+    ("rest:elapsed:from:", "music_restForBeats", &[Num("BEATS", NUM)]),
+    ("changeTempoBy:", "music_changeTempo", &[Num("TEMPO", NUM)]),
+    ("setTempoTo:", "music_setTempo", &[Num("TEMPO", NUM)]),
+    ("tempo", "music_getTempo", &[]),
+    // pen
+    ("clearPenTrails", "pen_clear", &[]),
+    ("stampCostume", "pen_stamp", &[]),
+    ("putPenDown", "pen_penDown", &[]),
+    ("putPenUp", "pen_penUp", &[]),
+    ("penColor:", "pen_setPenColorToColor", &[Color("COLOR")]),
+    ("changePenHueBy:", "pen_changePenColorParamBy", &[ConstMenu("COLOR_PARAM", "pen_menu_colorParam", "colorParam", "color"), Num("VALUE", NUM)]),
+    ("setPenHueTo:", "pen_setPenColorParamTo", &[ConstMenu("COLOR_PARAM", "pen_menu_colorParam", "colorParam", "color"), Num("VALUE", NUM)]),
+    ("changePenShadeBy:", "pen_changePenShadeBy", &[Num("SHADE", NUM)]),
+    ("setPenShadeTo:", "pen_setPenShadeToNumber", &[Num("SHADE", NUM)]),
+    ("changePenSizeBy:", "pen_changePenSizeBy", &[Num("SIZE", NUM)]),
+    ("penSize:", "pen_setPenSizeTo", &[Num("SIZE", NUM)]),
+    // events
+    ("whenGreenFlag", "event_whenflagclicked", &[]),
+    ("whenKeyPressed", "event_whenkeypressed", &[Field("KEY_OPTION")]),
+    ("whenClicked", "event_whenthisspriteclicked", &[]),
+    ("whenSceneStarts", "event_whenbackdropswitchesto", &[Field("BACKDROP")]),
+    ("whenSensorGreaterThan", "event_whengreaterthan", &[UpperField("WHENGREATERTHANMENU"), Num("VALUE", NUM)]),
+    ("whenIReceive", "event_whenbroadcastreceived", &[Variable("BROADCAST_OPTION", BROADCAST)]),
+    ("broadcast:", "event_broadcast", &[Broadcast("BROADCAST_INPUT")]),
+    ("doBroadcastAndWait", "event_broadcastandwait", &[Broadcast("BROADCAST_INPUT")]),
+    // control
+    ("wait:elapsed:from:", "control_wait", &[Num("DURATION", POSITIVE)]),
+    ("doRepeat", "control_repeat", &[Num("TIMES", WHOLE), Substack("SUBSTACK")]),
+    ("doForever", "control_forever", &[Substack("SUBSTACK")]),
+    ("doIf", "control_if", &[Bool("CONDITION"), Substack("SUBSTACK")]),
+    ("doIfElse", "control_if_else", &[Bool("CONDITION"), Substack("SUBSTACK"), Substack("SUBSTACK2")]),
+    ("doWaitUntil", "control_wait_until", &[Bool("CONDITION")]),
+    ("doUntil", "control_repeat_until", &[Bool("CONDITION"), Substack("SUBSTACK")]),
+    ("stopScripts", "control_stop", &[Field("STOP_OPTION")]),
+    ("whenCloned", "control_start_as_clone", &[]),
+    ("createCloneOf", "control_create_clone_of", &[Menu("CLONE_OPTION", "control_create_clone_of_menu", "CLONE_OPTION")]),
+    ("deleteClone", "control_delete_this_clone", &[]),
+    // sensing
+    ("touching:", "sensing_touchingobject", &[Menu("TOUCHINGOBJECTMENU", "sensing_touchingobjectmenu", "TOUCHINGOBJECTMENU")]),
+    ("touchingColor:", "sensing_touchingcolor", &[Color("COLOR")]),
+    ("color:sees:", "sensing_coloristouchingcolor", &[Color("COLOR"), Color("COLOR2")]),
+    ("distanceTo:", "sensing_distanceto", &[Menu("DISTANCETOMENU", "sensing_distancetomenu", "DISTANCETOMENU")]),
+    ("doAsk", "sensing_askandwait", &[Text("QUESTION")]),
+    ("answer", "sensing_answer", &[]),
+    ("keyPressed:", "sensing_keypressed", &[Menu("KEY_OPTION", "sensing_keyoptions", "KEY_OPTION")]),
+    ("mousePressed", "sensing_mousedown", &[]),
+    ("mouseX", "sensing_mousex", &[]),
+    ("mouseY", "sensing_mousey", &[]),
+    ("soundLevel", "sensing_loudness", &[]),
+    ("timer", "sensing_timer", &[]),
+    ("timerReset", "sensing_resettimer", &[]),
+    ("getAttribute:of:", "sensing_of", &[Field("PROPERTY"), Menu("OBJECT", "sensing_of_object_menu", "OBJECT")]),
+    ("timeAndDate", "sensing_current", &[UpperField("CURRENTMENU")]),
+    ("timestamp", "sensing_dayssince2000", &[]),
+    ("getUserName", "sensing_username", &[]),
+    // operators
+    ("+", "operator_add", &[Num("NUM1", NUM), Num("NUM2", NUM)]),
+    ("-", "operator_subtract", &[Num("NUM1", NUM), Num("NUM2", NUM)]),
+    ("*", "operator_multiply", &[Num("NUM1", NUM), Num("NUM2", NUM)]),
+    ("/", "operator_divide", &[Num("NUM1", NUM), Num("NUM2", NUM)]),
+    ("randomFrom:to:", "operator_random", &[Num("FROM", NUM), Num("TO", NUM)]),
+    ("<", "operator_lt", &[Text("OPERAND1"), Text("OPERAND2")]),
+    ("=", "operator_equals", &[Text("OPERAND1"), Text("OPERAND2")]),
+    (">", "operator_gt", &[Text("OPERAND1"), Text("OPERAND2")]),
+    ("&", "operator_and", &[Bool("OPERAND1"), Bool("OPERAND2")]),
+    ("|", "operator_or", &[Bool("OPERAND1"), Bool("OPERAND2")]),
+    ("not", "operator_not", &[Bool("OPERAND")]),
+    ("concatenate:with:", "operator_join", &[Text("STRING1"), Text("STRING2")]),
+    ("letter:of:", "operator_letter_of", &[Num("LETTER", WHOLE), Text("STRING")]),
+    ("stringLength:", "operator_length", &[Text("STRING")]),
+    ("%", "operator_mod", &[Num("NUM1", NUM), Num("NUM2", NUM)]),
+    ("rounded", "operator_round", &[Num("NUM", NUM)]),
+    ("computeFunction:of:", "operator_mathop", &[Field("OPERATOR"), Num("NUM", NUM)]),
+    // data
+    ("setVar:to:", "data_setvariableto", &[Variable("VARIABLE", SCALAR), Text("VALUE")]),
+    ("changeVar:by:", "data_changevariableby", &[Variable("VARIABLE", SCALAR), Num("VALUE", NUM)]),
+    ("showVariable:", "data_showvariable", &[Variable("VARIABLE", SCALAR)]),
+    ("hideVariable:", "data_hidevariable", &[Variable("VARIABLE", SCALAR)]),
+    ("append:toList:", "data_addtolist", &[Text("ITEM"), Variable("LIST", LIST)]),
+    ("deleteLine:ofList:", "data_deleteoflist", &[Num("INDEX", INTEGER), Variable("LIST", LIST)]),
+    ("insert:at:ofList:", "data_insertatlist", &[Text("ITEM"), Num("INDEX", INTEGER), Variable("LIST", LIST)]),
+    ("setLine:ofList:to:", "data_replaceitemoflist", &[Num("INDEX", INTEGER), Variable("LIST", LIST), Text("ITEM")]),
+    ("getLine:ofList:", "data_itemoflist", &[Num("INDEX", INTEGER), Variable("LIST", LIST)]),
+    ("lineCountOfList:", "data_lengthoflist", &[Variable("LIST", LIST)]),
+    ("list:contains:", "data_listcontainsitem", &[Variable("LIST", LIST), Text("ITEM")]),
+    ("showList:", "data_showlist", &[Variable("LIST", LIST)]),
+    ("hideList:", "data_hidelist", &[Variable("LIST", LIST)]),
+];
+
+fn spec(opcode: &str) -> Option<(&'static str, &'static [Arg])> {
+    SPECS
+        .iter()
+        .find(|(sb2, _, _)| *sb2 == opcode)
+        .map(|(_, sb3, args)| (*sb3, *args))
+}
+
+fn number(json: &Json) -> NumberOrString {
+    match json {
+        Json::Number(n) => NumberOrString::Number(if let Some(n) = n.as_u64() {
+            Number::UInt(n)
+        } else if let Some(n) = n.as_i64() {
+            Number::Int(n)
+        } else {
+            Number::Float(n.as_f64().unwrap_or(f64::NAN))
+        }),
+        Json::String(s) => NumberOrString::String(s.clone()),
+        Json::Bool(b) => NumberOrString::String(b.to_string()),
+        _ => NumberOrString::String(String::new()),
+    }
+}
+
+fn string(json: &Json) -> String {
+    match json {
+        Json::String(s) => s.clone(),
+        Json::Null => String::new(),
+        json => json.to_string(),
+    }
+}
+
+// sb2 colors are 0xAARRGGBB ints, sometimes negative
+fn color(json: &Json) -> sb3::Color {
+    let argb = match json {
+        Json::Number(n) => n.as_i64().unwrap_or(0) as u32,
+        Json::String(s) => {
+            return sb3::Color::from_hex(s).unwrap_or(sb3::Color { r: 0, g: 0, b: 0 });
+        }
+        _ => 0,
+    };
+    sb3::Color {
+        r: (argb >> 16) as u8,
+        g: (argb >> 8) as u8,
+        b: argb as u8,
+    }
+}
+
+fn is_block(json: &Json) -> bool {
+    match json {
+        Json::Array(array) => array.first().is_some_and(Json::is_string),
+        _ => false,
+    }
+}
+
+// the arg types of a proccode, in order, where %b is boolean and %n, %s, and %d are strings/numbers
+fn proc_arg_is_bool(proc_code: &str) -> Vec<bool> {
+    proc_code
+        .split_whitespace()
+        .filter(|token| token.len() == 2 && token.starts_with('%'))
+        .filter(|token| ["%b", "%n", "%s", "%d"].contains(token))
+        .map(|token| token == "%b")
+        .collect()
+}
+
+fn proc_arg_ids(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("arg{}", i)).collect()
+}
+
+fn text_input(value: NumberOrString) -> Input {
+    Input {
+        shadow: Shadow::UnObscured,
+        value: Some(InputValue::Primitive(Primitive::Text(value))),
+        obscured: None,
+    }
+}
+
+// IDs and names of variables, lists, and broadcasts,
+// which sb2 refers to by name and sb3 by ID
+#[derive(Default)]
+struct Names {
+    variables: HashMap<String, String>,
+    lists: HashMap<String, String>,
+}
+
+struct Converter<'a, E> {
+    next_id: &'a mut usize,
+    globals: Option<&'a Names>,
+    locals: &'a Names,
+    broadcasts: &'a mut BTreeMap<String, String>,
+    extensions: &'a mut Vec<String>,
+    is_stage: bool,
+    blocks: HashMap<BlockId, sb3::Block>,
+    top_level_primitives: HashMap<BlockId, VariablePrimitive>,
+    // in the order sb2 numbers blocks for comments
+    block_order: Vec<BlockId>,
+    error: std::marker::PhantomData<E>,
+}
+
+fn new_id(next_id: &mut usize, prefix: &str) -> String {
+    *next_id += 1;
+    format!("{}{}", prefix, next_id)
+}
+
+impl<'a, E: Error> Converter<'a, E> {
+    fn id(&mut self) -> BlockId {
+        new_id(self.next_id, "b")
+    }
+
+    fn variable_id(&mut self, name: &str, r#type: VariableType) -> String {
+        let lookup = |names: &Names| match r#type {
+            VariableType::List => names.lists.get(name).cloned(),
+            _ => names.variables.get(name).cloned(),
+        };
+        if r#type == VariableType::Broadcast {
+            return self.broadcast_id(name);
+        }
+        lookup(self.locals)
+            .or_else(|| self.globals.and_then(lookup))
+            // a reference to a variable that doesn't exist, which sb2 allowed
+            .unwrap_or_else(|| format!("{}-{}", name, if r#type == VariableType::List { "list" } else { "variable" }))
+    }
+
+    fn broadcast_id(&mut self, name: &str) -> String {
+        if let Some(id) = self.broadcasts.get(name) {
+            return id.clone();
+        }
+        let id = new_id(self.next_id, "m");
+        self.broadcasts.insert(name.to_owned(), id.clone());
+        id
+    }
+
+    fn variable_primitive(&mut self, name: &Json, r#type: VariableType) -> VariablePrimitive {
+        let name = string(name);
+        VariablePrimitive {
+            id: self.variable_id(&name, r#type),
+            name,
+            r#type,
+            position: None,
+        }
+    }
+
+    fn add(&mut self, id: BlockId, op_code: &str, parent: Option<&str>, shadow: bool) -> BlockId {
+        if let Some(extension) = op_code.split('_').next().filter(|prefix| ["pen", "music"].contains(prefix)) {
+            if !self.extensions.iter().any(|e| e == extension) {
+                self.extensions.push(extension.to_owned());
+            }
+        }
+        self.blocks.insert(id.clone(), sb3::Block {
+            op_code: sb3::OpCode::parse(op_code),
+            next: None,
+            parent: parent.map(str::to_owned),
+            comment: None,
+            inputs: HashMap::new(),
+            fields: HashMap::new(),
+            mutation: None,
+            top_level: parent.is_none(),
+            shadow,
+            position: Vec2(0.0, 0.0),
+        });
+        id
+    }
+
+    fn block_mut(&mut self, id: &str) -> &mut sb3::Block {
+        self.blocks.get_mut(id).unwrap()
+    }
+
+    fn menu(&mut self, parent: &str, op_code: &str, field: &str, value: String) -> BlockId {
+        let id = self.id();
+        self.add(id.clone(), op_code, Some(parent), true);
+        self.block_mut(&id).fields.insert(field.to_owned(), sb3::Field { value, id: None });
+        id
+    }
+
+    // a nested reporter in an input that also has a shadow
+    fn obscuring(&mut self, json: &Json, parent: &str, shadow: InputValue) -> Result<Input, E> {
+        Ok(Input {
+            shadow: Shadow::Obscured,
+            value: Some(self.reporter(json, parent)?),
+            obscured: Some(shadow),
+        })
+    }
+
+    fn reporter(&mut self, json: &Json, parent: &str) -> Result<InputValue, E> {
+        let array = json.as_array().unwrap();
+        let opcode = array[0].as_str().unwrap_or("");
+        Ok(match opcode {
+            "readVariable" => InputValue::Primitive(Primitive::Variable(
+                self.variable_primitive(array.get(1).unwrap_or(&Json::Null), VariableType::Scalar),
+            )),
+            "contentsOfList:" => InputValue::Primitive(Primitive::Variable(
+                self.variable_primitive(array.get(1).unwrap_or(&Json::Null), VariableType::List),
+            )),
+            _ => InputValue::Block(self.block(array, Some(parent))?),
+        })
+    }
+
+    fn arg(&mut self, id: &str, arg: Arg, json: &Json) -> Result<(), E> {
+        let input = |name: &str, input| Some((name.to_owned(), input));
+        let primitive = |primitive| InputValue::Primitive(primitive);
+        let (input, field) = match arg {
+            Num(name, kind) => {
+                let shadow = |value| primitive(Primitive::Num(NumPrimitive { kind, value }));
+                if is_block(json) {
+                    (input(name, self.obscuring(json, id, shadow(NumberOrString::String(String::new())))?), None)
+                } else {
+                    (input(name, Input { shadow: Shadow::UnObscured, value: Some(shadow(number(json))), obscured: None }), None)
+                }
+            }
+            Text(name) => {
+                if is_block(json) {
+                    let shadow = primitive(Primitive::Text(NumberOrString::String(String::new())));
+                    (input(name, self.obscuring(json, id, shadow)?), None)
+                } else {
+                    (input(name, text_input(number(json))), None)
+                }
+            }
+            Color(name) => {
+                let shadow = primitive(Primitive::Color(color(json)));
+                if is_block(json) {
+                    (input(name, self.obscuring(json, id, shadow)?), None)
+                } else {
+                    (input(name, Input { shadow: Shadow::UnObscured, value: Some(shadow), obscured: None }), None)
+                }
+            }
+            Bool(name) => {
+                let value = if is_block(json) { Some(self.reporter(json, id)?) } else { None };
+                (input(name, Input { shadow: Shadow::None, value, obscured: None }), None)
+            }
+            Broadcast(name) => {
+                if is_block(json) {
+                    let value = Some(self.reporter(json, id)?);
+                    (input(name, Input { shadow: Shadow::Obscured, value, obscured: None }), None)
+                } else {
+                    let broadcast = self.variable_primitive(json, VariableType::Broadcast);
+                    let value = Some(primitive(Primitive::Variable(broadcast)));
+                    (input(name, Input { shadow: Shadow::UnObscured, value, obscured: None }), None)
+                }
+            }
+            Menu(name, op_code, field) => {
+                if is_block(json) {
+                    let menu = self.menu(id, op_code, field, String::new());
+                    (input(name, self.obscuring(json, id, InputValue::Block(menu))?), None)
+                } else {
+                    let menu = self.menu(id, op_code, field, string(json));
+                    let value = Some(InputValue::Block(menu));
+                    (input(name, Input { shadow: Shadow::UnObscured, value, obscured: None }), None)
+                }
+            }
+            ConstMenu(name, op_code, field, value) => {
+                let menu = self.menu(id, op_code, field, value.to_owned());
+                let value = Some(InputValue::Block(menu));
+                (input(name, Input { shadow: Shadow::UnObscured, value, obscured: None }), None)
+            }
+            Field(name) => (None, Some((name, sb3::Field { value: string(json), id: None }))),
+            UpperField(name) => {
+                let value = string(json).replace(' ', "").to_uppercase();
+                (None, Some((name, sb3::Field { value, id: None })))
+            }
+            ConstField(name, value) => (None, Some((name, sb3::Field { value: value.to_owned(), id: None }))),
+            Variable(name, r#type) => {
+                let variable = self.variable_primitive(json, r#type);
+                (None, Some((name, sb3::Field { value: variable.name, id: Some(variable.id) })))
+            }
+            Substack(name) => {
+                let stack = json.as_array().map(Vec::as_slice).unwrap_or(&[]);
+                let first = self.stack(stack, Some(id))?;
+                (first.map(|first| (name.to_owned(), Input { shadow: Shadow::None, value: Some(InputValue::Block(first)), obscured: None })), None)
+            }
+        };
+        let block = self.block_mut(id);
+        if let Some((name, input)) = input {
+            block.inputs.insert(name, input);
+        }
+        if let Some((name, field)) = field {
+            block.fields.insert(name.to_owned(), field);
+        }
+        Ok(())
+    }
+
+    fn proc_def(&mut self, id: &str, args: &[Json]) -> Result<(), E> {
+        let proc_code = string(args.first().unwrap_or(&Json::Null)).replace("%n", "%s");
+        let names = args
+            .get(1)
+            .and_then(Json::as_array)
+            .map(|names| names.iter().map(string).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let defaults = args
+            .get(2)
+            .and_then(Json::as_array)
+            .map(|defaults| defaults.iter().map(|d| ScalarValue::String(string(d))).collect())
+            .unwrap_or_default();
+        let warp = args.get(3).and_then(Json::as_bool);
+        let is_bool = proc_arg_is_bool(&proc_code);
+        let ids = proc_arg_ids(names.len());
+
+        let prototype = self.id();
+        self.add(prototype.clone(), "procedures_prototype", Some(id), true);
+        for ((arg_id, name), is_bool) in ids.iter().zip(&names).zip(is_bool) {
+            let op_code = if is_bool { "argument_reporter_boolean" } else { "argument_reporter_string_number" };
+            let reporter = self.id();
+            self.add(reporter.clone(), op_code, Some(&prototype), true);
+            self.block_mut(&reporter).fields.insert("VALUE".to_owned(), sb3::Field { value: name.clone(), id: None });
+            let input = Input { shadow: Shadow::UnObscured, value: Some(InputValue::Block(reporter)), obscured: None };
+            self.block_mut(&prototype).inputs.insert(arg_id.clone(), input);
+        }
+        self.block_mut(&prototype).mutation = Some(sb3::Mutation {
+            tag_name: "mutation".to_owned(),
+            proc_code: Some(proc_code),
+            argument_ids: Some(ids),
+            argument_names: Some(names),
+            argument_defaults: Some(defaults),
+            warp,
+            ..Default::default()
+        });
+        let input = Input { shadow: Shadow::UnObscured, value: Some(InputValue::Block(prototype)), obscured: None };
+        self.block_mut(id).inputs.insert("custom_block".to_owned(), input);
+        Ok(())
+    }
+
+    fn call(&mut self, id: &str, args: &[Json]) -> Result<(), E> {
+        let proc_code = string(args.first().unwrap_or(&Json::Null)).replace("%n", "%s");
+        let is_bool = proc_arg_is_bool(&proc_code);
+        let ids = proc_arg_ids(is_bool.len());
+        for ((arg_id, is_bool), json) in ids.iter().zip(&is_bool).zip(args[1.min(args.len())..].iter()) {
+            let arg = if *is_bool { Bool("") } else { Text("") };
+            self.arg(id, arg, json)?;
+            let block = self.block_mut(id);
+            if let Some(input) = block.inputs.remove("") {
+                block.inputs.insert(arg_id.clone(), input);
+            }
+        }
+        self.block_mut(id).mutation = Some(sb3::Mutation {
+            tag_name: "mutation".to_owned(),
+            proc_code: Some(proc_code),
+            argument_ids: Some(ids),
+            warp: Some(false),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn block(&mut self, array: &[Json], parent: Option<&str>) -> Result<BlockId, E> {
+        let opcode = array
+            .first()
+            .and_then(Json::as_str)
+            .ok_or_else(|| E::custom(format!("invalid sb2 block: {:?}", array)))?;
+        let args = &array[1..];
+        let id = self.id();
+        self.block_order.push(id.clone());
+        match opcode {
+            "procDef" => {
+                self.add(id.clone(), "procedures_definition", parent, false);
+                self.proc_def(&id, args)?;
+            }
+            "call" => {
+                self.add(id.clone(), "procedures_call", parent, false);
+                self.call(&id, args)?;
+            }
+            "getParam" => {
+                let is_bool = args.get(1).and_then(Json::as_str) == Some("b");
+                let op_code = if is_bool { "argument_reporter_boolean" } else { "argument_reporter_string_number" };
+                self.add(id.clone(), op_code, parent, false);
+                let name = string(args.first().unwrap_or(&Json::Null));
+                self.block_mut(&id).fields.insert("VALUE".to_owned(), sb3::Field { value: name, id: None });
+            }
+            "readVariable" | "contentsOfList:" => {
+                let (op_code, field, r#type) = if opcode == "readVariable" {
+                    ("data_variable", "VARIABLE", VariableType::Scalar)
+                } else {
+                    ("data_listcontents", "LIST", VariableType::List)
+                };
+                self.add(id.clone(), op_code, parent, false);
+                let variable = self.variable_primitive(args.first().unwrap_or(&Json::Null), r#type);
+                let field_value = sb3::Field { value: variable.name, id: Some(variable.id) };
+                self.block_mut(&id).fields.insert(field.to_owned(), field_value);
+            }
+            "whenClicked" if self.is_stage => {
+                self.add(id.clone(), "event_whenstageclicked", parent, false);
+            }
+            _ => match spec(opcode) {
+                Some((op_code, spec_args)) => {
+                    self.add(id.clone(), op_code, parent, false);
+                    let mut args = args.iter();
+                    for arg in spec_args {
+                        let json = match arg {
+                            ConstField(_, _) | ConstMenu(_, _, _, _) => &Json::Null,
+                            _ => args.next().unwrap_or(&Json::Null),
+                        };
+                        self.arg(&id, *arg, json)?;
+                    }
+                    if op_code == "control_stop" {
+                        let stop = &self.block_mut(&id).fields["STOP_OPTION"].value;
+                        let has_next = stop.starts_with("other scripts");
+                        self.block_mut(&id).mutation = Some(sb3::Mutation {
+                            tag_name: "mutation".to_owned(),
+                            has_next: Some(has_next),
+                            ..Default::default()
+                        });
+                    }
+                }
+                // obsolete or unknown blocks are kept as is, so they show up as unknown opcodes later
+                None => {
+                    self.add(id.clone(), opcode, parent, false);
+                }
+            },
+        }
+        Ok(id)
+    }
+
+    // returns the first block's ID
+    fn stack(&mut self, stack: &[Json], parent: Option<&str>) -> Result<Option<BlockId>, E> {
+        let mut first = None;
+        let mut previous: Option<BlockId> = None;
+        for json in stack {
+            let array = json
+                .as_array()
+                .ok_or_else(|| E::custom(format!("invalid sb2 block: {}", json)))?;
+            let id = self.block(array, previous.as_deref().or(parent))?;
+            match &previous {
+                Some(previous) => self.block_mut(previous).next = Some(id.clone()),
+                None => first = Some(id.clone()),
+            }
+            previous = Some(id);
+        }
+        Ok(first)
+    }
+
+    fn script(&mut self, json: &Json) -> Result<(), E> {
+        let script = json.as_array().filter(|script| script.len() == 3);
+        let (x, y, stack) = match script {
+            Some(script) => (&script[0], &script[1], &script[2]),
+            None => return Err(E::custom(format!("invalid sb2 script: {}", json))),
+        };
+        let position = Vec2(x.as_f64().unwrap_or(0.0), y.as_f64().unwrap_or(0.0));
+        let stack = stack.as_array().map(Vec::as_slice).unwrap_or(&[]);
+
+        // a lone variable or list reporter is a top level primitive in sb3
+        if let [block] = stack {
+            let array = block.as_array().map(Vec::as_slice).unwrap_or(&[]);
+            let r#type = match array.first().and_then(Json::as_str) {
+                Some("readVariable") => Some(VariableType::Scalar),
+                Some("contentsOfList:") => Some(VariableType::List),
+                _ => None,
+            };
+            if let Some(r#type) = r#type {
+                let id = self.id();
+                self.block_order.push(id.clone());
+                let mut primitive = self.variable_primitive(array.get(1).unwrap_or(&Json::Null), r#type);
+                primitive.position = Some(position);
+                self.top_level_primitives.insert(id, primitive);
+                return Ok(());
+            }
+        }
+
+        if let Some(first) = self.stack(stack, None)? {
+            self.block_mut(&first).position = position;
+        }
+        Ok(())
+    }
+
+    // [x, y, width, height, open, block index, text]
+    fn comment(&mut self, json: &Json) -> (String, sb3::Comment) {
+        let get = |i: usize| json.get(i).unwrap_or(&Json::Null);
+        let f64 = |i: usize| get(i).as_f64().unwrap_or(0.0);
+        let block = get(5)
+            .as_i64()
+            .filter(|&i| i >= 0)
+            .and_then(|i| self.block_order.get(i as usize))
+            .cloned();
+        let id = new_id(self.next_id, "c");
+        if let Some(block) = &block {
+            self.block_mut(block).comment = Some(id.clone());
+        }
+        let comment = sb3::Comment {
+            block,
+            text: string(get(6)),
+            minimized: !get(4).as_bool().unwrap_or(true),
+            position: Vec2(f64(0), f64(1)),
+            size: Vec2(f64(2), f64(3)),
+        };
+        (id, comment)
+    }
+}
+
+fn split_md5ext(md5ext: &str) -> (&str, &str) {
+    let mut parts = md5ext.rsplitn(2, '.');
+    let ext = parts.next().unwrap_or("");
+    let md5 = parts.next().unwrap_or(md5ext);
+    (md5, ext)
+}
+
+// sb2 archives store assets by their layer/sound ID, not their md5, so that's what md5ext is set to
+fn asset<F>(md5: &str, id: Option<i64>, name: String, format: F) -> Asset<F> {
+    let (asset_id, ext) = split_md5ext(md5);
+    let md5ext = match id {
+        Some(id) if id >= 0 => format!("{}.{}", id, ext),
+        _ => md5.to_owned(),
+    };
+    Asset {
+        asset_id: AssetId(asset_id.to_owned()),
+        data_format: format,
+        name,
+        md5ext,
+    }
+}
+
+fn costume<E: Error>(costume: Costume) -> Result<sb3::Costume, E> {
+    let (_, ext) = split_md5ext(&costume.base_layer_m_d5);
+    let format = match ext.to_lowercase().as_str() {
+        "png" => ImageDataFormat::PNG,
+        "svg" => ImageDataFormat::SVG,
+        "jpeg" => ImageDataFormat::JPEG,
+        "jpg" => ImageDataFormat::JPG,
+        "bmp" => ImageDataFormat::BMP,
+        "gif" => ImageDataFormat::GIF,
+        _ => return Err(E::custom(format!("unknown costume format: {}", costume.base_layer_m_d5))),
+    };
+    Ok(sb3::Costume {
+        asset: asset(&costume.base_layer_m_d5, costume.base_layer_id, costume.costume_name, format),
+        bitmap_resolution: costume.bitmap_resolution.unwrap_or(1),
+        rotation_center: Vec2(costume.rotation_center_x, costume.rotation_center_y),
+    })
+}
+
+fn sound<E: Error>(sound: Sound) -> Result<sb3::Sound, E> {
+    let (_, ext) = split_md5ext(&sound.md5);
+    let format = match ext.to_lowercase().as_str() {
+        "wav" => AudioDataFormat::WAV,
+        "wave" => AudioDataFormat::WAVE,
+        "mp3" => AudioDataFormat::MP3,
+        _ => return Err(E::custom(format!("unknown sound format: {}", sound.md5))),
+    };
+    Ok(sb3::Sound {
+        asset: asset(&sound.md5, sound.sound_id, sound.sound_name, format),
+        rate: sound.rate,
+        sample_count: sound.sample_count,
+    })
+}
+
+// state shared between all the targets of a project
+#[derive(Default)]
+struct Context {
+    next_id: usize,
+    broadcasts: BTreeMap<String, String>,
+    extensions: Vec<String>,
+}
+
+impl Object {
+    fn names(&self, next_id: &mut usize) -> Names {
+        Names {
+            variables: self
+                .variables
+                .iter()
+                .map(|variable| (variable.name.clone(), new_id(next_id, "v")))
+                .collect(),
+            lists: self
+                .lists
+                .iter()
+                .map(|list| (list.list_name.clone(), new_id(next_id, "l")))
+                .collect(),
+        }
+    }
+
+    fn into_target<E: Error>(
+        self,
+        context: &mut Context,
+        globals: Option<&Names>,
+        locals: &Names,
+        is_stage: bool,
+    ) -> Result<sb3::Target, E> {
+        let mut converter = Converter {
+            next_id: &mut context.next_id,
+            globals,
+            locals,
+            broadcasts: &mut context.broadcasts,
+            extensions: &mut context.extensions,
+            is_stage,
+            blocks: HashMap::new(),
+            top_level_primitives: HashMap::new(),
+            block_order: Vec::new(),
+            error: std::marker::PhantomData,
+        };
+        for script in &self.scripts {
+            converter.script(script)?;
+        }
+        let comments = self
+            .script_comments
+            .iter()
+            .map(|comment| converter.comment(comment))
+            .collect();
+        let Converter { blocks, top_level_primitives, .. } = converter;
+
+        let variables = self.variables.into_iter().map(|variable| sb3::Variable {
+            id: locals.variables[&variable.name].clone(),
+            value: Value::Scalar(variable.value.unwrap_or(ScalarValue::Number(Number::UInt(0)))),
+            name: variable.name,
+            on_cloud: variable.is_persistent,
+        });
+        let lists = self.lists.into_iter().map(|list| sb3::Variable {
+            id: locals.lists[&list.list_name].clone(),
+            name: list.list_name,
+            value: Value::List(list.contents),
+            on_cloud: false,
+        });
+        Ok(sb3::Target {
+            current_costume: self.current_costume_index.max(0.0) as usize,
+            blocks,
+            top_level_primitives,
+            variables: variables.chain(lists).collect(),
+            comments,
+            costumes: self.costumes.into_iter().map(costume).collect::<Result<_, E>>()?,
+            sounds: self.sounds.into_iter().map(sound).collect::<Result<_, E>>()?,
+            volume: 100.0,
+        })
+    }
+
+    fn into_sprite<E: Error>(
+        self,
+        context: &mut Context,
+        globals: Option<&Names>,
+        layer_order: u32,
+    ) -> Result<sb3::Sprite, E> {
+        let locals = self.names(&mut context.next_id);
+        let name = self.obj_name.clone().unwrap_or_default();
+        let rotation_style = match self.rotation_style.as_deref() {
+            Some("leftRight") => RotationStyle::LeftRight,
+            Some("none") => RotationStyle::DontRotate,
+            _ => RotationStyle::AllAround,
+        };
+        Ok(sb3::Sprite {
+            name,
+            visible: self.visible.unwrap_or(true),
+            position: Vec2(self.scratch_x.unwrap_or(0.0), self.scratch_y.unwrap_or(0.0)),
+            size: self.scale.unwrap_or(1.0) * 100.0,
+            direction: self.direction.unwrap_or(90.0),
+            draggable: self.is_draggable,
+            rotation_style,
+            layer_order,
+            target: self.into_target(context, globals, &locals, false)?,
+        })
+    }
+
+    pub(super) fn into_project<E: Error>(mut self) -> Result<sb3::Project, E> {
+        let mut context = Context::default();
+        let globals = self.names(&mut context.next_id);
+        let children = std::mem::take(&mut self.children);
+        let sprites = children
+            .into_iter()
+            .filter(|child| child.obj_name.is_some())
+            .enumerate()
+            .map(|(i, child)| child.into_sprite(&mut context, Some(&globals), i as u32 + 1))
+            .collect::<Result<Vec<_>, E>>()?;
+
+        let tempo = self.tempo_bpm.unwrap_or(60.0);
+        let video_transparency = (1.0 - self.video_alpha.unwrap_or(0.5)) * 100.0;
+        let user_agent = self.info.take().and_then(|info| info.user_agent).unwrap_or_default();
+        let mut target = self.into_target(&mut context, None, &globals, true)?;
+        target
+            .variables
+            .extend(broadcasts(std::mem::take(&mut context.broadcasts)));
+
+        Ok(sb3::Project {
+            targets: sb3::Targets {
+                stage: sb3::Stage {
+                    target,
+                    tempo,
+                    video_transparency,
+                    video_state: VideoState::Off,
+                },
+                sprites,
+            },
+            extensions: context.extensions,
+            meta: sb3::Meta {
+                version: SemVer {
+                    major: 3,
+                    minor: 0,
+                    patch: "0".to_owned(),
+                },
+                vm: SemVer::default(),
+                user_agent,
+            },
+            assets: sb3::Assets::new(),
+        })
+    }
+
+    // a sprite2's broadcasts are kept in the sprite like in a sprite3
+    pub(super) fn into_lone_sprite<E: Error>(self) -> Result<sb3::Sprite, E> {
+        let mut context = Context::default();
+        let mut sprite = self.into_sprite(&mut context, None, 1)?;
+        sprite.target.variables.extend(broadcasts(context.broadcasts));
+        Ok(sprite)
+    }
+}
+
+fn broadcasts(broadcasts: BTreeMap<String, String>) -> impl Iterator<Item = sb3::Variable> {
+    broadcasts.into_iter().map(|(name, id)| sb3::Variable {
+        id,
+        name: name.clone(),
+        value: Value::Broadcast(sb3::Broadcast { name }),
+        on_cloud: false,
+    })
+}
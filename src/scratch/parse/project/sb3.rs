@@ -1,12 +1,19 @@
 use std::collections::HashMap;
-use zip::read::ZipFile;
 
-pub struct Vec2<T>(T, T);
+use serde::Deserialize;
 
-pub struct AssetId {
-    // TODO
-}
+use super::opcode::{
+    ControlOpCode, DataOpCode, EventOpCode, LooksOpCode, MotionOpCode, OperatorOpCode, PenOpCode,
+    ProcedureOpCode, SensingOpCode, SoundOpCode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vec2<T>(pub T, pub T);
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetId(pub String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ImageDataFormat {
     PNG,
     SVG,
@@ -16,92 +23,131 @@ pub enum ImageDataFormat {
     GIF,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AudioDataFormat {
     WAV,
     WAVE,
     MP3,
 }
 
-pub struct Asset<'a, DataFormat> {
+// the asset's data isn't kept here, since it's in the archive under `md5ext`, or in the project's assets
+// if it came with a sprite added to it. use ProjectArchive::read_asset to get it
+#[derive(Debug, Clone)]
+pub struct Asset<DataFormat> {
     pub asset_id: AssetId,
     pub data_format: DataFormat,
     pub name: String,
-    pub file: ZipFile<'a>,
+    pub md5ext: String,
 }
 
-pub struct Costume<'a> {
-    pub asset: Asset<'a, ImageDataFormat>,
+// asset data by md5ext
+pub type Assets = HashMap<String, Vec<u8>>;
+
+#[derive(Debug, Clone)]
+pub struct Costume {
+    pub asset: Asset<ImageDataFormat>,
     pub bitmap_resolution: u32,
     pub rotation_center: Vec2<f64>,
 }
 
-pub struct Sound<'a> {
-    pub asset: Asset<'a, AudioDataFormat>,
+#[derive(Debug, Clone)]
+pub struct Sound {
+    pub asset: Asset<AudioDataFormat>,
     pub rate: u32,
     pub sample_count: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Number {
     UInt(u64),
     Int(i64),
     Float(f64),
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum NumberOrString {
     Number(Number),
     String(String),
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ScalarValue {
     Bool(bool),
     Number(Number),
     String(String),
 }
 
+#[derive(Debug, Clone)]
 pub struct Broadcast {
     pub name: String,
 }
 
+#[derive(Debug, Clone)]
 pub enum Value {
     Scalar(ScalarValue),
-    List(Vec<Value>),
+    List(Vec<ScalarValue>),
     Broadcast(Broadcast),
 }
 
+#[derive(Debug, Clone)]
 pub struct Variable {
+    pub id: String,
     pub name: String,
     pub value: Value,
     pub on_cloud: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
-    // TODO
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
 }
 
-pub type NumPrimitive = NumberOrString;
+// Scratch distinguishes these only for the editor's input validation,
+// but they're still useful hints for what kind of number is expected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumKind {
+    Number,
+    PositiveNumber,
+    WholeNumber,
+    Integer,
+    Angle,
+}
+
+#[derive(Debug, Clone)]
+pub struct NumPrimitive {
+    pub kind: NumKind,
+    pub value: NumberOrString,
+}
 
 pub type ColorPrimitive = Color;
 
-pub type TextPrimitive = String;
+pub type TextPrimitive = NumberOrString;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VariableType {
     Scalar,
     List,
     Broadcast, // same as Value
 }
 
+#[derive(Debug, Clone)]
 pub struct VariablePrimitive {
-    name: String,
-    r#type: VariableType,
-    id: String,
-    position: Vec2<f64>,
+    pub name: String,
+    pub r#type: VariableType,
+    pub id: String,
+    // only top level primitives (reporters dropped directly in the workspace) have a position
+    pub position: Option<Vec2<f64>>,
 }
 
+#[derive(Debug, Clone)]
 pub struct IndexPrimitive {
-    value: i32,
+    pub value: i32,
 }
 
-//#[serde(tag = "kind")]
+// serialized as arrays tagged by their first element, like [4, "10"] or [12, "var", "id"]
+#[derive(Debug, Clone)]
 pub enum Primitive {
     Num(NumPrimitive),
     Color(ColorPrimitive),
@@ -110,6 +156,7 @@ pub enum Primitive {
     Index(IndexPrimitive),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BlockCategory {
     Motion,
     Look,
@@ -120,80 +167,137 @@ pub enum BlockCategory {
     Operator,
     Variable,
     Block,
-}
-
-pub enum CategoryOpCode {}
-
+    Pen,
+    Extension,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CategoryOpCode {
+    Motion(MotionOpCode),
+    Look(LooksOpCode),
+    Sound(SoundOpCode),
+    Event(EventOpCode),
+    Control(ControlOpCode),
+    Sensing(SensingOpCode),
+    Operator(OperatorOpCode),
+    Variable(DataOpCode),
+    Block(ProcedureOpCode),
+    Pen(PenOpCode),
+    // extension opcodes, or ones from a newer version of Scratch
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct OpCode {
     pub category: BlockCategory,
     pub op_code: CategoryOpCode,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Shadow {
     UnObscured,
     None,
     Obscured,
 }
 
+pub type BlockId = String;
+
+#[derive(Debug, Clone)]
+pub enum InputValue {
+    Block(BlockId),
+    Primitive(Primitive),
+}
+
+#[derive(Debug, Clone)]
 pub struct Input {
     pub shadow: Shadow,
-    pub args: Vec<Primitive>,
+    pub value: Option<InputValue>,
+    // only for Shadow::Obscured, the shadow hidden behind the block in `value`
+    pub obscured: Option<InputValue>,
 }
 
+#[derive(Debug, Clone)]
 pub struct Field {
-    // TODO
+    pub value: String,
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Mutation {
+    pub tag_name: String,
+    pub children: Vec<serde_json::Value>,
+    pub proc_code: Option<String>,
+    pub argument_ids: Option<Vec<String>>,
+    pub argument_names: Option<Vec<String>>,
+    pub argument_defaults: Option<Vec<ScalarValue>>,
+    pub warp: Option<bool>,
+    pub has_next: Option<bool>,
 }
 
-pub struct Block<'a> {
+#[derive(Debug, Clone)]
+pub struct Block {
     pub op_code: OpCode,
-    pub next: Option<&'a Block<'a>>,
-    pub parent: Option<&'a Block<'a>>,
+    pub next: Option<BlockId>,
+    pub parent: Option<BlockId>,
     pub comment: Option<String>,
     pub inputs: HashMap<String, Input>,
     pub fields: HashMap<String, Field>,
+    pub mutation: Option<Mutation>,
     pub top_level: bool,
     pub shadow: bool,
     pub position: Vec2<f64>,
 }
 
-pub struct Comment<'a> {
-    pub block: Option<&'a Block<'a>>,
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub block: Option<BlockId>,
     pub text: String,
     pub minimized: bool,
     pub position: Vec2<f64>,
+    pub size: Vec2<f64>,
 }
 
-pub struct Target<'a> {
-    pub current_costume: &'a Costume<'a>,
-    pub blocks: Vec<Block<'a>>,
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub current_costume: usize,
+    pub blocks: HashMap<BlockId, Block>,
+    pub top_level_primitives: HashMap<BlockId, VariablePrimitive>,
     pub variables: Vec<Variable>,
-    pub comments: Vec<Comment<'a>>,
-    pub costumes: Vec<Costume<'a>>,
-    pub sounds: Vec<Sound<'a>>,
+    pub comments: HashMap<String, Comment>,
+    pub costumes: Vec<Costume>,
+    pub sounds: Vec<Sound>,
     pub volume: f64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum VideoState {
     On,
     Off,
     OnFlipped,
 }
 
-pub struct Stage<'a> {
-    pub target: Target<'a>,
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub target: Target,
     pub tempo: f64,
     pub video_transparency: f64,
     pub video_state: VideoState,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum RotationStyle {
+    #[serde(rename = "all around")]
     AllAround,
+    #[serde(rename = "don't rotate")]
     DontRotate,
+    #[serde(rename = "left-right")]
     LeftRight,
 }
 
-pub struct Sprite<'a> {
-    pub target: Target<'a>,
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    pub target: Target,
     pub name: String,
     pub visible: bool,
     pub position: Vec2<f64>,
@@ -204,24 +308,33 @@ pub struct Sprite<'a> {
     pub layer_order: u32,
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct SemVer {
     pub major: u32,
     pub minor: u32,
     pub patch: String,
 }
 
+#[derive(Debug, Clone, Default)]
 pub struct Meta {
     pub version: SemVer,
     pub vm: SemVer,
     pub user_agent: String,
 }
 
-pub struct Targets<'a> {
-    pub stage: Stage<'a>,
-    pub sprites: Vec<Sprite<'a>>,
+#[derive(Debug, Clone)]
+pub struct Targets {
+    pub stage: Stage,
+    pub sprites: Vec<Sprite>,
 }
 
-pub struct Project<'a> {
-    pub targets: Targets<'a>,
+#[derive(Debug, Clone, Deserialize)]
+pub struct Project {
+    pub targets: Targets,
+    #[serde(default)]
+    pub extensions: Vec<String>,
     pub meta: Meta,
+    // of the sprites added to it, which aren't in its archive
+    #[serde(skip)]
+    pub assets: Assets,
 }
@@ -0,0 +1,641 @@
+// serde representation of project.json (see sb3_schema.json and sb3_definitions.json)
+// types whose json shape matches the model in sb3 are deserialized directly,
+// the rest are read into the raw types here and then converted
+
+use std::collections::HashMap;
+
+use serde::de::{DeserializeOwned, Error};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value as Json;
+
+use super::sb3;
+use super::sb3::{
+    Asset, AssetId, AudioDataFormat, Broadcast, Color, ImageDataFormat, Input, InputValue, NumKind,
+    NumPrimitive, Number, NumberOrString, Primitive, RotationStyle, ScalarValue, SemVer, Shadow,
+    Value, Variable, VariablePrimitive, VariableType, Vec2, VideoState,
+};
+
+fn from_json<T: DeserializeOwned, E: Error>(json: Json) -> Result<T, E> {
+    serde_json::from_value(json).map_err(E::custom)
+}
+
+fn number(number: &serde_json::Number) -> Number {
+    if let Some(n) = number.as_u64() {
+        Number::UInt(n)
+    } else if let Some(n) = number.as_i64() {
+        Number::Int(n)
+    } else {
+        Number::Float(number.as_f64().unwrap_or(f64::NAN))
+    }
+}
+
+impl ScalarValue {
+    fn from_json<E: Error>(json: Json) -> Result<ScalarValue, E> {
+        Ok(match json {
+            Json::Bool(b) => ScalarValue::Bool(b),
+            Json::Number(n) => ScalarValue::Number(number(&n)),
+            Json::String(s) => ScalarValue::String(s),
+            json => return Err(E::custom(format!("expected a scalar value, found {}", json))),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for ScalarValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ScalarValue::from_json(Json::deserialize(deserializer)?)
+    }
+}
+
+impl NumberOrString {
+    fn from_json<E: Error>(json: Json) -> Result<NumberOrString, E> {
+        Ok(match json {
+            Json::Number(n) => NumberOrString::Number(number(&n)),
+            Json::String(s) => NumberOrString::String(s),
+            json => return Err(E::custom(format!("expected a number or string, found {}", json))),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for SemVer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let mut parts = s.splitn(3, '.');
+        let mut next_u32 = || -> Result<u32, D::Error> {
+            parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(|| D::Error::custom(format!("invalid semver: {}", s)))
+        };
+        let major = next_u32()?;
+        let minor = next_u32()?;
+        let patch = parts.next().unwrap_or("0").to_owned();
+        Ok(SemVer { major, minor, patch })
+    }
+}
+
+impl Color {
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let hex = hex.strip_prefix('#')?;
+        // from_str_radix would take a sign, and slicing by bytes would split a multi-byte char
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+impl Primitive {
+    fn from_json<E: Error>(json: Json) -> Result<Primitive, E> {
+        let mut array = match json {
+            Json::Array(array) => array.into_iter(),
+            json => return Err(E::custom(format!("expected a primitive array, found {}", json))),
+        };
+        let kind = array
+            .next()
+            .and_then(|kind| kind.as_u64())
+            .ok_or_else(|| E::custom("primitive is missing its kind"))?;
+        let mut next = || array.next().ok_or_else(|| E::custom("primitive is missing a value"));
+        let num = |kind, json| -> Result<Primitive, E> {
+            Ok(Primitive::Num(NumPrimitive {
+                kind,
+                value: NumberOrString::from_json(json)?,
+            }))
+        };
+        let variable = |r#type, name: Json, id: Json, x: Option<Json>, y: Option<Json>| {
+            let position = match (x.as_ref().and_then(Json::as_f64), y.as_ref().and_then(Json::as_f64)) {
+                (Some(x), Some(y)) => Some(Vec2(x, y)),
+                _ => None,
+            };
+            Ok(Primitive::Variable(VariablePrimitive {
+                name: from_json(name)?,
+                r#type,
+                id: from_json(id)?,
+                position,
+            }))
+        };
+        match kind {
+            4 => num(NumKind::Number, next()?),
+            5 => num(NumKind::PositiveNumber, next()?),
+            6 => num(NumKind::WholeNumber, next()?),
+            7 => num(NumKind::Integer, next()?),
+            8 => num(NumKind::Angle, next()?),
+            9 => {
+                let hex: String = from_json(next()?)?;
+                Color::from_hex(&hex)
+                    .map(Primitive::Color)
+                    .ok_or_else(|| E::custom(format!("invalid color: {}", hex)))
+            }
+            10 => Ok(Primitive::Text(NumberOrString::from_json(next()?)?)),
+            11 => variable(VariableType::Broadcast, next()?, next()?, None, None),
+            12 | 13 => {
+                let r#type = if kind == 12 { VariableType::Scalar } else { VariableType::List };
+                let name = next()?;
+                let id = next()?;
+                variable(r#type, name, id, array.next(), array.next())
+            }
+            kind => Err(E::custom(format!("unknown primitive kind: {}", kind))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Primitive {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Primitive::from_json(Json::deserialize(deserializer)?)
+    }
+}
+
+impl InputValue {
+    fn from_json<E: Error>(json: Json) -> Result<Option<InputValue>, E> {
+        Ok(match json {
+            Json::Null => None,
+            Json::String(id) => Some(InputValue::Block(id)),
+            json => Some(InputValue::Primitive(Primitive::from_json(json)?)),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Input {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut array = Vec::<Json>::deserialize(deserializer)?.into_iter();
+        let shadow = match array.next().and_then(|shadow| shadow.as_u64()) {
+            Some(1) => Shadow::UnObscured,
+            Some(2) => Shadow::None,
+            Some(3) => Shadow::Obscured,
+            shadow => return Err(D::Error::custom(format!("invalid input shadow: {:?}", shadow))),
+        };
+        let mut next = || InputValue::from_json(array.next().unwrap_or(Json::Null));
+        let value = next()?;
+        let obscured = next()?;
+        Ok(Input {
+            shadow,
+            value,
+            obscured,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for sb3::Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut array = Vec::<Json>::deserialize(deserializer)?.into_iter();
+        let value = match array.next() {
+            Some(Json::String(s)) => s,
+            Some(Json::Number(n)) => n.to_string(),
+            Some(Json::Bool(b)) => b.to_string(),
+            value => return Err(D::Error::custom(format!("invalid field value: {:?}", value))),
+        };
+        let id = match array.next() {
+            Some(Json::String(id)) => Some(id),
+            _ => None,
+        };
+        Ok(sb3::Field { value, id })
+    }
+}
+
+// [name, value] or [name, value, true] if it's a cloud variable
+struct ScalarVariable {
+    name: String,
+    value: ScalarValue,
+    on_cloud: bool,
+}
+
+impl<'de> Deserialize<'de> for ScalarVariable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut array = Vec::<Json>::deserialize(deserializer)?.into_iter();
+        let name = from_json(array.next().unwrap_or(Json::Null))?;
+        let value = ScalarValue::from_json(array.next().unwrap_or(Json::Null))?;
+        let on_cloud = array.next().and_then(|cloud| cloud.as_bool()).unwrap_or(false);
+        Ok(ScalarVariable { name, value, on_cloud })
+    }
+}
+
+#[derive(Deserialize)]
+struct List(String, Vec<ScalarValue>);
+
+// the mutation's array-like attributes are themselves json strings,
+// and the boolean ones are sometimes strings
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Mutation {
+    #[serde(default)]
+    tag_name: String,
+    #[serde(default)]
+    children: Vec<Json>,
+    proccode: Option<String>,
+    argumentids: Option<String>,
+    argumentnames: Option<String>,
+    argumentdefaults: Option<String>,
+    #[serde(default)]
+    warp: Json,
+    #[serde(default)]
+    hasnext: Json,
+}
+
+fn json_bool<E: Error>(json: Json) -> Result<Option<bool>, E> {
+    match json {
+        Json::Null => Ok(None),
+        Json::Bool(b) => Ok(Some(b)),
+        Json::String(s) => match s.as_str() {
+            "true" => Ok(Some(true)),
+            "false" => Ok(Some(false)),
+            "null" => Ok(None),
+            _ => Err(E::custom(format!("invalid boolean string: {}", s))),
+        },
+        json => Err(E::custom(format!("expected a boolean, found {}", json))),
+    }
+}
+
+fn json_string<T: DeserializeOwned, E: Error>(s: Option<String>) -> Result<Option<T>, E> {
+    s.map(|s| serde_json::from_str(&s).map_err(E::custom)).transpose()
+}
+
+impl<'de> Deserialize<'de> for sb3::Mutation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Mutation::deserialize(deserializer)?;
+        Ok(sb3::Mutation {
+            tag_name: raw.tag_name,
+            children: raw.children,
+            proc_code: raw.proccode,
+            argument_ids: json_string(raw.argumentids)?,
+            argument_names: json_string(raw.argumentnames)?,
+            argument_defaults: json_string(raw.argumentdefaults)?,
+            warp: json_bool(raw.warp)?,
+            has_next: json_bool(raw.hasnext)?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Block {
+    opcode: sb3::OpCode,
+    next: Option<String>,
+    parent: Option<String>,
+    comment: Option<String>,
+    #[serde(default)]
+    inputs: HashMap<String, Input>,
+    #[serde(default)]
+    fields: HashMap<String, sb3::Field>,
+    mutation: Option<sb3::Mutation>,
+    #[serde(default)]
+    top_level: bool,
+    #[serde(default)]
+    shadow: bool,
+    x: Option<f64>,
+    y: Option<f64>,
+}
+
+impl From<Block> for sb3::Block {
+    fn from(raw: Block) -> Self {
+        sb3::Block {
+            op_code: raw.opcode,
+            next: raw.next,
+            parent: raw.parent,
+            comment: raw.comment,
+            inputs: raw.inputs,
+            fields: raw.fields,
+            mutation: raw.mutation,
+            top_level: raw.top_level,
+            shadow: raw.shadow,
+            position: Vec2(raw.x.unwrap_or(0.0), raw.y.unwrap_or(0.0)),
+        }
+    }
+}
+
+// the blocks map also contains variable and list reporters dropped directly in the workspace
+enum BlockOrPrimitive {
+    Block(Box<sb3::Block>),
+    Primitive(VariablePrimitive),
+}
+
+impl<'de> Deserialize<'de> for BlockOrPrimitive {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = Json::deserialize(deserializer)?;
+        if json.is_object() {
+            return Ok(BlockOrPrimitive::Block(Box::new(from_json::<Block, _>(json)?.into())));
+        }
+        match Primitive::from_json(json)? {
+            Primitive::Variable(primitive) => Ok(BlockOrPrimitive::Primitive(primitive)),
+            primitive => Err(D::Error::custom(format!(
+                "top level primitive must be a variable or list, found {:?}",
+                primitive
+            ))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Comment {
+    block_id: Option<String>,
+    text: String,
+    #[serde(default)]
+    minimized: bool,
+    x: Option<f64>,
+    y: Option<f64>,
+    width: Option<f64>,
+    height: Option<f64>,
+}
+
+impl From<Comment> for sb3::Comment {
+    fn from(raw: Comment) -> Self {
+        sb3::Comment {
+            block: raw.block_id,
+            text: raw.text,
+            minimized: raw.minimized,
+            position: Vec2(raw.x.unwrap_or(0.0), raw.y.unwrap_or(0.0)),
+            size: Vec2(raw.width.unwrap_or(200.0), raw.height.unwrap_or(200.0)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Costume {
+    asset_id: String,
+    name: String,
+    data_format: String,
+    md5ext: Option<String>,
+    bitmap_resolution: Option<u32>,
+    rotation_center_x: Option<f64>,
+    rotation_center_y: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sound {
+    asset_id: String,
+    name: String,
+    data_format: String,
+    md5ext: Option<String>,
+    #[serde(default)]
+    rate: u32,
+    #[serde(default)]
+    sample_count: u32,
+}
+
+fn asset<F, E: Error>(
+    asset_id: String,
+    name: String,
+    data_format: String,
+    md5ext: Option<String>,
+    parse_format: impl FnOnce(&str) -> Option<F>,
+) -> Result<Asset<F>, E> {
+    let format = parse_format(&data_format.to_lowercase())
+        .ok_or_else(|| E::custom(format!("unknown asset data format: {}", data_format)))?;
+    let md5ext = md5ext.unwrap_or_else(|| format!("{}.{}", asset_id, data_format));
+    Ok(Asset {
+        asset_id: AssetId(asset_id),
+        data_format: format,
+        name,
+        md5ext,
+    })
+}
+
+impl<'de> Deserialize<'de> for sb3::Costume {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Costume::deserialize(deserializer)?;
+        let asset = asset(raw.asset_id, raw.name, raw.data_format, raw.md5ext, |format| {
+            Some(match format {
+                "png" => ImageDataFormat::PNG,
+                "svg" => ImageDataFormat::SVG,
+                "jpeg" => ImageDataFormat::JPEG,
+                "jpg" => ImageDataFormat::JPG,
+                "bmp" => ImageDataFormat::BMP,
+                "gif" => ImageDataFormat::GIF,
+                _ => return None,
+            })
+        })?;
+        Ok(sb3::Costume {
+            asset,
+            bitmap_resolution: raw.bitmap_resolution.unwrap_or(1),
+            rotation_center: Vec2(
+                raw.rotation_center_x.unwrap_or(0.0),
+                raw.rotation_center_y.unwrap_or(0.0),
+            ),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for sb3::Sound {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Sound::deserialize(deserializer)?;
+        let asset = asset(raw.asset_id, raw.name, raw.data_format, raw.md5ext, |format| {
+            Some(match format {
+                "wav" => AudioDataFormat::WAV,
+                "wave" => AudioDataFormat::WAVE,
+                "mp3" => AudioDataFormat::MP3,
+                _ => return None,
+            })
+        })?;
+        Ok(sb3::Sound {
+            asset,
+            rate: raw.rate,
+            sample_count: raw.sample_count,
+        })
+    }
+}
+
+fn default_volume() -> f64 {
+    100.0
+}
+
+fn default_tempo() -> f64 {
+    60.0
+}
+
+fn default_video_transparency() -> f64 {
+    50.0
+}
+
+fn default_size() -> f64 {
+    100.0
+}
+
+fn default_direction() -> f64 {
+    90.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// stage and sprite properties are all in the same object, distinguished by isStage
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct Target {
+    is_stage: bool,
+    name: String,
+    #[serde(default)]
+    variables: HashMap<String, ScalarVariable>,
+    #[serde(default)]
+    lists: HashMap<String, List>,
+    #[serde(default)]
+    broadcasts: HashMap<String, String>,
+    #[serde(default)]
+    blocks: HashMap<String, BlockOrPrimitive>,
+    #[serde(default)]
+    comments: HashMap<String, Comment>,
+    #[serde(default)]
+    current_costume: usize,
+    #[serde(default)]
+    costumes: Vec<sb3::Costume>,
+    #[serde(default)]
+    sounds: Vec<sb3::Sound>,
+    #[serde(default = "default_volume")]
+    volume: f64,
+    #[serde(default)]
+    layer_order: u32,
+
+    // stage only
+    #[serde(default = "default_tempo")]
+    tempo: f64,
+    #[serde(default = "default_video_transparency")]
+    video_transparency: f64,
+    video_state: Option<VideoState>,
+
+    // sprite only
+    #[serde(default = "default_true")]
+    visible: bool,
+    #[serde(default)]
+    x: f64,
+    #[serde(default)]
+    y: f64,
+    #[serde(default = "default_size")]
+    size: f64,
+    #[serde(default = "default_direction")]
+    direction: f64,
+    #[serde(default)]
+    draggable: bool,
+    rotation_style: Option<RotationStyle>,
+}
+
+impl Target {
+    fn into_target(self) -> sb3::Target {
+        let scalars = self.variables.into_iter().map(|(id, variable)| Variable {
+            id,
+            name: variable.name,
+            value: Value::Scalar(variable.value),
+            on_cloud: variable.on_cloud,
+        });
+        let lists = self.lists.into_iter().map(|(id, List(name, values))| Variable {
+            id,
+            name,
+            value: Value::List(values),
+            on_cloud: false,
+        });
+        let broadcasts = self.broadcasts.into_iter().map(|(id, name)| Variable {
+            id,
+            name: name.clone(),
+            value: Value::Broadcast(Broadcast { name }),
+            on_cloud: false,
+        });
+        let mut blocks = HashMap::new();
+        let mut top_level_primitives = HashMap::new();
+        for (id, block) in self.blocks {
+            match block {
+                BlockOrPrimitive::Block(block) => {
+                    blocks.insert(id, *block);
+                }
+                BlockOrPrimitive::Primitive(primitive) => {
+                    top_level_primitives.insert(id, primitive);
+                }
+            }
+        }
+        sb3::Target {
+            current_costume: self.current_costume,
+            blocks,
+            top_level_primitives,
+            variables: scalars.chain(lists).chain(broadcasts).collect(),
+            comments: self
+                .comments
+                .into_iter()
+                .map(|(id, comment)| (id, comment.into()))
+                .collect(),
+            costumes: self.costumes,
+            sounds: self.sounds,
+            volume: self.volume,
+        }
+    }
+
+    pub(super) fn into_stage<E: Error>(self) -> Result<sb3::Stage, E> {
+        if !self.is_stage {
+            return Err(E::custom(format!("{} is a sprite, not the stage", self.name)));
+        }
+        Ok(sb3::Stage {
+            tempo: self.tempo,
+            video_transparency: self.video_transparency,
+            video_state: self.video_state.unwrap_or(VideoState::On),
+            target: self.into_target(),
+        })
+    }
+
+    pub(super) fn into_sprite<E: Error>(self) -> Result<sb3::Sprite, E> {
+        if self.is_stage {
+            return Err(E::custom(format!("{} is the stage, not a sprite", self.name)));
+        }
+        Ok(sb3::Sprite {
+            name: self.name.clone(),
+            visible: self.visible,
+            position: Vec2(self.x, self.y),
+            size: self.size,
+            direction: self.direction,
+            draggable: self.draggable,
+            rotation_style: self.rotation_style.unwrap_or(RotationStyle::AllAround),
+            layer_order: self.layer_order,
+            target: self.into_target(),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for sb3::Targets {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (stages, sprites): (Vec<Target>, Vec<Target>) = Vec::<Target>::deserialize(deserializer)?
+            .into_iter()
+            .partition(|target| target.is_stage);
+        let mut stages = stages.into_iter();
+        let stage = match (stages.next(), stages.next()) {
+            (Some(stage), None) => stage.into_stage()?,
+            (None, _) => return Err(D::Error::custom("project has no stage")),
+            (Some(_), Some(_)) => return Err(D::Error::custom("project has more than one stage")),
+        };
+        let mut sprites = sprites
+            .into_iter()
+            .map(Target::into_sprite)
+            .collect::<Result<Vec<_>, _>>()?;
+        sprites.sort_by_key(|sprite| sprite.layer_order);
+        Ok(sb3::Targets { stage, sprites })
+    }
+}
+
+// a sprite3's sprite.json is just a single sprite target
+impl<'de> Deserialize<'de> for sb3::Sprite {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Target::deserialize(deserializer)?.into_sprite()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meta {
+    semver: SemVer,
+    #[serde(default)]
+    vm: Option<SemVer>,
+    #[serde(default)]
+    agent: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for sb3::Meta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Meta::deserialize(deserializer)?;
+        Ok(sb3::Meta {
+            version: raw.semver,
+            vm: raw.vm.unwrap_or_default(),
+            user_agent: raw.agent.unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,202 @@
+// touching, checked a pixel at a time against costumes drawn the way the renderer draws them, like
+// Scratch's CPU fallback
+//
+// an instance's silhouette is its costume drawn by itself, and only the part of it on the stage can
+// touch anything except the edge. these only need the stage and the costumes, so anything running
+// scripts against a stage can use them, and hidden instances can still touch, but not be touched
+
+use std::ptr;
+
+use tiny_skia::{FilterQuality, Pixmap, PixmapPaint, Point};
+
+use crate::scratch::ast::Sprite;
+use crate::scratch::runtime::render::{self, Renderer};
+use crate::scratch::runtime::stage::{Stage, HEIGHT, WIDTH};
+
+pub type Rgb = [u8; 3];
+
+// Scratch only compares the top 5 bits of red and green and the top 4 of blue, since colors get
+// blended when they're drawn
+fn color_matches(a: Rgb, b: Rgb) -> bool {
+    (a[0] ^ b[0]) & 0b1111_1000 == 0 && (a[1] ^ b[1]) & 0b1111_1000 == 0 && (a[2] ^ b[2]) & 0b1111_0000 == 0
+}
+
+// and the top 6 bits of each for the sprite's own color in color is touching color
+fn mask_matches(a: Rgb, b: Rgb) -> bool {
+    a.iter().zip(b.iter()).all(|(a, b)| (a ^ b) & 0b1111_1100 == 0)
+}
+
+fn rgb(pixmap: &Pixmap, x: i32, y: i32) -> Rgb {
+    let color = pixmap.pixel(x as u32, y as u32).unwrap().demultiply();
+    [color.red(), color.green(), color.blue()]
+}
+
+// an instance drawn by itself, over the stage pixels it covers
+struct Silhouette {
+    left: i32,
+    top: i32,
+    pixmap: Pixmap,
+}
+
+impl Silhouette {
+    // clipped to the stage and margin pixels past it, or none if nothing's left
+    fn new(renderer: &Renderer, stage: &Stage, instance: usize, margin: i32) -> Option<Silhouette> {
+        let instance = &stage.instances[instance];
+        let costume = renderer.costume(instance)?;
+        // the shape effects change what touches, but not the color effects, even ghost
+        let pixmap = costume.effected(&instance.effects, false)?;
+        let transform = render::transform(instance, costume);
+        let (width, height) = (pixmap.width() as f32, pixmap.height() as f32);
+        let mut corners = [
+            Point::from_xy(0., 0.),
+            Point::from_xy(width, 0.),
+            Point::from_xy(0., height),
+            Point::from_xy(width, height),
+        ];
+        transform.map_points(&mut corners);
+        let bound = |extent: fn(f32, f32) -> f32, coordinate: fn(&Point) -> f32| {
+            corners.iter().map(coordinate).fold(coordinate(&corners[0]), extent)
+        };
+        let left = (bound(f32::min, |p| p.x).floor() as i32).max(-margin);
+        let top = (bound(f32::min, |p| p.y).floor() as i32).max(-margin);
+        let right = (bound(f32::max, |p| p.x).ceil() as i32).min(WIDTH as i32 + margin);
+        let bottom = (bound(f32::max, |p| p.y).ceil() as i32).min(HEIGHT as i32 + margin);
+        if right <= left || bottom <= top {
+            return None;
+        }
+        let mut silhouette = Pixmap::new((right - left) as u32, (bottom - top) as u32)?;
+        // nearest, so edges are as sharp as the costume's
+        let paint = PixmapPaint {
+            quality: FilterQuality::Nearest,
+            ..PixmapPaint::default()
+        };
+        let transform = transform.post_translate(-left as f32, -top as f32);
+        silhouette.draw_pixmap(0, 0, Pixmap::as_ref(&pixmap), &paint, transform, None);
+        Some(Silhouette { left, top, pixmap: silhouette })
+    }
+
+    fn is_opaque(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (x - self.left, y - self.top);
+        // pixel only checks the index, so x would wrap onto the next row
+        let inside = x >= 0 && y >= 0 && x < self.pixmap.width() as i32 && y < self.pixmap.height() as i32;
+        inside && self.pixmap.pixel(x as u32, y as u32).is_some_and(|pixel| pixel.alpha() > 0)
+    }
+
+    // whether any stage pixel it covers at all is one the check is true for
+    fn any_opaque(&self, mut check: impl FnMut(i32, i32) -> bool) -> bool {
+        let width = self.pixmap.width() as usize;
+        self.pixmap
+            .pixels()
+            .iter()
+            .enumerate()
+            .filter(|(_, pixel)| pixel.alpha() > 0)
+            .any(|(i, _)| check(self.left + (i % width) as i32, self.top + (i / width) as i32))
+    }
+
+    // everything else as it's drawn, the pen included, behind and in front of it, over the same pixels
+    fn surroundings(&self, renderer: &Renderer, stage: &Stage, instance: usize) -> Pixmap {
+        let mut frame = Pixmap::new(self.pixmap.width(), self.pixmap.height()).unwrap();
+        frame.fill(tiny_skia::Color::WHITE);
+        let origin = (self.left, self.top);
+        renderer.draw(&mut frame, stage.stage(), origin);
+        renderer.draw_pen(&mut frame, origin);
+        for &layer in stage.layers.iter().filter(|&&layer| layer != instance) {
+            renderer.draw(&mut frame, &stage.instances[layer], origin);
+        }
+        frame
+    }
+}
+
+// the stage coordinates of the left, top, right and bottom of what's opaque in its costume, like
+// Scratch's tight bounds, or none if none of it is
+pub fn bounds(renderer: &Renderer, stage: &Stage, instance: usize) -> Option<(f64, f64, f64, f64)> {
+    let instance = &stage.instances[instance];
+    let costume = renderer.costume(instance)?;
+    let pixmap = costume.effected(&instance.effects, false)?;
+    // the corners of each row's opaque span are mapped onto the stage, rather than drawing it there,
+    // which could take a pixmap far bigger than the stage
+    let mut corners = Vec::new();
+    for (y, row) in pixmap.pixels().chunks_exact(pixmap.width() as usize).enumerate() {
+        let left = match row.iter().position(|pixel| pixel.alpha() > 0) {
+            Some(left) => left as f32,
+            None => continue,
+        };
+        let right = row.iter().rposition(|pixel| pixel.alpha() > 0).unwrap() as f32 + 1.;
+        let (top, bottom) = (y as f32, y as f32 + 1.);
+        corners.extend_from_slice(&[
+            Point::from_xy(left, top),
+            Point::from_xy(right, top),
+            Point::from_xy(left, bottom),
+            Point::from_xy(right, bottom),
+        ]);
+    }
+    if corners.is_empty() {
+        return None;
+    }
+    render::transform(instance, costume).map_points(&mut corners);
+    let (mut left, mut top, mut right, mut bottom) = (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for corner in &corners {
+        let (x, y) = (corner.x as f64, corner.y as f64);
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    let (half_width, half_height) = (WIDTH / 2., HEIGHT / 2.);
+    Some((left - half_width, half_height - top, right - half_width, half_height - bottom))
+}
+
+pub fn touching_mouse(renderer: &Renderer, stage: &Stage, instance: usize) -> bool {
+    let x = (WIDTH / 2. + stage.mouse.0).floor() as i32;
+    let y = (HEIGHT / 2. - stage.mouse.1).floor() as i32;
+    Silhouette::new(renderer, stage, instance, 0).is_some_and(|silhouette| silhouette.is_opaque(x, y))
+}
+
+// whether any of it is past the stage
+pub fn touching_edge(renderer: &Renderer, stage: &Stage, instance: usize) -> bool {
+    let silhouette = match Silhouette::new(renderer, stage, instance, 1) {
+        Some(silhouette) => silhouette,
+        None => return false,
+    };
+    let (width, height) = (WIDTH as i32, HEIGHT as i32);
+    silhouette.any_opaque(|x, y| x < 0 || y < 0 || x >= width || y >= height)
+}
+
+// whether it touches the sprite or any of its clones, other than itself
+pub fn touching_sprite(renderer: &Renderer, stage: &Stage, instance: usize, sprite: &Sprite) -> bool {
+    let silhouette = match Silhouette::new(renderer, stage, instance, 0) {
+        Some(silhouette) => silhouette,
+        None => return false,
+    };
+    stage
+        .instances
+        .iter()
+        .enumerate()
+        .filter(|&(other, candidate)| other != instance && candidate.visible && ptr::eq(candidate.sprite, sprite))
+        .filter_map(|(other, _)| Silhouette::new(renderer, stage, other, 0))
+        .any(|other| silhouette.any_opaque(|x, y| other.is_opaque(x, y)))
+}
+
+pub fn touching_color(renderer: &Renderer, stage: &Stage, instance: usize, color: Rgb) -> bool {
+    let silhouette = match Silhouette::new(renderer, stage, instance, 0) {
+        Some(silhouette) => silhouette,
+        None => return false,
+    };
+    let surroundings = silhouette.surroundings(renderer, stage, instance);
+    let (left, top) = (silhouette.left, silhouette.top);
+    silhouette.any_opaque(|x, y| color_matches(rgb(&surroundings, x - left, y - top), color))
+}
+
+// whether the parts of it that are the first color touch the second
+pub fn color_touching_color(renderer: &Renderer, stage: &Stage, instance: usize, mask: Rgb, color: Rgb) -> bool {
+    let silhouette = match Silhouette::new(renderer, stage, instance, 0) {
+        Some(silhouette) => silhouette,
+        None => return false,
+    };
+    let surroundings = silhouette.surroundings(renderer, stage, instance);
+    let (left, top) = (silhouette.left, silhouette.top);
+    silhouette.any_opaque(|x, y| {
+        mask_matches(rgb(&silhouette.pixmap, x - left, y - top), mask)
+            && color_matches(rgb(&surroundings, x - left, y - top), color)
+    })
+}
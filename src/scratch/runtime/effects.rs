@@ -0,0 +1,150 @@
+// Scratch's graphic effects, applied to a costume's pixels the way its sprite shader applies them
+//
+// the shape effects move where each pixel is sampled from, in texture coordinates from 0 to 1 across
+// the costume, and then the color effects change what was sampled. an effect that's 0 does nothing at
+// all, like in Scratch where its part of the shader isn't compiled in
+
+use tiny_skia::Pixmap;
+
+use crate::scratch::ast::instruction::property::VisualEffectType;
+
+const CENTER: f32 = 0.5;
+// what Scratch adds to alpha before dividing by it
+const EPSILON: f32 = 1e-3;
+
+fn effect(effects: &[f64; 7], effect: VisualEffectType) -> f32 {
+    let value = effects[effect as usize];
+    if value.is_finite() { value as f32 } else { 0. }
+}
+
+// whether anything changes where the costume's pixels are, which touching sees too
+pub fn is_distorted(effects: &[f64; 7]) -> bool {
+    let shape = [VisualEffectType::Fisheye, VisualEffectType::Whirl, VisualEffectType::Pixelate, VisualEffectType::Mosaic];
+    shape.iter().any(|&shape| effect(effects, shape) != 0.)
+}
+
+// whether anything changes their colors, which is only seen when they're drawn
+pub fn is_recolored(effects: &[f64; 7]) -> bool {
+    let colors = [VisualEffectType::Color, VisualEffectType::Brightness, VisualEffectType::Ghost];
+    colors.iter().any(|&color| effect(effects, color) != 0.)
+}
+
+// the costume with the effects, or only the shape effects unless colors is set, where size is the
+// costume's size on the stage at 100%
+pub fn apply(pixmap: &Pixmap, size: (f64, f64), effects: &[f64; 7], colors: bool) -> Pixmap {
+    let mut effected = if is_distorted(effects) {
+        distort(pixmap, size, effects)
+    } else {
+        pixmap.clone()
+    };
+    if colors && is_recolored(effects) {
+        recolor(&mut effected, effects);
+    }
+    effected
+}
+
+fn distort(pixmap: &Pixmap, size: (f64, f64), effects: &[f64; 7]) -> Pixmap {
+    // converted like Scratch converts them into uniforms
+    let fisheye = effect(effects, VisualEffectType::Fisheye);
+    let fisheye = if fisheye != 0. { Some(((fisheye + 100.) / 100.).max(0.)) } else { None };
+    let whirl = -effect(effects, VisualEffectType::Whirl).to_radians();
+    let pixelate = effect(effects, VisualEffectType::Pixelate).abs() / 10.;
+    let mosaic = effect(effects, VisualEffectType::Mosaic);
+    let mosaic = if mosaic != 0. { ((mosaic.abs() + 10.) / 10.).round().clamp(1., 512.) } else { 1. };
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let mut distorted = Pixmap::new(width, height).unwrap();
+    let source = pixmap.data();
+    for (i, to) in distorted.data_mut().chunks_exact_mut(4).enumerate() {
+        let (x, y) = ((i as u32 % width) as f32, (i as u32 / width) as f32);
+        let mut u = (x + 0.5) / width as f32;
+        let mut v = (y + 0.5) / height as f32;
+        if mosaic != 1. {
+            u = (u * mosaic).fract();
+            v = (v * mosaic).fract();
+        }
+        if pixelate != 0. {
+            let (cells_u, cells_v) = (size.0 as f32 / pixelate, size.1 as f32 / pixelate);
+            u = ((u * cells_u).floor() + CENTER) / cells_u;
+            v = ((v * cells_v).floor() + CENTER) / cells_v;
+        }
+        if whirl != 0. {
+            // strongest at the center and nothing past the radius
+            let (dx, dy) = (u - CENTER, v - CENTER);
+            let factor = (1. - (dx * dx + dy * dy).sqrt() / CENTER).max(0.);
+            let (sin, cos) = (whirl * factor * factor).sin_cos();
+            u = cos * dx + sin * dy + CENTER;
+            v = -sin * dx + cos * dy + CENTER;
+        }
+        if let Some(fisheye) = fisheye {
+            let (dx, dy) = ((u - CENTER) / CENTER, (v - CENTER) / CENTER);
+            let length = (dx * dx + dy * dy).sqrt();
+            if length > 0. {
+                let r = length.min(1.).powf(fisheye) * length.max(1.);
+                u = CENTER + r * dx / length * CENTER;
+                v = CENTER + r * dy / length * CENTER;
+            }
+        }
+        // nothing past the costume's edges
+        if (0. ..1.).contains(&u) && (0. ..1.).contains(&v) {
+            let from = ((v * height as f32) as usize * width as usize + (u * width as f32) as usize) * 4;
+            to.copy_from_slice(&source[from..from + 4]);
+        }
+    }
+    distorted
+}
+
+fn recolor(pixmap: &mut Pixmap, effects: &[f64; 7]) {
+    let color = effect(effects, VisualEffectType::Color) / 200.;
+    let brightness = effect(effects, VisualEffectType::Brightness).clamp(-100., 100.) / 100.;
+    let ghost = 1. - effect(effects, VisualEffectType::Ghost).clamp(0., 100.) / 100.;
+    for pixel in pixmap.data_mut().chunks_exact_mut(4) {
+        let alpha = pixel[3] as f32 / 255.;
+        let mut rgb = [pixel[0] as f32 / 255., pixel[1] as f32 / 255., pixel[2] as f32 / 255.];
+        if color != 0. || brightness != 0. {
+            for channel in &mut rgb {
+                *channel = (*channel / (alpha + EPSILON)).clamp(0., 1.);
+            }
+            if color != 0. {
+                let mut hsv = rgb_to_hsv(rgb);
+                // so that black, white and grays still change a little
+                if hsv[2] < 0.11 / 2. {
+                    hsv = [0., 1., 0.11 / 2.];
+                } else if hsv[1] < 0.09 {
+                    hsv = [0., 0.09, hsv[2]];
+                }
+                hsv[0] = (hsv[0] + color).rem_euclid(1.);
+                rgb = hsv_to_rgb(hsv);
+            }
+            if brightness != 0. {
+                for channel in &mut rgb {
+                    *channel = (*channel + brightness).clamp(0., 1.);
+                }
+            }
+            for channel in &mut rgb {
+                *channel *= alpha + EPSILON;
+            }
+        }
+        let alpha = alpha * ghost;
+        let to_byte = |channel: f32| (channel * 255.).round().max(0.).min(alpha * 255.) as u8;
+        let (r, g, b) = (to_byte(rgb[0] * ghost), to_byte(rgb[1] * ghost), to_byte(rgb[2] * ghost));
+        pixel.copy_from_slice(&[r, g, b, (alpha * 255.).round() as u8]);
+    }
+}
+
+// the shader's, which is branchless and a little off for grays
+fn rgb_to_hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let p = if g < b { [b, g, -1., 2. / 3.] } else { [g, b, 0., -1. / 3.] };
+    let q = if r < p[0] { [p[0], p[1], p[3], r] } else { [r, p[1], p[2], p[0]] };
+    let d = q[0] - q[3].min(q[1]);
+    [(q[2] + (q[3] - q[1]) / (6. * d + EPSILON)).abs(), d / (q[0] + EPSILON), q[0]]
+}
+
+fn hsv_to_rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let hue = [(h * 6. - 3.).abs() - 1., 2. - (h * 6. - 2.).abs(), 2. - (h * 6. - 4.).abs()];
+    let c = v * s;
+    let mut rgb = [0.; 3];
+    for (to, from) in rgb.iter_mut().zip(hue.iter()) {
+        *to = from.clamp(0., 1.) * c + v - c;
+    }
+    rgb
+}
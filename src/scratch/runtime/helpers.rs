@@ -0,0 +1,164 @@
+// what compiled code calls for anything it can't do inline, named by the constants in compile::llvm::codegen
+// lists are the stage's, and indices are 1-based like Scratch's
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+
+use crate::scratch::ast::instruction::function_call::FloatToFloatOp;
+use crate::scratch::runtime::stage::LIST_LIMIT;
+use crate::scratch::runtime::value::{self, Value};
+
+pub type RawList = *mut Vec<Value>;
+
+thread_local! {
+    // xorshift, like the interpreter's
+    static RANDOM: Cell<u64> = const { Cell::new(0x2545_f491_4f6c_dd1d) };
+}
+
+pub fn seed(seed: u64) {
+    // xorshift never leaves 0
+    RANDOM.with(|random| random.set(seed.max(1)));
+}
+
+// in [0, 1)
+fn random() -> f64 {
+    RANDOM.with(|random| {
+        let mut x = random.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        random.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+// whole numbers if both ends are, like the interpreter
+#[no_mangle]
+pub extern "C" fn scratch_random(from: f64, to: f64) -> f64 {
+    let (low, high) = if from <= to { (from, to) } else { (to, from) };
+    if low == high {
+        low
+    } else if from.fract() == 0. && to.fract() == 0. {
+        low + (random() * (high + 1. - low)).floor()
+    } else {
+        low + random() * (high - low)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn scratch_tan(n: f64) -> f64 {
+    value::float_to_float(FloatToFloatOp::Tan, n)
+}
+
+#[no_mangle]
+pub extern "C" fn scratch_length(n: f64) -> f64 {
+    value::number_to_string(n).chars().count() as f64
+}
+
+#[no_mangle]
+pub extern "C" fn scratch_contains(n: f64, part: f64) -> f64 {
+    let n = value::number_to_string(n).to_lowercase();
+    n.contains(&value::number_to_string(part).to_lowercase()) as u8 as f64
+}
+
+// the 0-based index, if it's in the list
+fn index(index: f64, len: usize) -> Option<usize> {
+    let index = index.floor();
+    if index < 1. || index > len as f64 {
+        None
+    } else {
+        Some(index as usize - 1)
+    }
+}
+
+fn position(list: &[Value], item: f64) -> Option<usize> {
+    let item = Value::Number(item);
+    list.iter().position(|other| value::compare(other, &item) == Ordering::Equal)
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_length(list: RawList) -> f64 {
+    (*list).len() as f64
+}
+
+/// items that aren't numbers are 0, like they are in arithmetic
+///
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_item(list: RawList, i: f64) -> f64 {
+    let list = &*list;
+    index(i, list.len()).map_or(0., |i| list[i].to_number())
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_find(list: RawList, item: f64) -> f64 {
+    position(&*list, item).map_or(0., |i| i as f64 + 1.)
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_contains(list: RawList, item: f64) -> f64 {
+    position(&*list, item).is_some() as u8 as f64
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_set(list: RawList, i: f64, item: f64) {
+    let list = &mut *list;
+    if let Some(i) = index(i, list.len()) {
+        list[i] = Value::Number(item);
+    }
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_insert(list: RawList, i: f64, item: f64) {
+    let list = &mut *list;
+    if let Some(i) = index(i, list.len() + 1).filter(|_| list.len() < LIST_LIMIT) {
+        list.insert(i, Value::Number(item));
+    }
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_delete(list: RawList, i: f64) {
+    let list = &mut *list;
+    if let Some(i) = index(i, list.len()) {
+        list.remove(i);
+    }
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_push(list: RawList, item: f64) {
+    let list = &mut *list;
+    if list.len() < LIST_LIMIT {
+        list.push(Value::Number(item));
+    }
+}
+
+/// # Safety
+///
+/// `list` must be a live `*mut Vec<Value>` that isn't aliased during the call.
+#[no_mangle]
+pub unsafe extern "C" fn scratch_list_clear(list: RawList) {
+    (*list).clear();
+}
@@ -0,0 +1,1708 @@
+// runs a Program directly on the headless stage, as the ground truth for compiled code
+// scripts run as threads the scheduler takes turns between a frame at a time like Scratch,
+// and the stage's clock only moves forward between frames
+//
+// each script runs as a coroutine that stops where Scratch would yield, so it can be picked up again,
+// and a compiled script can be switched to the interpreter at any state once its variables aren't numbers
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::scratch::ast::{Block, BroadCast, Event, GreaterThanProperty, Key, List, Program, Variable};
+use crate::scratch::ast::compute_kind::Analysis;
+use crate::scratch::ast::coroutine::{Coroutine, Op};
+use crate::scratch::ast::instruction::{GetInstruction, Instruction, ReadWriteInstruction, SetInstruction};
+use crate::scratch::ast::instruction::control_flow::{ControlFlowInstruction, StopInstruction};
+use crate::scratch::ast::instruction::function_call::{BinaryOp, CallInstruction, FunctionCallInstruction, LogicOp, MathOp, StringOp, UnaryOp};
+use crate::scratch::ast::instruction::list::{ListInstruction, PushPop, PushPopSide, ReturningListInstruction, VoidListInstruction};
+use crate::scratch::ast::instruction::property::{AudioEffectType, NamedProperty, NamedType, PropertyInstruction, RotationStyle, StageProperty, TimeUnit, TouchingObject, VisibleThing, VisualEffectType};
+use crate::scratch::ast::instruction::render::{RenderInstruction, SayOrThink, SoundInstruction};
+use crate::scratch::parse::project::sb3;
+use crate::scratch::runtime::collision;
+use crate::scratch::runtime::native::{Native, Resumed};
+use crate::scratch::runtime::render::{Costume, Renderer};
+use crate::scratch::runtime::scheduler::{self, Activation, Pending, Scheduler, Status, ThreadState};
+use crate::scratch::runtime::stage::{Bubble, Stage, HEIGHT, LIST_LIMIT, WIDTH};
+use crate::scratch::runtime::value::{self, Value};
+
+// deep enough for any reasonable recursive custom block, and shallow enough for the native stack
+const RECURSION_LIMIT: usize = 500;
+
+// why the interpreter stopped before every script finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    StepLimit,
+    RecursionLimit,
+}
+
+// whether the rest of the script should run
+enum Flow {
+    Continue,
+    Stop,
+    // "stop this script", which only returns from a custom block, like Scratch's stopThisScript
+    Return,
+}
+
+// xorshift, so runs are reproducible
+struct Random(u64);
+
+impl Random {
+    // in [0, 1)
+    fn next(&mut self) -> f64 {
+        let Random(x) = self;
+        *x ^= *x << 13;
+        *x ^= *x >> 7;
+        *x ^= *x << 17;
+        (*x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+enum ListIndex {
+    // 0-based
+    Index(usize),
+    All,
+    Invalid,
+}
+
+fn rotation_style(style: RotationStyle) -> sb3::RotationStyle {
+    match style {
+        RotationStyle::LeftRight => sb3::RotationStyle::LeftRight,
+        RotationStyle::DontRotate => sb3::RotationStyle::DontRotate,
+        RotationStyle::AllAround => sb3::RotationStyle::AllAround,
+    }
+}
+
+// whether setting the property changes what's on screen
+fn redraws(property: &PropertyInstruction) -> bool {
+    matches!(
+        property,
+        PropertyInstruction::Visibility(_)
+            | PropertyInstruction::Position()
+            | PropertyInstruction::Direction()
+            | PropertyInstruction::Size()
+            | PropertyInstruction::Named(_, _)
+            | PropertyInstruction::RotationStyle(_)
+            | PropertyInstruction::Layer()
+            | PropertyInstruction::VisualEffect(_)
+    )
+}
+
+// a color input without its alpha, which touching ignores
+fn rgb(color: &Value) -> collision::Rgb {
+    let [r, g, b, _] = color.to_rgba();
+    [r, g, b]
+}
+
+// the list operand of a list instruction
+fn list_of<'a>(value: &CallInstruction<'a>) -> Option<&'a List<'a>> {
+    match value {
+        CallInstruction::Id(GetInstruction::ReadWrite(ReadWriteInstruction::List(list))) => Some(*list),
+        _ => None,
+    }
+}
+
+// Scratch joins items with spaces, unless they're all single characters
+fn list_contents(items: &[Value]) -> String {
+    let items = items.iter().map(Value::to_string).collect::<Vec<_>>();
+    let separator = if items.iter().all(|item| item.chars().count() == 1) { "" } else { " " };
+    items.join(separator)
+}
+
+// in UTC, since the local time zone isn't known
+fn current_time(unit: TimeUnit) -> f64 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0., |since| since.as_millis() as f64);
+    let seconds = (millis / 1000.).floor();
+    let days = (seconds / 86400.).floor();
+    // the proleptic Gregorian calendar from days since 1970, by Howard Hinnant's algorithm
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let date = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    match unit {
+        TimeUnit::Second => seconds % 60.,
+        TimeUnit::Minute => (seconds / 60.).floor() % 60.,
+        TimeUnit::Hour => (seconds / 3600.).floor() % 24.,
+        // Sunday is 1, and 1970-01-01 was a Thursday
+        TimeUnit::DayOfWeek => ((days as i64 + 4).rem_euclid(7) + 1) as f64,
+        TimeUnit::Date => date as f64,
+        TimeUnit::Month => month as f64,
+        TimeUnit::Year => year as f64,
+        TimeUnit::DaysSince2000 => (millis - 946_684_800_000.) / 86_400_000.,
+    }
+}
+
+pub struct Interpreter<'p, 'a> {
+    program: &'p Program<'a>,
+    pub stage: Stage<'a>,
+    pub scheduler: Scheduler<'p, 'a>,
+    // the thread being run, and its instance
+    thread: u64,
+    instance: usize,
+    // the args of the custom blocks being run, innermost last
+    frames: Vec<Vec<(*const Variable<'a>, Value)>>,
+    random: Random,
+    steps: u64,
+    max_steps: u64,
+    halt: Option<Halt>,
+    answers: Box<dyn Iterator<Item = String> + 'p>,
+    // runs the custom blocks and scripts that were compiled instead
+    native: Option<Native<'a>>,
+    // the costumes, without which nothing is touching anything
+    renderer: Option<Renderer>,
+    // by block and whether it's in warp mode
+    coroutines: HashMap<(*const Block<'a>, bool), Rc<Coroutine<'p, 'a>>>,
+    analysis: Analysis<'a>,
+}
+
+impl<'p, 'a> Interpreter<'p, 'a> {
+    pub fn new(program: &'p Program<'a>, stage: Stage<'a>) -> Interpreter<'p, 'a> {
+        Interpreter {
+            program,
+            stage,
+            scheduler: Scheduler::new(),
+            thread: 0,
+            instance: 0,
+            frames: Vec::new(),
+            random: Random(0x2545_f491_4f6c_dd1d),
+            steps: 0,
+            max_steps: 10_000_000,
+            halt: None,
+            answers: Box::new(std::iter::empty()),
+            native: None,
+            renderer: None,
+            coroutines: HashMap::new(),
+            analysis: Analysis::new(),
+        }
+    }
+
+    // every instruction and loop iteration is a step
+    pub fn set_max_steps(&mut self, max_steps: u64) {
+        self.max_steps = max_steps;
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        // xorshift never leaves 0
+        self.random = Random(seed.max(1));
+    }
+
+    // what ask and wait is answered with, which is the empty string once they run out
+    pub fn set_answers(&mut self, answers: impl Iterator<Item = String> + 'p) {
+        self.answers = Box::new(answers);
+    }
+
+    // a line at a time, until stdin ends or can't be read
+    pub fn answer_from_stdin(&mut self) {
+        self.set_answers(BufReader::new(io::stdin()).lines().map_while(Result::ok));
+    }
+
+    pub fn set_native(&mut self, native: Native<'a>) {
+        self.native = Some(native);
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = Some(renderer);
+    }
+
+    pub fn renderer(&self) -> Option<&Renderer> {
+        self.renderer.as_ref()
+    }
+
+    pub fn program(&self) -> &'p Program<'a> {
+        self.program
+    }
+
+    fn coroutine(&mut self, block: &'p Block<'a>, warp: bool) -> Rc<Coroutine<'p, 'a>> {
+        let analysis = &mut self.analysis;
+        self.coroutines
+            .entry((block as *const _, warp))
+            .or_insert_with(|| Rc::new(Coroutine::new(block, warp, analysis)))
+            .clone()
+    }
+
+    // starts the scripts the trigger starts, with sprites in front first and the stage last like Scratch,
+    // and returns their threads
+    fn start(&mut self, triggered: impl Fn(&Event<'a>) -> bool) -> Vec<u64> {
+        let instances = self.stage.layers.iter().rev().copied().chain(std::iter::once(0)).collect::<Vec<_>>();
+        self.start_on(instances, triggered)
+    }
+
+    fn start_on(&mut self, instances: Vec<usize>, triggered: impl Fn(&Event<'a>) -> bool) -> Vec<u64> {
+        let program = self.program;
+        let mut started = Vec::new();
+        for instance in instances {
+            let sprite_scripts = self.stage.instances[instance].sprite_scripts;
+            for (index, script) in program.sprite_scripts()[sprite_scripts].scripts().iter().enumerate() {
+                if triggered(script.trigger()) {
+                    let coroutine = self.coroutine(script.block(), false);
+                    let restart = scheduler::restarts(script.trigger());
+                    started.extend(self.scheduler.start(instance, script, (sprite_scripts, index), coroutine, restart));
+                }
+            }
+        }
+        started
+    }
+
+    // starts the when greater than scripts whose property went from not greater than their value to
+    // greater than it, like Scratch's edge-activated hats, which it checks before each frame
+    fn start_greater_than(&mut self) {
+        let program = self.program;
+        let instances = self.stage.layers.iter().rev().copied().chain(std::iter::once(0)).collect::<Vec<_>>();
+        for instance in instances {
+            let sprite_scripts = self.stage.instances[instance].sprite_scripts;
+            for (index, script) in program.sprite_scripts()[sprite_scripts].scripts().iter().enumerate() {
+                let (property, value) = match script.trigger() {
+                    Event::GreaterThan(property, value) => (*property, value),
+                    _ => continue,
+                };
+                self.instance = instance;
+                let value = self.number(value);
+                let current = match property {
+                    // like Scratch without a microphone
+                    GreaterThanProperty::Loudness => -1.,
+                    GreaterThanProperty::Timer => self.stage.timer(),
+                };
+                let greater = current > value;
+                let was_greater = self.stage.instances[instance].greater_than.insert(index, greater);
+                if greater && was_greater != Some(true) {
+                    let coroutine = self.coroutine(script.block(), false);
+                    let restart = scheduler::restarts(script.trigger());
+                    self.scheduler.start(instance, script, (sprite_scripts, index), coroutine, restart);
+                }
+            }
+        }
+    }
+
+    pub fn trigger(&mut self, event: &Event<'a>) {
+        self.start(|trigger| trigger.is_triggered_by(event));
+    }
+
+    // runs frames until every thread has finished
+    pub fn run(&mut self) -> Result<(), Halt> {
+        while !self.scheduler.is_empty() {
+            self.frame()?;
+        }
+        Ok(())
+    }
+
+    // runs each thread until it yields, in rounds until something's redrawn or none of them yielded,
+    // and then moves the clock forward a frame
+    pub fn frame(&mut self) -> Result<(), Halt> {
+        self.scheduler.start_frame();
+        self.start_greater_than();
+        let work = self.steps + self.scheduler.work();
+        loop {
+            let mut yielded = false;
+            // threads are in the order they were started, so their ids go up, and ones started this round run in it too
+            let mut next = 0;
+            while let Some(position) = self.scheduler.threads().iter().position(|thread| thread.id >= next) {
+                let thread = &self.scheduler.threads()[position];
+                next = thread.id + 1;
+                let ready = match thread.status {
+                    Status::Done => false,
+                    Status::Yielded | Status::Waiting(None) => true,
+                    Status::Waiting(Some(until)) => self.stage.clock >= until,
+                };
+                if !ready {
+                    continue;
+                }
+                let (instance, index) = (thread.instance, thread.index);
+                let (id, mut state) = self.scheduler.take(position);
+                let status = self.resume(id, instance, index, &mut state);
+                if let Some(halt) = self.halt {
+                    return Err(halt);
+                }
+                yielded |= match status {
+                    Status::Yielded => true,
+                    Status::Waiting(Some(until)) => self.stage.clock >= until,
+                    _ => false,
+                };
+                self.scheduler.put(id, state, status);
+            }
+            if !yielded || !self.scheduler.can_continue() || self.steps >= work {
+                break;
+            }
+        }
+        self.stage.clock += self.scheduler.frame();
+        Ok(())
+    }
+
+    // every thread, and deletes the clones and resets what Scratch resets when the stop sign's clicked,
+    // which would halt sounds too, but there's nothing playing to halt besides the threads waiting for them
+    pub fn stop_all(&mut self) {
+        self.scheduler.stop_all();
+        self.scheduler.request_redraw();
+        self.stage.delete_clones();
+        for instance in &mut self.stage.instances {
+            instance.bubble = None;
+            instance.effects = [0.; 7];
+            instance.audio_effects = [0.; 2];
+        }
+    }
+
+    // like Scratch, the green flag stops everything first
+    pub fn start_green_flag(&mut self) {
+        self.stop_all();
+        self.trigger(&Event::FlagClicked);
+    }
+
+    pub fn green_flag(&mut self) -> Result<(), Halt> {
+        self.start_green_flag();
+        self.run()
+    }
+
+    fn run_compiled(&mut self, instance: usize, index: (usize, usize)) -> bool {
+        match &mut self.native {
+            Some(native) => native.run_script_on_stage(&mut self.stage, instance, index),
+            None => false,
+        }
+    }
+
+    // runs the thread until it yields, waits or finishes
+    fn resume(&mut self, id: u64, instance: usize, index: (usize, usize), thread: &mut ThreadState<'p, 'a>) -> Status {
+        self.thread = id;
+        self.instance = instance;
+        // a compiled script that can't yield runs all at once
+        let fresh = thread.activations.len() == 1 && thread.activations[0].state == 0;
+        if fresh && self.run_compiled(instance, index) {
+            return Status::Done;
+        }
+        self.frames = std::mem::take(&mut thread.frames);
+        let status = self.resume_activations(index, thread);
+        thread.frames = std::mem::take(&mut self.frames);
+        if self.halt.is_some() {
+            Status::Done
+        } else {
+            status
+        }
+    }
+
+    fn resume_activations(&mut self, index: (usize, usize), thread: &mut ThreadState<'p, 'a>) -> Status {
+        loop {
+            if let Some(status) = self.resume_compiled(index, thread) {
+                return status;
+            }
+            let activation = match thread.activations.last() {
+                Some(activation) => activation,
+                None => return Status::Done,
+            };
+            let (coroutine, state) = (activation.coroutine.clone(), activation.state);
+            match coroutine.ops().get(state) {
+                Some(op) => {
+                    if !self.step() {
+                        return Status::Done;
+                    }
+                    if let Some(status) = self.op(thread, op, coroutine.warp()) {
+                        return status;
+                    }
+                }
+                // a custom block's finished, so its caller goes on
+                None => {
+                    thread.activations.pop();
+                    self.frames.pop();
+                }
+            }
+        }
+    }
+
+    // runs a compiled script until it yields, waits or finishes, unless it wasn't compiled or can't be run here
+    fn resume_compiled(&mut self, index: (usize, usize), thread: &mut ThreadState<'p, 'a>) -> Option<Status> {
+        let native = self.native.as_mut()?;
+        if thread.activations.len() != 1 || thread.pending.is_some() {
+            return None;
+        }
+        let activation = &mut thread.activations[0];
+        let stage = &mut self.stage;
+        let resumed = native.resume_on_stage(stage, self.instance, index, &mut activation.state, &mut activation.slots)?;
+        self.step();
+        Some(match resumed {
+            Resumed::Done => Status::Done,
+            Resumed::Waiting(secs) if secs > 0. => Status::Waiting(Some(self.stage.clock + secs)),
+            Resumed::Waiting(_) => match activation.coroutine.ops().get(activation.state) {
+                Some(Op::WaitUntil(_)) => Status::Waiting(None),
+                _ => Status::Yielded,
+            },
+        })
+    }
+
+    // runs the op at the top activation's state, moving it along, and returns why the thread stopped if it did
+    fn op(&mut self, thread: &mut ThreadState<'p, 'a>, op: &Op<'p, 'a>, warp: bool) -> Option<Status> {
+        let activation = thread.activations.last_mut().unwrap();
+        let next = activation.state + 1;
+        match op {
+            Op::Exec(instruction) => {
+                activation.state = next;
+                match self.exec(instruction) {
+                    Flow::Continue => {}
+                    Flow::Stop => return Some(Status::Done),
+                    Flow::Return => return self.stop_this_script(thread),
+                }
+            }
+            Op::Unless(condition, target) => {
+                activation.state = if self.eval(condition).to_bool() { next } else { *target };
+            }
+            Op::Goto(target) => activation.state = *target,
+            Op::Times(slot, times) => {
+                activation.slots[*slot] = value::round(self.number(times));
+                activation.state = next;
+            }
+            // NaN times is none
+            Op::CountDown(slot, target) => {
+                if activation.slots[*slot] > 0. {
+                    activation.slots[*slot] -= 1.;
+                    activation.state = next;
+                } else {
+                    activation.state = *target;
+                }
+            }
+            Op::Yield => {
+                activation.state = next;
+                return Some(Status::Yielded);
+            }
+            // even waiting for no time yields
+            Op::Wait(duration) => {
+                activation.state = next;
+                let duration = self.number(duration).max(0.);
+                return Some(Status::Waiting(Some(self.stage.clock + duration)));
+            }
+            Op::WaitUntil(condition) => {
+                if !self.eval(condition).to_bool() {
+                    return Some(Status::Waiting(None));
+                }
+                activation.state = next;
+            }
+            Op::AskAndWait(question) => {
+                activation.state = next;
+                let question = self.eval(question).to_string();
+                println!("{} asks: {}", self.stage.instances[self.instance].sprite.name(), question);
+                self.stage.answer = self.answers.next().unwrap_or_default();
+            }
+            // until the threads it started have finished
+            Op::BroadcastAndWait(broadcast) => {
+                let started = match thread.pending.take() {
+                    Some(Pending::Broadcast(started)) => started,
+                    _ => self.broadcast(broadcast),
+                };
+                let thread_id = self.thread;
+                if started.iter().any(|&id| id != thread_id && self.scheduler.is_running(id)) {
+                    thread.pending = Some(Pending::Broadcast(started));
+                    return Some(Status::Waiting(None));
+                }
+                activation.state = next;
+            }
+            Op::Timed(render) => return self.timed(thread, render),
+            Op::Call(call) => {
+                activation.state = next;
+                if self.frames.len() >= RECURSION_LIMIT {
+                    self.halt.get_or_insert(Halt::RecursionLimit);
+                    return Some(Status::Done);
+                }
+                let function = call.function();
+                let args = call.args().iter().map(|arg| self.eval(arg)).collect::<Vec<_>>();
+                let coroutine = self.coroutine(function.body(), warp || function.warp());
+                // compiled custom blocks run all at once, so only ones that wouldn't yield here
+                if let (false, Some(native)) = (coroutine.yields(), &mut self.native) {
+                    if native.call_on_stage(&mut self.stage, self.instance, function, &args) {
+                        return None;
+                    }
+                }
+                let frame = function
+                    .params()
+                    .iter()
+                    .map(|param| param.variable() as *const _)
+                    .zip(args)
+                    .collect();
+                thread.activations.push(Activation::new(coroutine));
+                self.frames.push(frame);
+            }
+            Op::Stop => return self.stop_this_script(thread),
+        }
+        None
+    }
+
+    // returns from the custom block it's in, or stops the script if it's not in one
+    fn stop_this_script(&mut self, thread: &mut ThreadState<'p, 'a>) -> Option<Status> {
+        if thread.activations.len() == 1 {
+            return Some(Status::Done);
+        }
+        thread.activations.pop();
+        self.frames.pop();
+        None
+    }
+
+    // starts the render, or moves it along, and waits until it's done
+    fn timed(&mut self, thread: &mut ThreadState<'p, 'a>, render: &RenderInstruction<'a>) -> Option<Status> {
+        self.scheduler.request_redraw();
+        let clock = self.stage.clock;
+        let pending = match thread.pending.take() {
+            Some(pending) => pending,
+            None => match render {
+                RenderInstruction::GlideTo(secs, position) => {
+                    let secs = self.number(secs);
+                    let to = self.eval_vector(position);
+                    let instance = &self.stage.instances[self.instance];
+                    Pending::Glide { start: clock, secs, from: (instance.x, instance.y), to }
+                }
+                RenderInstruction::SayOrThink(kind, message, Some(secs)) => {
+                    let secs = self.number(secs);
+                    self.say(kind, message);
+                    Pending::Say { until: clock + secs.max(0.) }
+                }
+                // for as long as the sound is, or no time if it couldn't be loaded
+                RenderInstruction::SoundInstruction(SoundInstruction::Play, sound) => {
+                    let instance = &self.stage.instances[self.instance];
+                    let duration = self.stage.assets[instance.sprite_scripts].sounds.get(sound.name()).copied();
+                    Pending::Sound { until: clock + duration.unwrap_or(0.) }
+                }
+                _ => unreachable!(),
+            },
+        };
+        let done = match pending {
+            Pending::Say { until } if clock >= until => {
+                self.stage.instances[self.instance].bubble = None;
+                true
+            }
+            Pending::Sound { until } if clock >= until => true,
+            Pending::Say { until } | Pending::Sound { until } => {
+                thread.pending = Some(pending);
+                return Some(Status::Waiting(Some(until)));
+            }
+            // NaN seconds is no time
+            Pending::Glide { start, secs, from, to } => {
+                let t = (clock - start) / secs;
+                if secs > 0. && t < 1. {
+                    self.move_to(self.instance, from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+                    false
+                } else {
+                    self.move_to(self.instance, to.0, to.1);
+                    true
+                }
+            }
+            Pending::Broadcast(_) => unreachable!(),
+        };
+        if done {
+            thread.activations.last_mut().unwrap().state += 1;
+            None
+        } else {
+            thread.pending = Some(pending);
+            Some(Status::Waiting(None))
+        }
+    }
+
+    // false once the interpreter has halted
+    fn step(&mut self) -> bool {
+        self.steps += 1;
+        if self.steps > self.max_steps {
+            self.halt.get_or_insert(Halt::StepLimit);
+        }
+        self.halt.is_none()
+    }
+
+    fn exec_block(&mut self, block: &Block<'a>) -> Flow {
+        for instruction in block.instructions() {
+            match self.exec(instruction) {
+                Flow::Continue => {}
+                flow => return flow,
+            }
+        }
+        Flow::Continue
+    }
+
+    fn exec(&mut self, instruction: &Instruction<'a>) -> Flow {
+        if !self.step() {
+            return Flow::Stop;
+        }
+        match instruction {
+            Instruction::Op(CallInstruction::Function(call)) => return self.call(call),
+            Instruction::Op(op) => {
+                self.eval(op);
+            }
+            Instruction::Get(get) => {
+                self.get(get);
+            }
+            Instruction::Set(set) => self.set(set),
+            Instruction::List(ListInstruction::Void(op), list) => {
+                if let Some(list) = list_of(list) {
+                    self.list_instruction(op, list);
+                }
+            }
+            Instruction::List(ListInstruction::Returning(op), list) => {
+                if let Some(list) = list_of(list) {
+                    self.list_element(op, list);
+                }
+            }
+            Instruction::ControlFlow(control_flow) => return self.control_flow(control_flow),
+            Instruction::Render(render) => self.render(render),
+        }
+        if self.halt.is_some() {
+            Flow::Stop
+        } else {
+            Flow::Continue
+        }
+    }
+
+    fn call(&mut self, call: &FunctionCallInstruction<'a>) -> Flow {
+        if self.frames.len() >= RECURSION_LIMIT {
+            self.halt.get_or_insert(Halt::RecursionLimit);
+            return Flow::Stop;
+        }
+        let function = call.function();
+        let args = call.args().iter().map(|arg| self.eval(arg)).collect::<Vec<_>>();
+        if let Some(native) = &mut self.native {
+            if native.call_on_stage(&mut self.stage, self.instance, function, &args) {
+                return Flow::Continue;
+            }
+        }
+        let frame = function
+            .params()
+            .iter()
+            .map(|param| param.variable() as *const _)
+            .zip(args)
+            .collect();
+        self.frames.push(frame);
+        let flow = self.exec_block(function.body());
+        self.frames.pop();
+        match flow {
+            Flow::Return => Flow::Continue,
+            flow => flow,
+        }
+    }
+
+    fn eval(&mut self, call: &CallInstruction<'a>) -> Value {
+        match call {
+            CallInstruction::Id(get) => self.get(get),
+            CallInstruction::UnaryOp { op, value } => self.unary(*op, value),
+            CallInstruction::BinaryOp { op, left, right } => self.binary(*op, left, right),
+            // custom blocks don't report anything
+            CallInstruction::Function(call) => {
+                self.call(call);
+                Value::String(String::new())
+            }
+        }
+    }
+
+    fn number(&mut self, call: &CallInstruction<'a>) -> f64 {
+        self.eval(call).to_number()
+    }
+
+    // positions are only ever built from an x and a y, or read from a property
+    fn eval_vector(&mut self, call: &CallInstruction<'a>) -> (f64, f64) {
+        match call {
+            CallInstruction::BinaryOp { op: BinaryOp::Vector(), left, right } => (self.number(left), self.number(right)),
+            CallInstruction::Id(GetInstruction::ReadWrite(ReadWriteInstruction::Property(PropertyInstruction::Position()))) => {
+                let instance = &self.stage.instances[self.instance];
+                (instance.x, instance.y)
+            }
+            CallInstruction::Id(GetInstruction::ReadWrite(ReadWriteInstruction::Property(PropertyInstruction::MousePointer()))) => {
+                self.stage.mouse
+            }
+            _ => (self.number(call), 0.),
+        }
+    }
+
+    fn unary(&mut self, op: UnaryOp, value: &CallInstruction<'a>) -> Value {
+        match op {
+            UnaryOp::VectorIndex(index) => {
+                let (x, y) = self.eval_vector(value);
+                Value::Number(if index == 0 { x } else { y })
+            }
+            UnaryOp::Not() => Value::Bool(!self.eval(value).to_bool()),
+            UnaryOp::Abs() => Value::Number(self.number(value).abs()),
+            UnaryOp::Length() => Value::Number(self.eval(value).to_string().chars().count() as f64),
+            UnaryOp::FloatToInt(op) => Value::Number(value::float_to_int(op, self.number(value))),
+            UnaryOp::FloatToFloat(op) => Value::Number(value::float_to_float(op, self.number(value))),
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: &CallInstruction<'a>, right: &CallInstruction<'a>) -> Value {
+        match op {
+            BinaryOp::Math(MathOp::Distance) => {
+                let (x1, y1) = self.eval_vector(left);
+                let (x2, y2) = self.eval_vector(right);
+                return Value::Number((x1 - x2).hypot(y1 - y2));
+            }
+            BinaryOp::Vector() => return Value::Number(self.number(left)),
+            _ => {}
+        }
+        let left = self.eval(left);
+        let right = self.eval(right);
+        match op {
+            BinaryOp::Math(MathOp::Random) => Value::Number(self.random_between(&left, &right)),
+            BinaryOp::Math(op) => Value::Number(value::math(op, left.to_number(), right.to_number()).unwrap_or_default()),
+            BinaryOp::Comparison(op) => Value::Bool(value::comparison(op, &left, &right)),
+            BinaryOp::Logic(LogicOp::And) => Value::Bool(left.to_bool() && right.to_bool()),
+            BinaryOp::Logic(LogicOp::Or) => Value::Bool(left.to_bool() || right.to_bool()),
+            BinaryOp::String(StringOp::Join) => Value::String(format!("{}{}", left, right)),
+            BinaryOp::String(StringOp::LetterOf) => {
+                let index = left.to_number() - 1.;
+                let letter = if index >= 0. {
+                    right.to_string().chars().nth(index as usize)
+                } else {
+                    None
+                };
+                Value::String(letter.map(String::from).unwrap_or_default())
+            }
+            BinaryOp::String(StringOp::Contains) => {
+                let haystack = left.to_string().to_lowercase();
+                Value::Bool(haystack.contains(&right.to_string().to_lowercase()))
+            }
+            BinaryOp::Vector() => unreachable!(),
+        }
+    }
+
+    // whole numbers if both ends are written as integers, like Scratch
+    fn random_between(&mut self, from: &Value, to: &Value) -> f64 {
+        let (from_number, to_number) = (from.to_number(), to.to_number());
+        let (low, high) = if from_number <= to_number { (from_number, to_number) } else { (to_number, from_number) };
+        if low == high {
+            low
+        } else if from.is_int() && to.is_int() {
+            low + (self.random.next() * (high + 1. - low)).floor()
+        } else {
+            low + self.random.next() * (high - low)
+        }
+    }
+
+    fn get(&mut self, get: &GetInstruction<'a>) -> Value {
+        match get {
+            GetInstruction::Constant(constant) => Value::from(constant),
+            GetInstruction::ReadWrite(read_write) => self.read(read_write),
+            GetInstruction::Element(op, list) => match list_of(list) {
+                Some(list) => self.list_element(op, list),
+                None => Value::default(),
+            },
+        }
+    }
+
+    fn variable(&mut self, variable: &Variable<'a>) -> Option<&mut Value> {
+        let ptr = variable as *const _;
+        if let Some(frame) = self.frames.last_mut() {
+            if let Some(arg) = frame.iter_mut().find(|(param, _)| *param == ptr) {
+                return Some(&mut arg.1);
+            }
+        }
+        // a sprite's own variables shadow the stage's
+        let instance = self.instance;
+        if self.stage.instances[instance].variables.get(variable).is_some() {
+            self.stage.instances[instance].variables.get_mut(variable)
+        } else {
+            self.stage.instances[0].variables.get_mut(variable)
+        }
+    }
+
+    fn list(&mut self, list: &List<'a>) -> Option<&mut Vec<Value>> {
+        let instance = self.instance;
+        if self.stage.instances[instance].variables.list(list).is_some() {
+            self.stage.instances[instance].variables.list_mut(list)
+        } else {
+            self.stage.instances[0].variables.list_mut(list)
+        }
+    }
+
+    fn read(&mut self, read_write: &ReadWriteInstruction<'a>) -> Value {
+        match read_write {
+            ReadWriteInstruction::Variable(variable) => self.variable(variable).cloned().unwrap_or_default(),
+            ReadWriteInstruction::List(list) => {
+                Value::String(self.list(list).map(|items| list_contents(items)).unwrap_or_default())
+            }
+            ReadWriteInstruction::Property(property) => self.property(property),
+        }
+    }
+
+    fn named(&self, instance: usize, property: NamedProperty) -> Value {
+        match property {
+            NamedProperty::Number => Value::Number(self.stage.instances[instance].costume as f64 + 1.),
+            NamedProperty::Name => Value::String(self.stage.costume_name(instance).to_string()),
+        }
+    }
+
+    fn property(&mut self, property: &PropertyInstruction<'a>) -> Value {
+        if let PropertyInstruction::Touching(_)
+        | PropertyInstruction::TouchingColor(_)
+        | PropertyInstruction::ColorIsTouchingColor(_, _) = property
+        {
+            return Value::Bool(self.touching(property));
+        }
+        let instance = &self.stage.instances[self.instance];
+        match property {
+            PropertyInstruction::Visibility(VisibleThing::Sprite(_)) => Value::Bool(instance.visible),
+            // there aren't any monitors to show
+            PropertyInstruction::Visibility(_) => Value::Bool(false),
+            PropertyInstruction::Position() => Value::Number(instance.x),
+            PropertyInstruction::MousePointer() => Value::Number(self.stage.mouse.0),
+            PropertyInstruction::Direction() => Value::Number(instance.direction),
+            PropertyInstruction::Size() => Value::Number(value::round(instance.size)),
+            PropertyInstruction::Volume() => Value::Number(instance.volume),
+            // like Scratch without a microphone
+            PropertyInstruction::Loudness() => Value::Number(-1.),
+            PropertyInstruction::Timer() => Value::Number(self.stage.timer()),
+            PropertyInstruction::CurrentTime(unit) => Value::Number(current_time(*unit)),
+            PropertyInstruction::Answer() => Value::String(self.stage.answer.clone()),
+            PropertyInstruction::UserName() => Value::String(self.stage.user_name.clone()),
+            PropertyInstruction::Named(NamedType::Costume, property) => self.named(self.instance, *property),
+            PropertyInstruction::Named(NamedType::Backdrop, property) => self.named(0, *property),
+            PropertyInstruction::RotationStyle(style) => Value::Bool(instance.rotation_style == rotation_style(*style)),
+            PropertyInstruction::Draggable() => Value::Bool(instance.draggable),
+            PropertyInstruction::Layer() => Value::Number(self.stage.layer(self.instance) as f64),
+            PropertyInstruction::Stage(StageProperty::Backdrop(property)) => self.named(0, *property),
+            PropertyInstruction::Stage(StageProperty::Volume()) => Value::Number(self.stage.stage().volume),
+            PropertyInstruction::Stage(StageProperty::Variable(variable)) => {
+                self.stage.stage().variables.get(variable).cloned().unwrap_or_default()
+            }
+            PropertyInstruction::MouseDown() => Value::Bool(self.stage.mouse_down),
+            PropertyInstruction::KeyPressed(Key::Any) => Value::Bool(!self.stage.keys.is_empty()),
+            PropertyInstruction::KeyPressed(key) => Value::Bool(self.stage.keys.contains(key)),
+            PropertyInstruction::VisualEffect(effect) => Value::Number(instance.effects[*effect as usize]),
+            PropertyInstruction::AudioEffect(effect) => Value::Number(instance.audio_effects[*effect as usize]),
+            PropertyInstruction::Touching(_)
+            | PropertyInstruction::TouchingColor(_)
+            | PropertyInstruction::ColorIsTouchingColor(_, _) => unreachable!(),
+        }
+    }
+
+    fn touching(&mut self, property: &PropertyInstruction<'a>) -> bool {
+        // the colors are evaluated even when there's nothing to touch
+        let colors = match property {
+            PropertyInstruction::TouchingColor(color) => [rgb(&self.eval(color)), [0; 3]],
+            PropertyInstruction::ColorIsTouchingColor(color, touching) => {
+                [rgb(&self.eval(color)), rgb(&self.eval(touching))]
+            }
+            _ => [[0; 3]; 2],
+        };
+        let renderer = match &self.renderer {
+            Some(renderer) => renderer,
+            None => return false,
+        };
+        let (stage, instance) = (&self.stage, self.instance);
+        match property {
+            PropertyInstruction::Touching(TouchingObject::MousePointer) => {
+                collision::touching_mouse(renderer, stage, instance)
+            }
+            PropertyInstruction::Touching(TouchingObject::Edge) => collision::touching_edge(renderer, stage, instance),
+            PropertyInstruction::Touching(TouchingObject::Sprite(sprite)) => {
+                collision::touching_sprite(renderer, stage, instance, sprite)
+            }
+            PropertyInstruction::TouchingColor(_) => collision::touching_color(renderer, stage, instance, colors[0]),
+            _ => collision::color_touching_color(renderer, stage, instance, colors[0], colors[1]),
+        }
+    }
+
+    fn set(&mut self, set: &SetInstruction<'a>) {
+        match set.property() {
+            ReadWriteInstruction::Variable(variable) => {
+                let value = self.eval(set.value());
+                if let Some(slot) = self.variable(variable) {
+                    *slot = value;
+                }
+            }
+            // whole lists are never set
+            ReadWriteInstruction::List(_) => {}
+            ReadWriteInstruction::Property(PropertyInstruction::Position()) => {
+                let (x, y) = self.eval_vector(set.value());
+                self.scheduler.request_redraw();
+                if x.is_finite() && y.is_finite() {
+                    self.move_to(self.instance, x, y);
+                }
+            }
+            ReadWriteInstruction::Property(property) => {
+                let value = self.eval(set.value());
+                if redraws(property) {
+                    self.scheduler.request_redraw();
+                }
+                self.set_property(property, value);
+            }
+        }
+    }
+
+    fn set_property(&mut self, property: &PropertyInstruction<'a>, value: Value) {
+        let index = self.instance;
+        let instance = &mut self.stage.instances[index];
+        match property {
+            PropertyInstruction::Visibility(VisibleThing::Sprite(_)) => instance.visible = value.to_bool(),
+            PropertyInstruction::Direction() => instance.set_direction(value.to_number()),
+            PropertyInstruction::Size() => {
+                let size = value.to_number();
+                let costume = self.renderer.as_ref().and_then(|renderer| renderer.costume(instance));
+                instance.size = match costume.and_then(Costume::size) {
+                    // like Scratch's setSize, at least 5 across and at most 1.5 times the stage
+                    Some((width, height)) if !instance.sprite.is_stage() => {
+                        let min = (5. / width).max(5. / height).min(1.);
+                        let max = (1.5 * WIDTH / width).min(1.5 * HEIGHT / height);
+                        (size / 100.).max(min).min(max) * 100.
+                    }
+                    _ => size.max(0.),
+                };
+            }
+            PropertyInstruction::Volume() => instance.volume = value.to_number().clamp(0., 100.),
+            PropertyInstruction::Timer() => self.stage.timer_start = self.stage.clock - value.to_number(),
+            PropertyInstruction::Named(NamedType::Costume, _) => {
+                let assets = &self.stage.assets[instance.sprite_scripts];
+                if let Some(costume) = assets.costume(&value, instance.costume) {
+                    instance.costume = costume;
+                }
+            }
+            PropertyInstruction::Named(NamedType::Backdrop, _) => {
+                let stage = &mut self.stage.instances[0];
+                if let Some(backdrop) = self.stage.assets[0].costume(&value, stage.costume) {
+                    stage.costume = backdrop;
+                    let name = self.stage.costume_name(0).to_string();
+                    self.start(|trigger| match trigger {
+                        Event::BackdropSwitchesTo(backdrop) => *backdrop == name,
+                        _ => false,
+                    });
+                }
+            }
+            PropertyInstruction::RotationStyle(style) if value.to_bool() => {
+                instance.rotation_style = rotation_style(*style);
+            }
+            PropertyInstruction::Draggable() => instance.draggable = value.to_bool(),
+            PropertyInstruction::Layer() => self.stage.set_layer(index, value.to_number()),
+            PropertyInstruction::VisualEffect(effect) => {
+                let n = value.to_number();
+                instance.effects[*effect as usize] = match effect {
+                    VisualEffectType::Ghost => n.clamp(0., 100.),
+                    VisualEffectType::Brightness => n.clamp(-100., 100.),
+                    _ => n,
+                };
+            }
+            PropertyInstruction::AudioEffect(effect) => {
+                let n = value.to_number();
+                instance.audio_effects[*effect as usize] = match effect {
+                    AudioEffectType::Pitch => n.clamp(-360., 360.),
+                    AudioEffectType::PanLeftRight => n.clamp(-100., 100.),
+                };
+            }
+            // the rest are read only, or set by the user rather than scripts
+            _ => {}
+        }
+    }
+
+    // like Scratch's Cast.toListIndex
+    fn list_index(&mut self, index: &Value, len: usize, accept_all: bool) -> ListIndex {
+        if let Value::String(name) = index {
+            match name.as_str() {
+                "all" if accept_all => return ListIndex::All,
+                "all" => return ListIndex::Invalid,
+                "last" if len > 0 => return ListIndex::Index(len - 1),
+                "random" | "any" if len > 0 => {
+                    return ListIndex::Index((self.random.next() * len as f64) as usize);
+                }
+                "last" | "random" | "any" => return ListIndex::Invalid,
+                _ => {}
+            }
+        }
+        let index = index.to_number().floor();
+        if index < 1. || index > len as f64 {
+            ListIndex::Invalid
+        } else {
+            ListIndex::Index(index as usize - 1)
+        }
+    }
+
+    fn len(&mut self, list: &List<'a>) -> usize {
+        self.list(list).map_or(0, |items| items.len())
+    }
+
+    fn list_instruction(&mut self, op: &VoidListInstruction<'a>, list: &List<'a>) {
+        match op {
+            VoidListInstruction::Set(index, item) => {
+                let (index, item) = (self.eval(index), self.eval(item));
+                let len = self.len(list);
+                if let ListIndex::Index(index) = self.list_index(&index, len, false) {
+                    if let Some(items) = self.list(list) {
+                        items[index] = item;
+                    }
+                }
+            }
+            VoidListInstruction::Insert(index, item) => {
+                let (index, item) = (self.eval(index), self.eval(item));
+                let len = self.len(list);
+                if let ListIndex::Index(index) = self.list_index(&index, len + 1, false) {
+                    if let Some(items) = self.list(list).filter(|items| items.len() < LIST_LIMIT) {
+                        items.insert(index, item);
+                    }
+                }
+            }
+            VoidListInstruction::Remove(index) => {
+                let index = self.eval(index);
+                let len = self.len(list);
+                match self.list_index(&index, len, true) {
+                    ListIndex::Index(index) => {
+                        self.list(list).map(|items| items.remove(index));
+                    }
+                    ListIndex::All => {
+                        self.list(list).map(Vec::clear);
+                    }
+                    ListIndex::Invalid => {}
+                }
+            }
+            VoidListInstruction::Push(item, push) => {
+                let item = self.eval(item);
+                if let Some(items) = self.list(list).filter(|items| items.len() < LIST_LIMIT) {
+                    let index = match push.side() {
+                        PushPopSide::Left => (push.index() as usize).min(items.len()),
+                        PushPopSide::Right => items.len().saturating_sub(push.index() as usize),
+                    };
+                    items.insert(index, item);
+                }
+            }
+            VoidListInstruction::Clear() => {
+                self.list(list).map(Vec::clear);
+            }
+        }
+    }
+
+    fn pop(&mut self, pop: &PushPop, list: &List<'a>) -> Option<Value> {
+        let items = self.list(list)?;
+        let index = match pop.side() {
+            PushPopSide::Left => pop.index() as usize,
+            PushPopSide::Right => items.len().checked_sub(pop.index() as usize + 1)?,
+        };
+        if index < items.len() {
+            Some(items.remove(index))
+        } else {
+            None
+        }
+    }
+
+    fn list_element(&mut self, op: &ReturningListInstruction<'a>, list: &List<'a>) -> Value {
+        let equals = |a: &Value, b: &Value| value::compare(a, b) == Ordering::Equal;
+        match op {
+            ReturningListInstruction::Length() => Value::Number(self.len(list) as f64),
+            ReturningListInstruction::Get(index) => {
+                let index = self.eval(index);
+                let len = self.len(list);
+                match self.list_index(&index, len, false) {
+                    ListIndex::Index(index) => self.list(list).map(|items| items[index].clone()).unwrap_or_default(),
+                    _ => Value::String(String::new()),
+                }
+            }
+            ReturningListInstruction::Pop(pop) => self.pop(pop, list).unwrap_or_else(|| Value::String(String::new())),
+            ReturningListInstruction::Find(item) => {
+                let item = self.eval(item);
+                let index = self
+                    .list(list)
+                    .and_then(|items| items.iter().position(|other| equals(other, &item)))
+                    .map_or(0, |index| index + 1);
+                Value::Number(index as f64)
+            }
+            ReturningListInstruction::Contains(item) => {
+                let item = self.eval(item);
+                Value::Bool(self.list(list).is_some_and(|items| items.iter().any(|other| equals(other, &item))))
+            }
+            // these work on the list's contents as a string
+            ReturningListInstruction::ContainsSeq(value) => {
+                let value = self.eval(value).to_string().to_lowercase();
+                let contents = self.list(list).map(|items| list_contents(items)).unwrap_or_default();
+                Value::Bool(contents.to_lowercase().contains(&value))
+            }
+            ReturningListInstruction::Concat(value) => {
+                let value = self.eval(value);
+                let contents = self.list(list).map(|items| list_contents(items)).unwrap_or_default();
+                Value::String(format!("{}{}", contents, value))
+            }
+        }
+    }
+
+    fn control_flow(&mut self, control_flow: &ControlFlowInstruction<'a>) -> Flow {
+        match control_flow {
+            // only coroutines can wait for the receivers, so they're just started here
+            ControlFlowInstruction::BroadCast(_, broadcast) => {
+                self.broadcast(broadcast);
+            }
+            // only coroutines wait, a frame at a time, since the clock only moves between frames
+            ControlFlowInstruction::Wait(_) | ControlFlowInstruction::WaitUntil(_) => {}
+            ControlFlowInstruction::AskAndWait(question) => {
+                let question = self.eval(question).to_string();
+                println!("{} asks: {}", self.stage.instances[self.instance].sprite.name(), question);
+                self.stage.answer = self.answers.next().unwrap_or_default();
+            }
+            ControlFlowInstruction::Branch { condition, r#if, r#else } => {
+                let block = if self.eval(condition).to_bool() { r#if } else { r#else };
+                return self.exec_block(block);
+            }
+            ControlFlowInstruction::While(condition, body) => {
+                while self.eval(condition).to_bool() {
+                    if !self.step() {
+                        return Flow::Stop;
+                    }
+                    match self.exec_block(body) {
+                        Flow::Continue => {}
+                        flow => return flow,
+                    }
+                }
+            }
+            ControlFlowInstruction::Repeat(times, body) => {
+                let times = value::round(self.number(times));
+                let mut i = 0.;
+                while i < times {
+                    if !self.step() {
+                        return Flow::Stop;
+                    }
+                    match self.exec_block(body) {
+                        Flow::Continue => {}
+                        flow => return flow,
+                    }
+                    i += 1.;
+                }
+            }
+            ControlFlowInstruction::Stop(StopInstruction::All()) => {
+                self.stop_all();
+                return Flow::Stop;
+            }
+            ControlFlowInstruction::Stop(StopInstruction::OwnScript()) => return Flow::Return,
+            // only this instance's scripts, not its clones'
+            ControlFlowInstruction::Stop(StopInstruction::OtherScriptsInSprite(_)) => {
+                let (instance, id) = (self.instance, self.thread);
+                self.scheduler.stop(|thread| thread.instance == instance && thread.id != id);
+            }
+            // of this instance, which might be a clone, if it's of its own sprite, and otherwise of the original
+            ControlFlowInstruction::CreateClone(sprite) => {
+                let of = if std::ptr::eq(*sprite, self.stage.instances[self.instance].sprite) {
+                    self.instance
+                } else {
+                    match self.program.sprite_scripts_of(sprite) {
+                        Some(original) => original,
+                        None => return Flow::Continue,
+                    }
+                };
+                if let Some(clone) = self.stage.create_clone(of) {
+                    self.scheduler.request_redraw();
+                    self.start_on(vec![clone], |trigger| matches!(trigger, Event::StartAsClone));
+                }
+            }
+            // originals can't be deleted, so they go on
+            ControlFlowInstruction::DeleteSelf() => {
+                let instance = self.instance;
+                if self.stage.delete_clone(instance) {
+                    self.scheduler.request_redraw();
+                    self.scheduler.instance_deleted(instance);
+                    return Flow::Stop;
+                }
+            }
+        }
+        Flow::Continue
+    }
+
+    // starts the receivers, or restarts them if they're running, and returns their threads
+    fn broadcast(&mut self, broadcast: &'a BroadCast<'a>) -> Vec<u64> {
+        self.start(|trigger| match trigger {
+            Event::BroadcastReceived(received) => std::ptr::eq(*received, broadcast),
+            _ => false,
+        })
+    }
+
+    fn say(&mut self, kind: &SayOrThink, message: &CallInstruction<'a>) {
+        let text = self.eval(message).to_string();
+        let instance = &mut self.stage.instances[self.instance];
+        let think = match kind {
+            SayOrThink::Say => false,
+            SayOrThink::Think => true,
+        };
+        println!("{} {}: {}", instance.sprite.name(), if think { "thinks" } else { "says" }, text);
+        instance.bubble = if text.is_empty() { None } else { Some(Bubble { think, text }) };
+    }
+
+    fn render(&mut self, render: &RenderInstruction<'a>) {
+        match render {
+            RenderInstruction::SoundInstruction(_, _) | RenderInstruction::StopAllSounds() | RenderInstruction::ClearAudioEffects() => {}
+            // like Scratch, only drawing with the pen redraws
+            RenderInstruction::PenDown(false)
+            | RenderInstruction::SetPen(_, _)
+            | RenderInstruction::ChangePen(_, _)
+            | RenderInstruction::SetPenColor(_) => {}
+            _ => self.scheduler.request_redraw(),
+        }
+        match render {
+            // like Scratch's, with unrounded trig
+            RenderInstruction::MoveSteps(steps) => {
+                let steps = self.number(steps);
+                let instance = &self.stage.instances[self.instance];
+                let radians = (90. - instance.direction).to_radians();
+                let (x, y) = (instance.x + steps * radians.cos(), instance.y + steps * radians.sin());
+                if x.is_finite() && y.is_finite() {
+                    self.move_to(self.instance, x, y);
+                }
+            }
+            // coroutines glide, say for some seconds and play sounds until they're done a frame at a time,
+            // since the clock only moves between frames, so here they're over at once
+            RenderInstruction::GlideTo(_, position) => {
+                let (x, y) = self.eval_vector(position);
+                self.move_to(self.instance, x, y);
+            }
+            RenderInstruction::IfOnEdgeBounce() => self.bounce(),
+            RenderInstruction::SayOrThink(kind, message, secs) => {
+                self.say(kind, message);
+                if secs.is_some() {
+                    self.stage.instances[self.instance].bubble = None;
+                }
+            }
+            RenderInstruction::ClearVisualEffects() => self.stage.instances[self.instance].effects = [0.; 7],
+            // there's nothing to hear
+            RenderInstruction::SoundInstruction(_, _) | RenderInstruction::StopAllSounds() => {}
+            RenderInstruction::ClearAudioEffects() => self.stage.instances[self.instance].audio_effects = [0.; 2],
+            RenderInstruction::PenDown(down) => {
+                let instance = &mut self.stage.instances[self.instance];
+                instance.pen.down = *down;
+                // which draws a dot where it is
+                if let (true, Some(renderer)) = (*down, &mut self.renderer) {
+                    let at = (instance.x, instance.y);
+                    renderer.pen_line(&instance.pen, at, at);
+                }
+            }
+            RenderInstruction::SetPen(param, value) => {
+                let value = self.number(value);
+                self.stage.instances[self.instance].pen.set(*param, value);
+            }
+            RenderInstruction::ChangePen(param, change) => {
+                let change = self.number(change);
+                self.stage.instances[self.instance].pen.change(*param, change);
+            }
+            RenderInstruction::SetPenColor(color) => {
+                let color = self.eval(color).to_rgba();
+                self.stage.instances[self.instance].pen.set_color(color);
+            }
+            RenderInstruction::Stamp() => {
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.stamp(&self.stage.instances[self.instance]);
+                }
+            }
+            RenderInstruction::ClearPen() => {
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.clear_pen();
+                }
+            }
+        }
+    }
+
+    // and draws a line behind it if its pen is down
+    fn move_to(&mut self, index: usize, x: f64, y: f64) {
+        let instance = &mut self.stage.instances[index];
+        let from = (instance.x, instance.y);
+        instance.x = x;
+        instance.y = y;
+        if let (true, Some(renderer)) = (instance.pen.down, &mut self.renderer) {
+            renderer.pen_line(&instance.pen, from, (x, y));
+        }
+    }
+
+    // the stage coordinates of the left, top, right and bottom of its costume, or of its position if
+    // there's no renderer to know its costume's size
+    fn bounds(&self, index: usize) -> (f64, f64, f64, f64) {
+        let instance = &self.stage.instances[index];
+        self.renderer
+            .as_ref()
+            .and_then(|renderer| collision::bounds(renderer, &self.stage, index))
+            .unwrap_or((instance.x, instance.y, instance.x, instance.y))
+    }
+
+    fn bounce(&mut self) {
+        let (half_width, half_height) = (WIDTH / 2., HEIGHT / 2.);
+        let (left, top, right, bottom) = self.bounds(self.instance);
+        let distances = [
+            (half_width + left).max(0.),
+            (half_height - top).max(0.),
+            (half_width - right).max(0.),
+            (half_height + bottom).max(0.),
+        ];
+        let (nearest, distance) = distances
+            .iter()
+            .enumerate()
+            .fold((0, f64::INFINITY), |nearest, (edge, &distance)| {
+                if distance < nearest.1 { (edge, distance) } else { nearest }
+            });
+        if distance > 0. {
+            return;
+        }
+        let instance = &mut self.stage.instances[self.instance];
+        let radians = (90. - instance.direction).to_radians();
+        let (mut dx, mut dy) = (radians.cos(), radians.sin());
+        match nearest {
+            0 => dx = dx.abs().max(0.2),
+            1 => dy = -dy.abs().max(0.2),
+            2 => dx = -dx.abs().max(0.2),
+            _ => dy = dy.abs().max(0.2),
+        }
+        instance.set_direction(90. - dy.atan2(dx).to_degrees());
+        // then back onto the stage, turned, like keepInFence, which lets up to 15 of it stay past the edge
+        let (x, y) = (instance.x, instance.y);
+        let (left, top, right, bottom) = self.bounds(self.instance);
+        let inset = ((right - left).min(top - bottom).floor() / 2.).floor().min(15.);
+        let (mut dx, mut dy) = (0., 0.);
+        if left + inset < -half_width {
+            dx += -half_width - (left + inset);
+        }
+        if right - inset > half_width {
+            dx += half_width - (right - inset);
+        }
+        if top - inset > half_height {
+            dy += half_height - (top - inset);
+        }
+        if bottom + inset < -half_height {
+            dy += -half_height - (bottom + inset);
+        }
+        self.move_to(self.instance, x + dx, y + dy);
+    }
+
+    // the variables and lists of the stage and every sprite, by name, but not of their clones
+    pub fn variables_json(&self) -> serde_json::Value {
+        let mut targets = serde_json::Map::new();
+        for instance in self.stage.instances.iter().filter(|instance| !instance.is_clone) {
+            let sprite_scripts = &self.program.sprite_scripts()[instance.sprite_scripts];
+            let scope = if instance.sprite.is_stage() { self.program.globals() } else { sprite_scripts.locals() };
+            let variables = scope
+                .variables()
+                .iter()
+                .map(|variable| {
+                    let value = instance.variables.get(variable).cloned().unwrap_or_default();
+                    (variable.name().to_string(), value.to_json())
+                })
+                .collect::<serde_json::Map<_, _>>();
+            let lists = scope
+                .lists()
+                .iter()
+                .map(|list| {
+                    let items = instance.variables.list(list).map_or(&[][..], Vec::as_slice);
+                    let items = items.iter().map(Value::to_json).collect();
+                    (list.name().to_string(), serde_json::Value::Array(items))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            let mut target = serde_json::Map::new();
+            target.insert("variables".to_string(), serde_json::Value::Object(variables));
+            target.insert("lists".to_string(), serde_json::Value::Object(lists));
+            targets.insert(instance.sprite.name().to_string(), serde_json::Value::Object(target));
+        }
+        serde_json::Value::Object(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+    use serde_json::{json, Map, Value as Json};
+
+    use crate::scratch::ast::lower;
+    use crate::scratch::parse::project::archive::parse_project;
+    use crate::scratch::runtime::stage::Stage;
+
+    use super::Interpreter;
+
+    // a block like project.json has it, where variables, lists and broadcasts have their names as IDs
+    struct Block {
+        op_code: &'static str,
+        inputs: Vec<(&'static str, Input)>,
+        fields: Vec<(&'static str, &'static str)>,
+        mutation: Option<Json>,
+        shadow: bool,
+    }
+
+    enum Input {
+        Literal(&'static str),
+        Variable(&'static str),
+        Broadcast(&'static str),
+        Reporter(Block),
+        Menu(Block),
+        Substack(Vec<Block>),
+    }
+
+    fn block(op_code: &'static str) -> Block {
+        Block { op_code, inputs: Vec::new(), fields: Vec::new(), mutation: None, shadow: false }
+    }
+
+    impl Block {
+        fn input(mut self, name: &'static str, input: Input) -> Block {
+            self.inputs.push((name, input));
+            self
+        }
+
+        fn field(mut self, name: &'static str, value: &'static str) -> Block {
+            self.fields.push((name, value));
+            self
+        }
+
+        fn mutation(mut self, mutation: Json) -> Block {
+            self.mutation = Some(mutation);
+            self
+        }
+
+        fn shadow(mut self) -> Block {
+            self.shadow = true;
+            self
+        }
+    }
+
+    fn set(variable: &'static str, value: Input) -> Block {
+        block("data_setvariableto").field("VARIABLE", variable).input("VALUE", value)
+    }
+
+    fn change(variable: &'static str) -> Block {
+        block("data_changevariableby").field("VARIABLE", variable).input("VALUE", Input::Literal("1"))
+    }
+
+    // appends to the variable
+    fn join(variable: &'static str, s: &'static str) -> Block {
+        let join = block("operator_join").input("STRING1", Input::Variable(variable)).input("STRING2", Input::Literal(s));
+        set(variable, Input::Reporter(join))
+    }
+
+    fn item(index: &'static str, list: &'static str) -> Input {
+        Input::Reporter(block("data_itemoflist").input("INDEX", Input::Literal(index)).field("LIST", list))
+    }
+
+    fn repeat(times: Input, body: Vec<Block>) -> Block {
+        block("control_repeat").input("TIMES", times).input("SUBSTACK", Input::Substack(body))
+    }
+
+    fn create_clone() -> Block {
+        let menu = block("control_create_clone_of_menu").field("CLONE_OPTION", "_myself_").shadow();
+        block("control_create_clone_of").input("CLONE_OPTION", Input::Menu(menu))
+    }
+
+    fn stop_this_script() -> Block {
+        block("control_stop")
+            .field("STOP_OPTION", "this script")
+            .mutation(json!({"tagName": "mutation", "children": [], "hasnext": "false"}))
+    }
+
+    // a custom block without params
+    fn define(proc_code: &str, warp: bool, body: Vec<Block>) -> Vec<Block> {
+        let mutation = json!({
+            "tagName": "mutation", "children": [], "proccode": proc_code,
+            "argumentids": "[]", "argumentnames": "[]", "argumentdefaults": "[]", "warp": warp.to_string(),
+        });
+        let prototype = block("procedures_prototype").mutation(mutation).shadow();
+        let mut script = vec![block("procedures_definition").input("custom_block", Input::Menu(prototype))];
+        script.extend(body);
+        script
+    }
+
+    fn call(proc_code: &str) -> Block {
+        let mutation = json!({
+            "tagName": "mutation", "children": [], "proccode": proc_code, "argumentids": "[]", "warp": "false",
+        });
+        block("procedures_call").mutation(mutation)
+    }
+
+    #[derive(Default)]
+    struct Blocks {
+        blocks: Map<String, Json>,
+    }
+
+    impl Blocks {
+        fn add(&mut self, block: Block, parent: Option<&str>) -> String {
+            let id = format!("b{}", self.blocks.len());
+            self.blocks.insert(id.clone(), Json::Null);
+            let mut inputs = Map::new();
+            for (name, input) in block.inputs {
+                let input = match input {
+                    Input::Literal(s) => json!([1, [10, s]]),
+                    Input::Variable(name) => json!([3, [12, name, name], [10, ""]]),
+                    Input::Broadcast(name) => json!([1, [11, name, name]]),
+                    Input::Reporter(reporter) => json!([3, self.add(reporter, Some(&id)), [10, ""]]),
+                    Input::Menu(menu) => json!([1, self.add(menu, Some(&id))]),
+                    Input::Substack(stack) => match self.stack(stack, Some(&id)) {
+                        Some(first) => json!([2, first]),
+                        None => continue,
+                    },
+                };
+                inputs.insert(name.to_string(), input);
+            }
+            let fields = block.fields.iter().map(|(name, value)| (name.to_string(), json!([value, value])));
+            let mut json = json!({
+                "opcode": block.op_code,
+                "next": null,
+                "parent": parent,
+                "inputs": inputs,
+                "fields": fields.collect::<Map<_, _>>(),
+                "shadow": block.shadow,
+                "topLevel": parent.is_none(),
+            });
+            if parent.is_none() {
+                json["x"] = json!(0);
+                json["y"] = json!(0);
+            }
+            if let Some(mutation) = block.mutation {
+                json["mutation"] = mutation;
+            }
+            self.blocks.insert(id.clone(), json);
+            id
+        }
+
+        // one after another under the parent, returning the first's ID
+        fn stack(&mut self, stack: Vec<Block>, parent: Option<&str>) -> Option<String> {
+            let mut first = None;
+            let mut previous: Option<String> = None;
+            for block in stack {
+                let id = self.add(block, previous.as_deref().or(parent));
+                match &previous {
+                    Some(previous) => self.blocks[previous]["next"] = json!(id),
+                    None => first = Some(id.clone()),
+                }
+                previous = Some(id);
+            }
+            first
+        }
+    }
+
+    fn target(name: &str, variables: &[&str], lists: Json, scripts: Vec<Vec<Block>>) -> Json {
+        let mut blocks = Blocks::default();
+        for script in scripts {
+            blocks.stack(script, None);
+        }
+        let is_stage = name == "Stage";
+        let mut target = json!({
+            "isStage": is_stage,
+            "name": name,
+            "variables": variables.iter().map(|name| (name.to_string(), json!([name, 0]))).collect::<Map<_, _>>(),
+            "lists": lists,
+            "broadcasts": {},
+            "blocks": blocks.blocks,
+            "comments": {},
+            "currentCostume": 0,
+            "costumes": [{"assetId": "0", "name": "costume", "md5ext": "0.svg", "dataFormat": "svg", "rotationCenterX": 0, "rotationCenterY": 0}],
+            "sounds": [],
+            "volume": 100,
+            "layerOrder": if is_stage { 0 } else { 1 },
+        });
+        let extra = if is_stage {
+            json!({"tempo": 60, "videoTransparency": 50, "videoState": "on", "textToSpeechLanguage": null})
+        } else {
+            json!({"visible": true, "x": 0, "y": 0, "size": 100, "direction": 90, "draggable": false, "rotationStyle": "all around"})
+        };
+        target.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        target
+    }
+
+    // from the green flag until every script's done, and then the variables and lists by target
+    fn run(targets: Vec<Json>) -> Json {
+        let project = json!({
+            "targets": targets,
+            "monitors": [],
+            "extensions": [],
+            "meta": {"semver": "3.0.0", "vm": "0.2.0", "agent": ""},
+        });
+        let project = parse_project(project.to_string().as_bytes(), 3).unwrap();
+        let bump = Bump::new();
+        let lowered = lower::lower(&bump, &project);
+        assert_eq!(lowered.errors, vec![]);
+        let stage = Stage::new(&lowered.program, &project);
+        let mut interpreter = Interpreter::new(&lowered.program, stage);
+        interpreter.green_flag().unwrap();
+        interpreter.variables_json()
+    }
+
+    fn flag(mut script: Vec<Block>) -> Vec<Block> {
+        script.insert(0, block("event_whenflagclicked"));
+        script
+    }
+
+    #[test]
+    fn indexes_lists_like_scratch() {
+        let lists = json!({"L": ["L", ["a", "b", "c"]], "M": ["M", ["only"]]});
+        let script = flag(vec![
+            set("a", item("last", "L")),
+            set("b", item("0", "L")),
+            set("c", item("1.9", "L")),
+            set("d", item("all", "L")),
+            set("e", item("random", "M")),
+            block("data_replaceitemoflist")
+                .input("INDEX", Input::Literal("4"))
+                .input("ITEM", Input::Literal("x"))
+                .field("LIST", "L"),
+            block("data_insertatlist")
+                .input("ITEM", Input::Literal("d"))
+                .input("INDEX", Input::Literal("last"))
+                .field("LIST", "L"),
+            block("data_deleteoflist").input("INDEX", Input::Literal("all")).field("LIST", "M"),
+            set("f", item("last", "M")),
+            block("data_deleteoflist").input("INDEX", Input::Literal("5")).field("LIST", "L"),
+        ]);
+        let stage = target("Stage", &["a", "b", "c", "d", "e", "f"], lists, vec![script]);
+        let json = run(vec![stage]);
+        assert_eq!(
+            json["Stage"]["variables"],
+            json!({"a": "c", "b": "", "c": "a", "d": "", "e": "only", "f": ""}),
+        );
+        assert_eq!(json["Stage"]["lists"], json!({"L": ["a", "b", "c", "d"], "M": []}));
+    }
+
+    #[test]
+    fn repeats_like_scratch() {
+        let nan = block("operator_divide").input("NUM1", Input::Literal("0")).input("NUM2", Input::Literal("0"));
+        let script = flag(vec![
+            repeat(Input::Literal("2.5"), vec![change("a")]),
+            repeat(Input::Literal("2.4"), vec![change("b")]),
+            repeat(Input::Literal("abc"), vec![change("c")]),
+            repeat(Input::Reporter(nan), vec![change("d")]),
+            repeat(Input::Literal("-1"), vec![change("e")]),
+        ]);
+        let stage = target("Stage", &["a", "b", "c", "d", "e"], json!({}), vec![script]);
+        let json = run(vec![stage]);
+        assert_eq!(json["Stage"]["variables"], json!({"a": 3, "b": 2, "c": 0, "d": 0, "e": 0}));
+    }
+
+    #[test]
+    fn broadcasts_and_waits() {
+        let script = flag(vec![
+            set("log", Input::Literal("a")),
+            block("event_broadcastandwait").input("BROADCAST_INPUT", Input::Broadcast("go")),
+            join("log", "d"),
+        ]);
+        let stage = target("Stage", &["log"], json!({}), vec![script]);
+        let received = || block("event_whenbroadcastreceived").field("BROADCAST_OPTION", "go");
+        let wait = |secs| block("control_wait").input("DURATION", Input::Literal(secs));
+        let sprite = target(
+            "A",
+            &[],
+            json!({}),
+            vec![
+                vec![received(), join("log", "b"), wait("0"), join("log", "c")],
+                vec![received(), wait("0.1"), join("log", "e")],
+            ],
+        );
+        let json = run(vec![stage, sprite]);
+        assert_eq!(json["Stage"]["variables"]["log"], json!("abced"));
+    }
+
+    #[test]
+    fn stops_only_the_custom_block() {
+        // whether it yields or runs all at once
+        let body = || vec![join("log", "a"), repeat(Input::Literal("3"), vec![join("log", "b"), stop_this_script()]), join("log", "x")];
+        let f = define("f", false, body());
+        let g = define("g", true, body());
+        let script = flag(vec![set("log", Input::Literal("")), call("f"), join("log", "c"), call("g"), join("log", "c")]);
+        let stage = target("Stage", &["log"], json!({}), vec![]);
+        let sprite = target("A", &[], json!({}), vec![f, g, script]);
+        let json = run(vec![stage, sprite]);
+        assert_eq!(json["Stage"]["variables"]["log"], json!("abcabc"));
+    }
+
+    #[test]
+    fn limits_clones() {
+        let script = flag(vec![repeat(Input::Literal("310"), vec![create_clone()])]);
+        let clone = vec![block("control_start_as_clone"), change("n")];
+        let stage = target("Stage", &["n"], json!({}), vec![]);
+        let sprite = target("A", &[], json!({}), vec![script, clone]);
+        let json = run(vec![stage, sprite]);
+        assert_eq!(json["Stage"]["variables"]["n"], json!(300));
+    }
+
+    #[test]
+    fn deletes_clones() {
+        let script = flag(vec![repeat(Input::Literal("310"), vec![create_clone()])]);
+        let clone = vec![block("control_start_as_clone"), change("n"), block("control_delete_this_clone"), change("m")];
+        let stage = target("Stage", &["n", "m"], json!({}), vec![]);
+        let sprite = target("A", &[], json!({}), vec![script, clone]);
+        let json = run(vec![stage, sprite]);
+        assert_eq!(json["Stage"]["variables"], json!({"n": 310, "m": 0}));
+    }
+}
@@ -0,0 +1,10 @@
+pub mod value;
+pub mod helpers;
+pub mod native;
+pub mod pen;
+pub mod stage;
+pub mod effects;
+pub mod render;
+pub mod collision;
+pub mod scheduler;
+pub mod interpreter;
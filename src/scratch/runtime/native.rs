@@ -0,0 +1,267 @@
+// natively compiled custom blocks and scripts, however they were compiled, and running them on the stage
+//
+// compiled code only knows numbers, so variables are copied from the stage into the globals struct
+// before each call and the ones it changed are copied back after, and calls fall back to the interpreter
+// when a variable or arg isn't a number
+
+use std::any::Any;
+use std::collections::HashMap;
+
+use crate::scratch::ast::{Function, List, Program, Variable};
+use crate::scratch::runtime::helpers::RawList;
+use crate::scratch::runtime::stage::Stage;
+use crate::scratch::runtime::value::Value;
+
+// takes the globals struct and the args
+pub type Entry = unsafe extern "C" fn(*mut u64, *const f64);
+// takes the globals struct
+pub type ScriptEntry = unsafe extern "C" fn(*mut u64);
+// takes the globals struct, the coroutine's state and its slots, and returns how many seconds to wait,
+// unless it's finished and set its state to DONE
+pub type CoroutineEntry = unsafe extern "C" fn(*mut u64, *mut i64, *mut f64) -> f64;
+
+// the state a compiled coroutine is left in once it's finished
+pub const DONE: i64 = -1;
+
+// why a compiled coroutine returned
+pub enum Resumed {
+    // for how many seconds, where none is a yield
+    Waiting(f64),
+    Done,
+}
+
+// the variables in the globals struct, globals first and then each sprite's locals
+pub fn globals<'a>(program: &Program<'a>) -> Vec<&'a Variable<'a>> {
+    let locals = program
+        .sprite_scripts()
+        .iter()
+        .filter(|sprite_scripts| !sprite_scripts.sprite().is_stage())
+        .flat_map(|sprite_scripts| sprite_scripts.locals().variables());
+    program.globals().variables().iter().chain(locals).collect()
+}
+
+// the lists in the globals struct, after the variables and in the same order
+pub fn lists<'a>(program: &Program<'a>) -> Vec<&'a List<'a>> {
+    let locals = program
+        .sprite_scripts()
+        .iter()
+        .filter(|sprite_scripts| !sprite_scripts.sprite().is_stage())
+        .flat_map(|sprite_scripts| sprite_scripts.locals().lists());
+    program.globals().lists().iter().chain(locals).collect()
+}
+
+pub struct Native<'a> {
+    variables: Vec<&'a Variable<'a>>,
+    list_variables: Vec<&'a List<'a>>,
+    functions: HashMap<*const Function<'a>, Entry>,
+    // by sprite scripts index and script index
+    scripts: HashMap<(usize, usize), ScriptEntry>,
+    // the scripts that yield, with the same states and slots as their ast::coroutine::Coroutine
+    coroutines: HashMap<(usize, usize), CoroutineEntry>,
+    // the globals struct, with doubles as their bits and lists as pointers, since both are 64 bits
+    globals: Vec<u64>,
+    // what the lists point to when the globals aren't the stage's
+    lists: Vec<Vec<Value>>,
+    // whatever the code lives in, like a JIT
+    owner: Option<Box<dyn Any>>,
+}
+
+impl<'a> Native<'a> {
+    pub fn new(
+        program: &Program<'a>,
+        functions: Vec<(&'a Function<'a>, Entry)>,
+        scripts: Vec<((usize, usize), ScriptEntry)>,
+        coroutines: Vec<((usize, usize), CoroutineEntry)>,
+    ) -> Native<'a> {
+        let variables = globals(program);
+        let list_variables = lists(program);
+        let globals = vec![0f64.to_bits(); variables.len() + list_variables.len()];
+        let lists = vec![Vec::new(); list_variables.len()];
+        let mut native = Native {
+            variables,
+            list_variables,
+            functions: functions
+                .into_iter()
+                .map(|(function, entry)| (function as *const _, entry))
+                .collect(),
+            scripts: scripts.into_iter().collect(),
+            coroutines: coroutines.into_iter().collect(),
+            globals,
+            lists,
+            owner: None,
+        };
+        native.point_to_own_lists();
+        native
+    }
+
+    // keeps the code's owner alive as long as the code can be called
+    pub fn owned_by(mut self, owner: Box<dyn Any>) -> Native<'a> {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn is_compiled(&self, function: &Function<'a>) -> bool {
+        self.functions.contains_key(&(function as *const _))
+    }
+
+    fn point_to_own_lists(&mut self) {
+        let offset = self.variables.len();
+        for (index, list) in self.lists.iter_mut().enumerate() {
+            self.globals[offset + index] = list as RawList as u64;
+        }
+    }
+
+    fn field(&self, variable: &Variable<'a>) -> Option<usize> {
+        self.variables.iter().position(|&other| std::ptr::eq(other, variable))
+    }
+
+    pub fn global(&self, variable: &Variable<'a>) -> Option<f64> {
+        self.field(variable).map(|index| f64::from_bits(self.globals[index]))
+    }
+
+    pub fn set_global(&mut self, variable: &Variable<'a>, value: f64) -> bool {
+        self.field(variable).map(|index| self.globals[index] = value.to_bits()).is_some()
+    }
+
+    pub fn list(&self, list: &List<'a>) -> Option<&[Value]> {
+        let index = self.list_variables.iter().position(|&other| std::ptr::eq(other, list))?;
+        Some(&self.lists[index])
+    }
+
+    pub fn list_mut(&mut self, list: &List<'a>) -> Option<&mut Vec<Value>> {
+        let index = self.list_variables.iter().position(|&other| std::ptr::eq(other, list))?;
+        Some(&mut self.lists[index])
+    }
+
+    // calls a compiled custom block on the globals as they are, or returns false if it wasn't compiled
+    pub fn call(&mut self, function: &Function<'a>, args: &[f64]) -> bool {
+        match self.functions.get(&(function as *const _)) {
+            Some(entry) if args.len() == function.params().len() => {
+                unsafe { entry(self.globals.as_mut_ptr(), args.as_ptr()) };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // runs a compiled script, by sprite scripts index and script index, on the globals as they are
+    pub fn run_script(&mut self, script: (usize, usize)) -> bool {
+        match self.scripts.get(&script) {
+            Some(entry) => {
+                unsafe { entry(self.globals.as_mut_ptr()) };
+                true
+            }
+            None => false,
+        }
+    }
+
+    // resumes a compiled coroutine on the globals as they are, or returns None if it wasn't compiled
+    pub fn resume(&mut self, script: (usize, usize), state: &mut usize, slots: &mut [f64]) -> Option<Resumed> {
+        let entry = self.coroutines.get(&script)?;
+        let mut raw_state = *state as i64;
+        let secs = unsafe { entry(self.globals.as_mut_ptr(), &mut raw_state, slots.as_mut_ptr()) };
+        if raw_state == DONE {
+            return Some(Resumed::Done);
+        }
+        *state = raw_state as usize;
+        Some(Resumed::Waiting(secs))
+    }
+
+    // points the globals at the instance's variables and the stage's, or returns false if one or a list
+    // item isn't a number
+    fn load(&mut self, stage: &mut Stage<'a>, instance: usize) -> bool {
+        for (index, &variable) in self.variables.iter().enumerate() {
+            // another sprite's locals can't be touched by this instance's code
+            let value = stage.instances[instance]
+                .variables
+                .get(variable)
+                .or_else(|| stage.instances[0].variables.get(variable));
+            match value {
+                Some(Value::Number(n)) => self.globals[index] = n.to_bits(),
+                Some(_) => return false,
+                None => {}
+            }
+        }
+        let offset = self.variables.len();
+        for (index, &list) in self.list_variables.iter().enumerate() {
+            let items = if stage.instances[instance].variables.list(list).is_some() {
+                stage.instances[instance].variables.list_mut(list)
+            } else {
+                stage.instances[0].variables.list_mut(list)
+            };
+            if let Some(items) = items {
+                // items are read as numbers too
+                if items.iter().any(|item| !matches!(item, Value::Number(_))) {
+                    return false;
+                }
+                self.globals[offset + index] = items as RawList as u64;
+            }
+        }
+        true
+    }
+
+    // copies back the variables that changed, so the rest keep their types
+    fn store(&mut self, stage: &mut Stage<'a>, instance: usize, before: &[u64]) {
+        for (index, &variable) in self.variables.iter().enumerate() {
+            if self.globals[index] == before[index] {
+                continue;
+            }
+            let instance = if stage.instances[instance].variables.get(variable).is_some() { instance } else { 0 };
+            if let Some(value) = stage.instances[instance].variables.get_mut(variable) {
+                *value = Value::Number(f64::from_bits(self.globals[index]));
+            }
+        }
+        self.point_to_own_lists();
+    }
+
+    fn on_stage(&mut self, stage: &mut Stage<'a>, instance: usize, run: impl FnOnce(&mut Native<'a>) -> bool) -> bool {
+        if !self.load(stage, instance) {
+            self.point_to_own_lists();
+            return false;
+        }
+        let before = self.globals.clone();
+        let ran = run(self);
+        self.store(stage, instance, &before);
+        ran
+    }
+
+    // like call, but on the stage's variables as seen by the instance,
+    // or returns false if it wasn't compiled or can't be run on these values
+    pub fn call_on_stage(&mut self, stage: &mut Stage<'a>, instance: usize, function: &Function<'a>, args: &[Value]) -> bool {
+        if !self.is_compiled(function) {
+            return false;
+        }
+        let mut numbers = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg {
+                Value::Number(n) => numbers.push(*n),
+                _ => return false,
+            }
+        }
+        self.on_stage(stage, instance, |native| native.call(function, &numbers))
+    }
+
+    // only scripts that never yield were compiled to run all at once, and the rest are resumed instead
+    pub fn run_script_on_stage(&mut self, stage: &mut Stage<'a>, instance: usize, script: (usize, usize)) -> bool {
+        self.scripts.contains_key(&script) && self.on_stage(stage, instance, |native| native.run_script(script))
+    }
+
+    pub fn resume_on_stage(
+        &mut self,
+        stage: &mut Stage<'a>,
+        instance: usize,
+        script: (usize, usize),
+        state: &mut usize,
+        slots: &mut [f64],
+    ) -> Option<Resumed> {
+        if !self.coroutines.contains_key(&script) {
+            return None;
+        }
+        let mut resumed = None;
+        self.on_stage(stage, instance, |native| {
+            resumed = native.resume(script, state, slots);
+            resumed.is_some()
+        });
+        resumed
+    }
+}
@@ -0,0 +1,169 @@
+// the pen extension's state for each instance, which follows scratch-vm's so colors match Scratch's
+//
+// the color is a hue, saturation, brightness and transparency from 0 to 100, and the shade is Scratch
+// 2's, which the legacy hue and shade blocks mix the hue with to get the color
+
+use crate::scratch::ast::instruction::render::PenParam;
+
+#[derive(Clone, Debug)]
+pub struct Pen {
+    pub down: bool,
+    color: f64,
+    saturation: f64,
+    brightness: f64,
+    transparency: f64,
+    // from 0 to 200, where 50 is the hue itself
+    shade: f64,
+    // the diameter, in stage units
+    size: f64,
+}
+
+impl Default for Pen {
+    fn default() -> Pen {
+        Pen {
+            down: false,
+            color: 66.66,
+            saturation: 100.,
+            brightness: 100.,
+            transparency: 0.,
+            shade: 50.,
+            size: 1.,
+        }
+    }
+}
+
+fn clamp(value: f64, min: f64, max: f64) -> f64 {
+    value.max(min).min(max)
+}
+
+// MathUtil.wrapClamp, which wraps 101 to 0 rather than 100 to 0
+fn wrap(value: f64, min: f64, max: f64) -> f64 {
+    let range = max - min + 1.;
+    value - ((value - min) / range).floor() * range
+}
+
+// Color.hsvToRgb, with the hue in degrees
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> [f64; 3] {
+    let h = h.rem_euclid(360.);
+    let (s, v) = (clamp(s, 0., 1.), clamp(v, 0., 1.));
+    let i = (h / 60.).floor();
+    let f = h / 60. - i;
+    let (p, q, t) = (v * (1. - s), v * (1. - s * f), v * (1. - s * (1. - f)));
+    let rgb = match i as u8 {
+        0 => [v, t, p],
+        1 => [q, v, p],
+        2 => [p, v, t],
+        3 => [p, q, v],
+        4 => [t, p, v],
+        _ => [v, p, q],
+    };
+    [(rgb[0] * 255.).floor(), (rgb[1] * 255.).floor(), (rgb[2] * 255.).floor()]
+}
+
+// Color.rgbToHsv, where grays have a hue of 0
+fn rgb_to_hsv([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let (r, g, b) = (r / 255., g / 255., b / 255.);
+    let min = r.min(g).min(b);
+    let v = r.max(g).max(b);
+    if min == v {
+        return [0., 0., v];
+    }
+    let f = if r == min { g - b } else if g == min { b - r } else { r - g };
+    let i = if r == min { 3. } else if g == min { 5. } else { 1. };
+    [((i - f / (v - min)) * 60.) % 360., (v - min) / v, v]
+}
+
+fn mix(from: [f64; 3], to: [f64; 3], fraction: f64) -> [f64; 3] {
+    if fraction <= 0. {
+        return from;
+    }
+    if fraction >= 1. {
+        return to;
+    }
+    let mut mixed = [0.; 3];
+    for (channel, mixed) in mixed.iter_mut().enumerate() {
+        *mixed = (1. - fraction) * from[channel] + fraction * to[channel];
+    }
+    mixed
+}
+
+impl Pen {
+    pub fn size(&self) -> f64 {
+        self.size
+    }
+
+    // what it draws with, from 0 to 255
+    pub fn rgba(&self) -> [u8; 4] {
+        let [r, g, b] = hsv_to_rgb(self.color * 360. / 100., self.saturation / 100., self.brightness / 100.);
+        let alpha = (1. - self.transparency / 100.) * 255.;
+        [r as u8, g as u8, b as u8, alpha.round() as u8]
+    }
+
+    fn get(&self, param: PenParam) -> f64 {
+        match param {
+            PenParam::Color => self.color,
+            PenParam::Saturation => self.saturation,
+            PenParam::Brightness => self.brightness,
+            PenParam::Transparency => self.transparency,
+            PenParam::Size => self.size,
+            // in Scratch 2's range, which is twice the color's
+            PenParam::Hue => self.color * 2.,
+            PenParam::Shade => self.shade,
+        }
+    }
+
+    pub fn set(&mut self, param: PenParam, value: f64) {
+        match param {
+            PenParam::Color => self.color = wrap(value, 0., 100.),
+            PenParam::Saturation => self.saturation = clamp(value, 0., 100.),
+            PenParam::Brightness => self.brightness = clamp(value, 0., 100.),
+            PenParam::Transparency => self.transparency = clamp(value, 0., 100.),
+            PenParam::Size => self.size = clamp(value, 1., 1200.),
+            PenParam::Hue => {
+                self.color = wrap(value / 2., 0., 100.);
+                self.transparency = 0.;
+                self.mix_shade();
+            }
+            PenParam::Shade => {
+                self.shade = value.rem_euclid(200.);
+                self.mix_shade();
+            }
+        }
+    }
+
+    pub fn change(&mut self, param: PenParam, change: f64) {
+        match param {
+            // which unlike setting it leaves the transparency be
+            PenParam::Hue => {
+                self.color = wrap(self.color + change / 2., 0., 100.);
+                self.mix_shade();
+            }
+            _ => self.set(param, self.get(param) + change),
+        }
+    }
+
+    // from a color input, which sets the shade to half its brightness like Scratch 2 did
+    pub fn set_color(&mut self, [r, g, b, a]: [u8; 4]) {
+        let [h, s, v] = rgb_to_hsv([r as f64, g as f64, b as f64]);
+        self.color = h / 360. * 100.;
+        self.saturation = s * 100.;
+        self.brightness = v * 100.;
+        self.transparency = 100. * (1. - a as f64 / 255.);
+        self.shade = self.brightness / 2.;
+    }
+
+    // the fully saturated hue mixed toward black below a shade of 50 and toward white above it
+    fn mix_shade(&mut self) {
+        let rgb = hsv_to_rgb(self.color * 360. / 100., 1., 1.);
+        let shade = if self.shade > 100. { 200. - self.shade } else { self.shade };
+        let rgb = if shade < 50. {
+            mix([0., 0., 0.], rgb, (10. + shade) / 60.)
+        } else {
+            mix(rgb, [255., 255., 255.], (shade - 50.) / 60.)
+        };
+        let [h, s, v] = rgb_to_hsv(rgb);
+        self.color = h / 360. * 100.;
+        self.saturation = s * 100.;
+        self.brightness = v * 100.;
+    }
+}
@@ -0,0 +1,296 @@
+// draws the stage without a GPU, so frames can be saved as PNGs and compared
+//
+// costumes are rasterized once when they're loaded, bitmaps as they are and SVGs at twice their size
+// so they stay sharp when sprites are scaled up a little, and then every visible instance is drawn
+// back to front onto a 480×360 framebuffer with its position, size, direction and rotation style, and
+// its graphic effects
+//
+// the pen draws onto its own layer, which keeps what's drawn between frames and is drawn over the
+// backdrop but under the sprites
+
+use std::borrow::Cow;
+use std::fmt;
+
+use tiny_skia::{FillRule, FilterQuality, LineCap, Paint, PathBuilder, Pixmap, PixmapPaint, Stroke, Transform};
+
+use crate::scratch::parse::project::archive::{self, ProjectArchive};
+use crate::scratch::parse::project::sb3::{self, ImageDataFormat, RotationStyle};
+use crate::scratch::runtime::effects;
+use crate::scratch::runtime::pen::Pen;
+use crate::scratch::runtime::stage::{Instance, Stage, HEIGHT, WIDTH};
+
+// how many pixels SVGs are rasterized at per unit
+const SVG_RESOLUTION: f64 = 2.;
+
+// the only fonts text in SVGs is drawn with, so it looks the same everywhere
+const SANS_SERIF: &[u8] = include_bytes!("../../../fonts/DejaVuSans.ttf");
+const SERIF: &[u8] = include_bytes!("../../../fonts/DejaVuSerif.ttf");
+
+// the bundled fonts, where any font family that isn't theirs, like Scratch's own, is sans serif
+fn svg_options() -> usvg::Options {
+    let mut svg = usvg::Options::default();
+    svg.fontdb.load_font_data(SANS_SERIF.to_vec());
+    svg.fontdb.load_font_data(SERIF.to_vec());
+    svg.fontdb.set_sans_serif_family("DejaVu Sans");
+    svg.fontdb.set_serif_family("DejaVu Serif");
+    svg.fontdb.set_cursive_family("DejaVu Sans");
+    svg.fontdb.set_fantasy_family("DejaVu Sans");
+    svg.fontdb.set_monospace_family("DejaVu Sans");
+    svg.font_family = "DejaVu Sans".to_string();
+    svg
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Asset(archive::Error),
+    Image(image::ImageError),
+    Svg(usvg::Error),
+    // too big or empty to rasterize
+    Size,
+}
+
+// a costume that couldn't be loaded, which is drawn as nothing
+#[derive(Debug)]
+pub struct Error {
+    pub target: String,
+    pub costume: String,
+    pub error: DecodeError,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}'s costume \"{}\": {:?}", self.target, self.costume, self.error)
+    }
+}
+
+pub struct Costume {
+    pixmap: Option<Pixmap>,
+    // pixels per stage unit
+    resolution: f64,
+    // the rotation center, in pixels from the top left
+    center: (f64, f64),
+}
+
+impl Costume {
+    fn blank() -> Costume {
+        Costume {
+            pixmap: None,
+            resolution: 1.,
+            center: (0., 0.),
+        }
+    }
+
+    fn decode(costume: &sb3::Costume, bytes: &[u8], svg: &usvg::Options) -> Result<Costume, DecodeError> {
+        let (pixmap, resolution) = match costume.asset.data_format {
+            ImageDataFormat::SVG => (rasterize(bytes, svg)?, SVG_RESOLUTION),
+            _ => (decode_bitmap(bytes)?, costume.bitmap_resolution.max(1) as f64),
+        };
+        // an SVG's rotation center is in its own units, and a bitmap's is in pixels
+        let scale = match costume.asset.data_format {
+            ImageDataFormat::SVG => SVG_RESOLUTION,
+            _ => 1.,
+        };
+        Ok(Costume {
+            pixmap: Some(pixmap),
+            resolution,
+            center: (costume.rotation_center.0 * scale, costume.rotation_center.1 * scale),
+        })
+    }
+
+    pub fn pixmap(&self) -> Option<&Pixmap> {
+        self.pixmap.as_ref()
+    }
+
+    pub fn resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        self.center
+    }
+
+    // in stage units, or none if it couldn't be loaded
+    pub fn size(&self) -> Option<(f64, f64)> {
+        let pixmap = self.pixmap.as_ref()?;
+        Some((pixmap.width() as f64 / self.resolution, pixmap.height() as f64 / self.resolution))
+    }
+
+    // with the effects, or only the ones that change its shape unless colors is set
+    pub fn effected(&self, effects: &[f64; 7], colors: bool) -> Option<Cow<'_, Pixmap>> {
+        let pixmap = self.pixmap.as_ref()?;
+        if !(effects::is_distorted(effects) || (colors && effects::is_recolored(effects))) {
+            return Some(Cow::Borrowed(pixmap));
+        }
+        Some(Cow::Owned(effects::apply(pixmap, self.size()?, effects, colors)))
+    }
+}
+
+// tiny-skia's pixels are premultiplied
+fn decode_bitmap(bytes: &[u8]) -> Result<Pixmap, DecodeError> {
+    let image = image::load_from_memory(bytes).map_err(DecodeError::Image)?.to_rgba8();
+    let mut pixmap = Pixmap::new(image.width(), image.height()).ok_or(DecodeError::Size)?;
+    for (to, from) in pixmap.data_mut().chunks_exact_mut(4).zip(image.pixels()) {
+        let [r, g, b, a] = from.0;
+        let premultiply = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+        to.copy_from_slice(&[premultiply(r), premultiply(g), premultiply(b), a]);
+    }
+    Ok(pixmap)
+}
+
+fn rasterize(bytes: &[u8], options: &usvg::Options) -> Result<Pixmap, DecodeError> {
+    let tree = usvg::Tree::from_data(bytes, &options.to_ref()).map_err(DecodeError::Svg)?;
+    let size = tree.svg_node().size;
+    let width = (size.width() * SVG_RESOLUTION).ceil() as u32;
+    let height = (size.height() * SVG_RESOLUTION).ceil() as u32;
+    let mut pixmap = Pixmap::new(width, height).ok_or(DecodeError::Size)?;
+    resvg::render(&tree, usvg::FitTo::Zoom(SVG_RESOLUTION as f32), Transform::identity(), pixmap.as_mut())
+        .ok_or(DecodeError::Size)?;
+    Ok(pixmap)
+}
+
+// where a costume's pixels go on the stage, in framebuffer pixels with y down
+pub fn transform(instance: &Instance, costume: &Costume) -> Transform {
+    let scale = instance.size / 100. / costume.resolution;
+    let (rotation, flip) = match instance.rotation_style {
+        RotationStyle::AllAround => (instance.direction - 90., 1.),
+        RotationStyle::LeftRight => (0., if instance.direction < 0. { -1. } else { 1. }),
+        RotationStyle::DontRotate => (0., 1.),
+    };
+    Transform::from_translate((WIDTH / 2. + instance.x) as f32, (HEIGHT / 2. - instance.y) as f32)
+        .pre_concat(Transform::from_rotate(rotation as f32))
+        .pre_scale((scale * flip) as f32, scale as f32)
+        .pre_translate(-costume.center.0 as f32, -costume.center.1 as f32)
+}
+
+// draws the instance whether it's hidden or not, unless its costume couldn't be loaded
+fn draw_instance(costumes: &[Vec<Costume>], frame: &mut Pixmap, instance: &Instance, origin: (i32, i32)) {
+    let costume = match costumes.get(instance.sprite_scripts).and_then(|costumes| costumes.get(instance.costume)) {
+        Some(costume) => costume,
+        None => return,
+    };
+    let pixmap = match costume.effected(&instance.effects, true) {
+        Some(pixmap) => pixmap,
+        None => return,
+    };
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bilinear,
+        ..PixmapPaint::default()
+    };
+    let transform = transform(instance, costume).post_translate(-origin.0 as f32, -origin.1 as f32);
+    frame.draw_pixmap(0, 0, Pixmap::as_ref(&pixmap), &paint, transform, None);
+}
+
+// in framebuffer pixels, where Scratch moves lines 1 or 3 wide by half a pixel to line them up with pixels
+fn pen_point(pen: &Pen, (x, y): (f64, f64)) -> (f32, f32) {
+    let offset = if pen.size() == 1. || pen.size() == 3. { 0.5 } else { 0. };
+    ((WIDTH / 2. + x + offset) as f32, (HEIGHT / 2. - y - offset) as f32)
+}
+
+pub struct Renderer {
+    // by sprite scripts index, and then by costume index
+    costumes: Vec<Vec<Costume>>,
+    pen: Pixmap,
+}
+
+impl Renderer {
+    // loads every target's costumes, and returns the ones that couldn't be loaded too
+    pub fn load(archive: &mut ProjectArchive, project: &sb3::Project) -> (Renderer, Vec<Error>) {
+        let svg = svg_options();
+        let targets = std::iter::once(("Stage", &project.targets.stage.target))
+            .chain(project.targets.sprites.iter().map(|sprite| (sprite.name.as_str(), &sprite.target)));
+        let mut errors = Vec::new();
+        let costumes = targets
+            .map(|(name, target)| {
+                target
+                    .costumes
+                    .iter()
+                    .map(|costume| {
+                        let decoded = archive
+                            .read_asset(&project.assets, &costume.asset)
+                            .map_err(DecodeError::Asset)
+                            .and_then(|bytes| Costume::decode(costume, &bytes, &svg));
+                        decoded.unwrap_or_else(|error| {
+                            errors.push(Error {
+                                target: name.to_string(),
+                                costume: costume.asset.name.clone(),
+                                error,
+                            });
+                            Costume::blank()
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        let pen = Pixmap::new(WIDTH as u32, HEIGHT as u32).unwrap();
+        (Renderer { costumes, pen }, errors)
+    }
+
+    pub fn costume(&self, instance: &Instance) -> Option<&Costume> {
+        self.costumes.get(instance.sprite_scripts)?.get(instance.costume)
+    }
+
+    // draws the instance onto the framebuffer, unless it's hidden or its costume couldn't be loaded,
+    // where origin is the stage pixel at the framebuffer's top left
+    pub fn draw(&self, frame: &mut Pixmap, instance: &Instance, origin: (i32, i32)) {
+        if instance.visible {
+            draw_instance(&self.costumes, frame, instance, origin);
+        }
+    }
+
+    // what the pen's drawn, transparent where it hasn't
+    pub fn pen(&self) -> &Pixmap {
+        &self.pen
+    }
+
+    pub fn draw_pen(&self, frame: &mut Pixmap, origin: (i32, i32)) {
+        let transform = Transform::from_translate(-origin.0 as f32, -origin.1 as f32);
+        frame.draw_pixmap(0, 0, self.pen.as_ref(), &PixmapPaint::default(), transform, None);
+    }
+
+    pub fn clear_pen(&mut self) {
+        self.pen.fill(tiny_skia::Color::TRANSPARENT);
+    }
+
+    // with round ends, so a line that doesn't go anywhere is a dot
+    pub fn pen_line(&mut self, pen: &Pen, from: (f64, f64), to: (f64, f64)) {
+        let [r, g, b, a] = pen.rgba();
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(r, g, b, a);
+        paint.anti_alias = true;
+        let (from, to) = (pen_point(pen, from), pen_point(pen, to));
+        if from == to {
+            if let Some(dot) = PathBuilder::from_circle(from.0, from.1, pen.size() as f32 / 2.) {
+                self.pen.fill_path(&dot, &paint, FillRule::Winding, Transform::identity(), None);
+            }
+            return;
+        }
+        let mut line = PathBuilder::new();
+        line.move_to(from.0, from.1);
+        line.line_to(to.0, to.1);
+        let stroke = Stroke {
+            width: pen.size() as f32,
+            line_cap: LineCap::Round,
+            ..Stroke::default()
+        };
+        if let Some(line) = line.finish() {
+            self.pen.stroke_path(&line, &paint, &stroke, Transform::identity(), None);
+        }
+    }
+
+    // draws the instance onto the pen layer, even if it's hidden like in Scratch
+    pub fn stamp(&mut self, instance: &Instance) {
+        draw_instance(&self.costumes, &mut self.pen, instance, (0, 0));
+    }
+
+    // the backdrop, the pen layer, and then the sprites back to front, on white like Scratch's stage
+    pub fn render(&self, stage: &Stage) -> Pixmap {
+        let mut frame = Pixmap::new(WIDTH as u32, HEIGHT as u32).unwrap();
+        frame.fill(tiny_skia::Color::WHITE);
+        self.draw(&mut frame, stage.stage(), (0, 0));
+        self.draw_pen(&mut frame, (0, 0));
+        for &layer in &stage.layers {
+            self.draw(&mut frame, &stage.instances[layer], (0, 0));
+        }
+        frame
+    }
+}
@@ -0,0 +1,252 @@
+// Scratch's frame model: every frame, each thread runs until it yields, in the order they were started,
+// and then again while they keep yielding and nothing's asked for a redraw, unless in turbo mode,
+// and then the stage's clock moves forward a frame
+//
+// the interpreter runs the threads, and this owns them between frames
+
+use std::rc::Rc;
+
+use crate::scratch::ast::{Event, Script, Variable};
+use crate::scratch::ast::coroutine::{Coroutine, State};
+use crate::scratch::runtime::value::Value;
+
+// how far a thread got before it stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Done,
+    // at the end of a loop iteration, so it can run again this frame
+    Yielded,
+    // until the stage's clock reaches the time, or until it's polled again if it can't know when
+    Waiting(Option<f64>),
+}
+
+// a coroutine being run, which is a script or a custom block it called
+pub struct Activation<'p, 'a> {
+    pub coroutine: Rc<Coroutine<'p, 'a>>,
+    pub state: State,
+    pub slots: Vec<f64>,
+}
+
+impl<'p, 'a> Activation<'p, 'a> {
+    pub fn new(coroutine: Rc<Coroutine<'p, 'a>>) -> Activation<'p, 'a> {
+        let slots = vec![0.; coroutine.slots()];
+        Activation {
+            coroutine,
+            state: 0,
+            slots,
+        }
+    }
+}
+
+// an op that's started and takes more than one resume
+pub enum Pending {
+    Say { until: f64 },
+    Sound { until: f64 },
+    Glide { start: f64, secs: f64, from: (f64, f64), to: (f64, f64) },
+    // the threads it started
+    Broadcast(Vec<u64>),
+}
+
+// where a thread is, which is empty while it's being run
+#[derive(Default)]
+pub struct ThreadState<'p, 'a> {
+    // innermost last, and each one's args are in the same place in frames
+    pub activations: Vec<Activation<'p, 'a>>,
+    pub frames: Vec<Vec<(*const Variable<'a>, Value)>>,
+    pub pending: Option<Pending>,
+}
+
+impl<'p, 'a> ThreadState<'p, 'a> {
+    fn new(coroutine: Rc<Coroutine<'p, 'a>>) -> ThreadState<'p, 'a> {
+        ThreadState {
+            activations: vec![Activation::new(coroutine)],
+            frames: vec![Vec::new()],
+            pending: None,
+        }
+    }
+}
+
+pub struct Thread<'p, 'a> {
+    pub id: u64,
+    pub instance: usize,
+    pub script: &'p Script<'a>,
+    // by sprite scripts index and script index
+    pub index: (usize, usize),
+    pub state: ThreadState<'p, 'a>,
+    pub status: Status,
+    // restarted while it was being run, so the state it's run with is stale
+    restarted: bool,
+}
+
+// whether a hat restarts its script if it's already running, or leaves it be, like Scratch's
+pub fn restarts(trigger: &Event) -> bool {
+    match trigger {
+        Event::FlagClicked | Event::Clicked | Event::BroadcastReceived(_) => true,
+        Event::KeyPressed(_) | Event::BackdropSwitchesTo(_) | Event::GreaterThan(_, _) | Event::StartAsClone => false,
+    }
+}
+
+pub struct Scheduler<'p, 'a> {
+    // in the order they were started
+    threads: Vec<Thread<'p, 'a>>,
+    next_id: u64,
+    frame_rate: f64,
+    turbo: bool,
+    // how many steps a frame can take, which stands in for Scratch only working for 75% of a frame
+    work: u64,
+    redraw: bool,
+}
+
+impl<'p, 'a> Default for Scheduler<'p, 'a> {
+    fn default() -> Scheduler<'p, 'a> {
+        Scheduler::new()
+    }
+}
+
+impl<'p, 'a> Scheduler<'p, 'a> {
+    pub fn new() -> Scheduler<'p, 'a> {
+        Scheduler {
+            threads: Vec::new(),
+            next_id: 0,
+            frame_rate: 30.,
+            turbo: false,
+            work: 100_000,
+            redraw: false,
+        }
+    }
+
+    // 30 like Scratch, or 60 like its 60 FPS mode
+    pub fn set_frame_rate(&mut self, frame_rate: f64) {
+        self.frame_rate = frame_rate;
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    // in seconds
+    pub fn frame(&self) -> f64 {
+        1. / self.frame_rate
+    }
+
+    // frames only end when there's no time left, not when something's redrawn
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
+
+    pub fn turbo(&self) -> bool {
+        self.turbo
+    }
+
+    pub fn set_work(&mut self, work: u64) {
+        self.work = work;
+    }
+
+    pub fn work(&self) -> u64 {
+        self.work
+    }
+
+    pub fn request_redraw(&mut self) {
+        self.redraw = true;
+    }
+
+    // whether another round can run this frame
+    pub fn can_continue(&self) -> bool {
+        self.turbo || !self.redraw
+    }
+
+    pub fn start_frame(&mut self) {
+        self.redraw = false;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    pub fn threads(&self) -> &[Thread<'p, 'a>] {
+        &self.threads[..]
+    }
+
+    pub fn is_running(&self, id: u64) -> bool {
+        self.threads.iter().any(|thread| thread.id == id)
+    }
+
+    // starts the script on the instance, or restarts it if it's already running and restart is set,
+    // and returns the thread running it unless it was left be
+    pub fn start(
+        &mut self,
+        instance: usize,
+        script: &'p Script<'a>,
+        index: (usize, usize),
+        coroutine: Rc<Coroutine<'p, 'a>>,
+        restart: bool,
+    ) -> Option<u64> {
+        let running = self
+            .threads
+            .iter_mut()
+            .find(|thread| thread.instance == instance && thread.index == index);
+        if let Some(thread) = running {
+            if !restart {
+                return None;
+            }
+            thread.state = ThreadState::new(coroutine);
+            thread.status = Status::Yielded;
+            thread.restarted = true;
+            return Some(thread.id);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.threads.push(Thread {
+            id,
+            instance,
+            script,
+            index,
+            state: ThreadState::new(coroutine),
+            status: Status::Yielded,
+            restarted: false,
+        });
+        Some(id)
+    }
+
+    pub fn stop(&mut self, stopped: impl Fn(&Thread<'p, 'a>) -> bool) {
+        self.threads.retain(|thread| !stopped(thread));
+    }
+
+    pub fn stop_all(&mut self) {
+        self.threads.clear();
+    }
+
+    // stops the deleted instance's threads, and moves the ones after it down to match the stage
+    pub fn instance_deleted(&mut self, instance: usize) {
+        self.threads.retain(|thread| thread.instance != instance);
+        for thread in &mut self.threads {
+            if thread.instance > instance {
+                thread.instance -= 1;
+            }
+        }
+    }
+
+    // the state of the thread at the position, to be run and then put back
+    pub fn take(&mut self, position: usize) -> (u64, ThreadState<'p, 'a>) {
+        let thread = &mut self.threads[position];
+        thread.restarted = false;
+        (thread.id, std::mem::take(&mut thread.state))
+    }
+
+    // unless it was stopped or restarted while it ran, in which case the state's stale
+    pub fn put(&mut self, id: u64, state: ThreadState<'p, 'a>, status: Status) {
+        let position = match self.threads.iter().position(|thread| thread.id == id) {
+            Some(position) => position,
+            None => return,
+        };
+        let thread = &mut self.threads[position];
+        if thread.restarted {
+            thread.restarted = false;
+        } else if status == Status::Done {
+            self.threads.remove(position);
+        } else {
+            thread.state = state;
+            thread.status = status;
+        }
+    }
+}
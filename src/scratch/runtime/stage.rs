@@ -0,0 +1,328 @@
+// the state of the stage and its sprites, without drawing anything,
+// so scripts can be run from the command line
+
+use std::collections::HashMap;
+
+use crate::scratch::ast::{Key, List, Program, Scope, Sprite, Variable};
+use crate::scratch::parse::project::sb3::{self, RotationStyle};
+use crate::scratch::runtime::pen::Pen;
+use crate::scratch::runtime::value::{self, Value};
+
+pub const WIDTH: f64 = 480.;
+pub const HEIGHT: f64 = 360.;
+
+// Scratch's limit on the length of a list
+pub const LIST_LIMIT: usize = 200_000;
+
+// and on how many clones there can be at once
+pub const CLONE_LIMIT: usize = 300;
+
+// variables and lists by their declaration in the AST
+#[derive(Clone)]
+pub struct Variables<'a> {
+    scalars: HashMap<*const Variable<'a>, Value>,
+    lists: HashMap<*const List<'a>, Vec<Value>>,
+}
+
+impl<'a> Variables<'a> {
+    pub fn new(scope: &Scope<'a>, initial: &[sb3::Variable]) -> Variables<'a> {
+        // the scope's variables and lists are in the same order as the target's
+        let mut scalars = initial.iter().filter_map(|variable| match &variable.value {
+            sb3::Value::Scalar(value) => Some(Value::from(value)),
+            _ => None,
+        });
+        let mut lists = initial.iter().filter_map(|variable| match &variable.value {
+            sb3::Value::List(items) => Some(items.iter().map(Value::from).collect()),
+            _ => None,
+        });
+        Variables {
+            scalars: scope
+                .variables()
+                .iter()
+                .map(|variable| (variable as *const _, scalars.next().unwrap_or_default()))
+                .collect(),
+            lists: scope
+                .lists()
+                .iter()
+                .map(|list| (list as *const _, lists.next().unwrap_or_default()))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, variable: &Variable<'a>) -> Option<&Value> {
+        self.scalars.get(&(variable as *const _))
+    }
+
+    pub fn get_mut(&mut self, variable: &Variable<'a>) -> Option<&mut Value> {
+        self.scalars.get_mut(&(variable as *const _))
+    }
+
+    pub fn list(&self, list: &List<'a>) -> Option<&Vec<Value>> {
+        self.lists.get(&(list as *const _))
+    }
+
+    pub fn list_mut(&mut self, list: &List<'a>) -> Option<&mut Vec<Value>> {
+        self.lists.get_mut(&(list as *const _))
+    }
+}
+
+// what a sprite's instances share
+pub struct SpriteAssets {
+    pub costumes: Vec<String>,
+    // in seconds, by name
+    pub sounds: HashMap<String, f64>,
+}
+
+impl SpriteAssets {
+    fn new(target: &sb3::Target) -> SpriteAssets {
+        SpriteAssets {
+            costumes: target.costumes.iter().map(|costume| costume.asset.name.clone()).collect(),
+            sounds: target
+                .sounds
+                .iter()
+                .filter(|sound| sound.rate > 0)
+                .map(|sound| (sound.asset.name.clone(), sound.sample_count as f64 / sound.rate as f64))
+                .collect(),
+        }
+    }
+
+    // a costume's index by name, or by number if there's no costume with that name
+    pub fn costume(&self, value: &Value, current: usize) -> Option<usize> {
+        let len = self.costumes.len() as i64;
+        if len == 0 {
+            return None;
+        }
+        let by_number = |n: f64| Some((value::round(n) as i64 - 1).rem_euclid(len) as usize);
+        match value {
+            Value::String(name) => {
+                if let Some(index) = self.costumes.iter().position(|costume| costume == name) {
+                    return Some(index);
+                }
+                match name.as_str() {
+                    "next costume" | "next backdrop" => by_number(current as f64 + 2.),
+                    "previous costume" | "previous backdrop" => by_number(current as f64),
+                    _ if name.trim().is_empty() => None,
+                    _ => match value::parse_number(name) {
+                        n if n.is_nan() => None,
+                        n => by_number(n),
+                    },
+                }
+            }
+            _ => by_number(value.to_number()),
+        }
+    }
+}
+
+pub struct Bubble {
+    pub think: bool,
+    pub text: String,
+}
+
+// the stage or a sprite
+pub struct Instance<'a> {
+    pub sprite: &'a Sprite<'a>,
+    // the index into the program's sprite scripts
+    pub sprite_scripts: usize,
+    pub x: f64,
+    pub y: f64,
+    pub direction: f64,
+    // in percent
+    pub size: f64,
+    pub visible: bool,
+    pub costume: usize,
+    pub rotation_style: RotationStyle,
+    pub draggable: bool,
+    pub volume: f64,
+    // by VisualEffectType and AudioEffectType
+    pub effects: [f64; 7],
+    pub audio_effects: [f64; 2],
+    pub bubble: Option<Bubble>,
+    pub pen: Pen,
+    // by script index, whether each when greater than hat's property was greater than its value last frame
+    pub greater_than: HashMap<usize, bool>,
+    pub variables: Variables<'a>,
+    pub is_clone: bool,
+}
+
+impl<'a> Instance<'a> {
+    fn new(program: &Program<'a>, sprite_scripts: usize, target: &sb3::Target) -> Instance<'a> {
+        let scripts = &program.sprite_scripts()[sprite_scripts];
+        let scope = if scripts.sprite().is_stage() { program.globals() } else { scripts.locals() };
+        Instance {
+            sprite: scripts.sprite(),
+            sprite_scripts,
+            x: 0.,
+            y: 0.,
+            direction: 90.,
+            size: 100.,
+            visible: true,
+            costume: target.current_costume,
+            rotation_style: RotationStyle::AllAround,
+            draggable: false,
+            volume: target.volume,
+            effects: [0.; 7],
+            audio_effects: [0.; 2],
+            bubble: None,
+            pen: Pen::default(),
+            greater_than: HashMap::new(),
+            variables: Variables::new(scope, &target.variables),
+            is_clone: false,
+        }
+    }
+
+    // with its own copy of the sprite's locals, but without its speech bubble
+    fn make_clone(&self) -> Instance<'a> {
+        Instance {
+            sprite: self.sprite,
+            sprite_scripts: self.sprite_scripts,
+            x: self.x,
+            y: self.y,
+            direction: self.direction,
+            size: self.size,
+            visible: self.visible,
+            costume: self.costume,
+            rotation_style: self.rotation_style,
+            draggable: self.draggable,
+            volume: self.volume,
+            effects: self.effects,
+            audio_effects: self.audio_effects,
+            bubble: None,
+            pen: self.pen.clone(),
+            // so it doesn't start them again for what its original already did
+            greater_than: self.greater_than.clone(),
+            variables: self.variables.clone(),
+            is_clone: true,
+        }
+    }
+
+    // wrapped into (-180, 180] like Scratch
+    pub fn set_direction(&mut self, direction: f64) {
+        if direction.is_finite() {
+            let wrapped = (direction + 180.).rem_euclid(360.) - 180.;
+            self.direction = if wrapped == -180. { 180. } else { wrapped };
+        }
+    }
+}
+
+pub struct Stage<'a> {
+    // by sprite scripts index
+    pub assets: Vec<SpriteAssets>,
+    // the stage is first, followed by the sprites in the order of the program's sprite scripts,
+    // so an original's index is its sprite scripts index, and then the clones
+    pub instances: Vec<Instance<'a>>,
+    // the instances drawn above the stage, back to front
+    pub layers: Vec<usize>,
+    // seconds since the project started
+    pub clock: f64,
+    pub timer_start: f64,
+    pub answer: String,
+    pub user_name: String,
+    pub mouse: (f64, f64),
+    pub mouse_down: bool,
+    pub keys: Vec<Key>,
+}
+
+impl<'a> Stage<'a> {
+    pub fn new(program: &Program<'a>, project: &sb3::Project) -> Stage<'a> {
+        let targets = std::iter::once(&project.targets.stage.target)
+            .chain(project.targets.sprites.iter().map(|sprite| &sprite.target))
+            .collect::<Vec<_>>();
+        let assets = targets.iter().map(|target| SpriteAssets::new(target)).collect();
+        let mut instances = targets
+            .iter()
+            .enumerate()
+            .map(|(index, target)| Instance::new(program, index, target))
+            .collect::<Vec<_>>();
+        for (instance, sprite) in instances.iter_mut().skip(1).zip(&project.targets.sprites) {
+            instance.x = sprite.position.0;
+            instance.y = sprite.position.1;
+            instance.set_direction(sprite.direction);
+            instance.size = sprite.size;
+            instance.visible = sprite.visible;
+            instance.rotation_style = sprite.rotation_style;
+            instance.draggable = sprite.draggable;
+        }
+        let mut layers = (1..instances.len()).collect::<Vec<_>>();
+        layers.sort_by_key(|&index| project.targets.sprites[index - 1].layer_order);
+        Stage {
+            assets,
+            instances,
+            layers,
+            clock: 0.,
+            timer_start: 0.,
+            answer: String::new(),
+            user_name: String::new(),
+            mouse: (0., 0.),
+            mouse_down: false,
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn stage(&self) -> &Instance<'a> {
+        &self.instances[0]
+    }
+
+    pub fn timer(&self) -> f64 {
+        self.clock - self.timer_start
+    }
+
+    // 1 is just above the stage
+    pub fn layer(&self, instance: usize) -> usize {
+        self.layers.iter().position(|&layer| layer == instance).map_or(0, |layer| layer + 1)
+    }
+
+    pub fn set_layer(&mut self, instance: usize, layer: f64) {
+        if let Some(current) = self.layers.iter().position(|&other| other == instance) {
+            self.layers.remove(current);
+            let layer = if layer.is_nan() { 1. } else { layer.max(1.).min(self.layers.len() as f64 + 1.) };
+            self.layers.insert(layer as usize - 1, instance);
+        }
+    }
+
+    pub fn clones(&self) -> usize {
+        self.instances.iter().filter(|instance| instance.is_clone).count()
+    }
+
+    // just behind the instance it's a clone of like Scratch, unless it's the stage or there are too many clones
+    pub fn create_clone(&mut self, of: usize) -> Option<usize> {
+        if self.instances[of].sprite.is_stage() || self.clones() >= CLONE_LIMIT {
+            return None;
+        }
+        let clone = self.instances[of].make_clone();
+        self.instances.push(clone);
+        let index = self.instances.len() - 1;
+        let layer = self.layers.iter().position(|&layer| layer == of).unwrap_or(0);
+        self.layers.insert(layer, index);
+        Some(index)
+    }
+
+    // the instances after it move down one, or returns false if it isn't a clone
+    pub fn delete_clone(&mut self, index: usize) -> bool {
+        if !self.instances[index].is_clone {
+            return false;
+        }
+        self.instances.remove(index);
+        self.layers.retain(|&layer| layer != index);
+        for layer in &mut self.layers {
+            if *layer > index {
+                *layer -= 1;
+            }
+        }
+        true
+    }
+
+    // which leaves the originals where they were, since the clones come after them
+    pub fn delete_clones(&mut self) {
+        self.instances.retain(|instance| !instance.is_clone);
+        let originals = self.instances.len();
+        self.layers.retain(|&layer| layer < originals);
+    }
+
+    pub fn costume_name(&self, instance: usize) -> &str {
+        let instance = &self.instances[instance];
+        self.assets[instance.sprite_scripts]
+            .costumes
+            .get(instance.costume)
+            .map_or("", String::as_str)
+    }
+}
@@ -0,0 +1,391 @@
+// Scratch's dynamically typed values, and the casts and ops on them,
+// which follow scratch-vm's Cast and operator blocks so results match Scratch's
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::scratch::ast::{Constant, Number};
+use crate::scratch::ast::instruction::function_call::{ComparisonOp, FloatToFloatOp, FloatToIntOp, MathOp};
+use crate::scratch::parse::project::sb3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Default for Value {
+    fn default() -> Value {
+        Value::Number(0.0)
+    }
+}
+
+// JavaScript's whitespace, which unlike Rust's includes the byte order mark
+fn is_whitespace(c: char) -> bool {
+    c.is_whitespace() || c == '\u{feff}'
+}
+
+fn trim(s: &str) -> &str {
+    s.trim_matches(is_whitespace)
+}
+
+fn parse_radix(digits: &str, radix: u32) -> f64 {
+    if digits.is_empty() {
+        return f64::NAN;
+    }
+    // can be longer than a u64, so accumulate into a float like JavaScript does
+    digits
+        .chars()
+        .try_fold(0.0, |n, c| c.to_digit(radix).map(|digit| n * radix as f64 + digit as f64))
+        .unwrap_or(f64::NAN)
+}
+
+// JavaScript's Number(string), which is NaN if it's not a number
+pub fn parse_number(s: &str) -> f64 {
+    let s = trim(s);
+    if s.is_empty() {
+        return 0.0;
+    }
+    // prefixed literals can't be signed
+    let prefix = s.get(..2).map(str::to_ascii_lowercase);
+    match prefix.as_deref() {
+        Some("0x") => return parse_radix(&s[2..], 16),
+        Some("0b") => return parse_radix(&s[2..], 2),
+        Some("0o") => return parse_radix(&s[2..], 8),
+        _ => {}
+    }
+    let (sign, unsigned) = match s.as_bytes()[0] {
+        b'+' => (1.0, &s[1..]),
+        b'-' => (-1.0, &s[1..]),
+        _ => (1.0, s),
+    };
+    if unsigned == "Infinity" {
+        return sign * f64::INFINITY;
+    }
+    // Rust also parses "inf" and "nan", which JavaScript doesn't
+    let is_decimal = unsigned
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || c == '+' || c == '-');
+    if !is_decimal || unsigned.starts_with(['+', '-']) {
+        return f64::NAN;
+    }
+    unsigned.parse::<f64>().map(|n| sign * n).unwrap_or(f64::NAN)
+}
+
+// JavaScript's Number.prototype.toString
+pub fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        // includes -0
+        "0".to_string()
+    } else if n.abs() >= 1e21 || n.abs() < 1e-6 {
+        let exponential = format!("{:e}", n);
+        match exponential.find('e') {
+            Some(e) if !exponential[e + 1..].starts_with('-') => {
+                format!("{}e+{}", &exponential[..e], &exponential[e + 1..])
+            }
+            _ => exponential,
+        }
+    } else {
+        format!("{}", n)
+    }
+}
+
+fn hex_color(digits: &str) -> Option<[u8; 4]> {
+    let digit = |i: usize| digits.get(i..i + 1).and_then(|d| u8::from_str_radix(d, 16).ok());
+    match digits.len() {
+        3 => Some([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17, 255]),
+        6 => Some([digit(0)? * 16 + digit(1)?, digit(2)? * 16 + digit(3)?, digit(4)? * 16 + digit(5)?, 255]),
+        _ => None,
+    }
+}
+
+impl Value {
+    // NaN is 0
+    pub fn to_number(&self) -> f64 {
+        let n = match self {
+            Value::Bool(b) => *b as u8 as f64,
+            Value::Number(n) => *n,
+            Value::String(s) => parse_number(s),
+        };
+        if n.is_nan() { 0.0 } else { n }
+    }
+
+    pub fn to_bool(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0 && !n.is_nan(),
+            Value::String(s) => !(s.is_empty() || s == "0" || s.eq_ignore_ascii_case("false")),
+        }
+    }
+
+    // like to_number, but an integer only if it's written as one, so "1.0" isn't
+    pub fn is_int(&self) -> bool {
+        match self {
+            Value::Bool(_) => true,
+            Value::Number(n) => n.fract() == 0.0 || n.is_nan(),
+            Value::String(s) => !s.contains('.'),
+        }
+    }
+
+    // Cast.toRgbColorList, from "#rrggbb" or "#rgb", or a number like 0xaarrggbb where an alpha of
+    // 0 is opaque, and opaque black if it's neither
+    pub fn to_rgba(&self) -> [u8; 4] {
+        match self {
+            Value::String(s) if s.starts_with('#') => hex_color(&s[1..]).unwrap_or([0, 0, 0, 255]),
+            _ => {
+                let n = self.to_number();
+                let n = if n.is_finite() { n as i64 as u32 } else { 0 };
+                let alpha = (n >> 24) as u8;
+                [(n >> 16) as u8, (n >> 8) as u8, n as u8, if alpha > 0 { alpha } else { 255 }]
+            }
+        }
+    }
+
+    // JavaScript's Number(value) without NaN becoming 0, for comparisons
+    fn to_raw_number(&self) -> f64 {
+        match self {
+            Value::Bool(b) => *b as u8 as f64,
+            Value::Number(n) => *n,
+            Value::String(s) => parse_number(s),
+        }
+    }
+
+    // JSON has no NaN or infinities, so those are strings like Scratch saves them
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Bool(b) => serde_json::Value::Bool(*b),
+            // whole numbers are written without a decimal point, like JavaScript does
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 => {
+                serde_json::Value::Number((*n as i64).into())
+            }
+            Value::Number(n) => serde_json::Number::from_f64(*n)
+                .map_or_else(|| serde_json::Value::String(number_to_string(*n)), serde_json::Value::Number),
+            Value::String(s) => serde_json::Value::String(s.clone()),
+        }
+    }
+
+    fn is_whitespace(&self) -> bool {
+        match self {
+            Value::String(s) => trim(s).is_empty(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => f.write_str(&number_to_string(*n)),
+            Value::String(s) => f.write_str(s),
+        }
+    }
+}
+
+fn number(number: &Number) -> f64 {
+    match number {
+        Number::UInt(n) => *n as f64,
+        Number::Int(n) => *n as f64,
+        Number::Float(n) => *n,
+    }
+}
+
+impl From<&Constant<'_>> for Value {
+    fn from(constant: &Constant) -> Value {
+        match constant {
+            Constant::Bool(b) => Value::Bool(*b),
+            Constant::Number(n) => Value::Number(number(n)),
+            Constant::String(s) => Value::String(s.to_string()),
+        }
+    }
+}
+
+impl From<&sb3::ScalarValue> for Value {
+    fn from(scalar: &sb3::ScalarValue) -> Value {
+        match scalar {
+            sb3::ScalarValue::Bool(b) => Value::Bool(*b),
+            sb3::ScalarValue::Number(sb3::Number::UInt(n)) => Value::Number(*n as f64),
+            sb3::ScalarValue::Number(sb3::Number::Int(n)) => Value::Number(*n as f64),
+            sb3::ScalarValue::Number(sb3::Number::Float(n)) => Value::Number(*n),
+            sb3::ScalarValue::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+// numerically if both are numbers, otherwise as case-insensitive strings,
+// and whitespace isn't 0 here like it is everywhere else
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    let mut n1 = a.to_raw_number();
+    let mut n2 = b.to_raw_number();
+    if n1 == 0.0 && a.is_whitespace() {
+        n1 = f64::NAN;
+    } else if n2 == 0.0 && b.is_whitespace() {
+        n2 = f64::NAN;
+    }
+    if n1.is_nan() || n2.is_nan() {
+        let s1 = a.to_string().to_lowercase();
+        let s2 = b.to_string().to_lowercase();
+        return s1.cmp(&s2);
+    }
+    // also equates infinities of the same sign
+    n1.partial_cmp(&n2).unwrap_or(Ordering::Equal)
+}
+
+pub fn comparison(op: ComparisonOp, a: &Value, b: &Value) -> bool {
+    let ordering = compare(a, b);
+    match op {
+        ComparisonOp::LessThan => ordering == Ordering::Less,
+        ComparisonOp::GreaterThan => ordering == Ordering::Greater,
+        ComparisonOp::Equals => ordering == Ordering::Equal,
+    }
+}
+
+// the result has the sign of the modulus, like Python's and unlike Rust's %
+pub fn modulo(n: f64, modulus: f64) -> f64 {
+    let result = n % modulus;
+    if result / modulus < 0.0 {
+        result + modulus
+    } else {
+        result
+    }
+}
+
+// halves round up, so -2.5 is -2, like JavaScript's Math.round
+pub fn round(n: f64) -> f64 {
+    let floor = n.floor();
+    if n - floor >= 0.5 {
+        floor + 1.0
+    } else {
+        floor
+    }
+}
+
+pub fn float_to_int(op: FloatToIntOp, n: f64) -> f64 {
+    match op {
+        FloatToIntOp::Round => round(n),
+        FloatToIntOp::Floor => n.floor(),
+        FloatToIntOp::Ceiling => n.ceil(),
+    }
+}
+
+// rounded to 10 decimal places, so sin 180 is 0 rather than 1.2e-16
+fn trig(f: fn(f64) -> f64, degrees: f64) -> f64 {
+    round(f(degrees.to_radians()) * 1e10) / 1e10
+}
+
+pub fn float_to_float(op: FloatToFloatOp, n: f64) -> f64 {
+    match op {
+        FloatToFloatOp::Sqrt => n.sqrt(),
+        FloatToFloatOp::Sin => trig(f64::sin, n),
+        FloatToFloatOp::Cos => trig(f64::cos, n),
+        FloatToFloatOp::Tan => match n % 360. {
+            angle if angle == 90. || angle == -270. => f64::INFINITY,
+            angle if angle == -90. || angle == 270. => f64::NEG_INFINITY,
+            angle => trig(f64::tan, angle),
+        },
+        FloatToFloatOp::ASin => n.asin().to_degrees(),
+        FloatToFloatOp::ACos => n.acos().to_degrees(),
+        FloatToFloatOp::ATan => n.atan().to_degrees(),
+        FloatToFloatOp::Ln => n.ln(),
+        // how Scratch does it, so log 1000 is 2.9999999999999996 like there
+        FloatToFloatOp::Log => n.ln() / std::f64::consts::LN_10,
+        FloatToFloatOp::Exp => n.exp(),
+    }
+}
+
+// Random and Distance need more than two numbers, so they're left to the caller
+pub fn math(op: MathOp, a: f64, b: f64) -> Option<f64> {
+    Some(match op {
+        MathOp::Add => a + b,
+        MathOp::Subtract => a - b,
+        MathOp::Multiply => a * b,
+        MathOp::Divide => a / b,
+        MathOp::Modulo => modulo(a, b),
+        MathOp::Pow => a.powf(b),
+        MathOp::Random | MathOp::Distance => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn parses_numbers_like_javascript() {
+        assert_eq!(parse_number(""), 0.0);
+        assert_eq!(parse_number(" 12 "), 12.0);
+        assert_eq!(parse_number("0x1F"), 31.0);
+        assert!(parse_number("-0x10").is_nan());
+        assert_eq!(parse_number("Infinity"), f64::INFINITY);
+        assert!(parse_number("1e").is_nan());
+    }
+
+    #[test]
+    fn casts_like_scratch() {
+        assert!(!string("false").to_bool());
+        assert!(!string("FALSE").to_bool());
+        assert!(!string("0").to_bool());
+        assert!(string("0.0").to_bool());
+        assert!(string(" ").to_bool());
+        assert!(!Value::Number(f64::NAN).to_bool());
+        assert!(!string("1.0").is_int());
+        assert!(string("1").is_int());
+        assert!(!Value::Number(1.5).is_int());
+        assert!(Value::Number(f64::NAN).is_int());
+        assert_eq!(string("abc").to_number(), 0.0);
+    }
+
+    #[test]
+    fn casts_colors_like_scratch() {
+        assert_eq!(string("#f80").to_rgba(), [255, 136, 0, 255]);
+        assert_eq!(string("#ff8800").to_rgba(), [255, 136, 0, 255]);
+        assert_eq!(string("#ff88").to_rgba(), [0, 0, 0, 255]);
+        assert_eq!(string("#zzz").to_rgba(), [0, 0, 0, 255]);
+        // an alpha of 0 is opaque
+        assert_eq!(Value::Number(0xff0000 as f64).to_rgba(), [255, 0, 0, 255]);
+        assert_eq!(Value::Number(0x80ff0000u32 as f64).to_rgba(), [255, 0, 0, 128]);
+        assert_eq!(Value::Number(-1.0).to_rgba(), [255, 255, 255, 255]);
+        assert_eq!(string("65280").to_rgba(), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn compares_like_scratch() {
+        assert_eq!(compare(&string("abc"), &string("ABC")), Ordering::Equal);
+        assert_ne!(compare(&string(" "), &Value::Number(0.0)), Ordering::Equal);
+        assert_ne!(compare(&Value::Number(0.0), &string(" ")), Ordering::Equal);
+        assert_eq!(compare(&string("0"), &string(" ")), Ordering::Greater);
+        assert_eq!(compare(&string(" "), &string("\t")), Ordering::Greater);
+        assert_eq!(compare(&string("10"), &string("9")), Ordering::Greater);
+        assert_eq!(compare(&string("1e1"), &Value::Number(10.0)), Ordering::Equal);
+        assert_eq!(compare(&Value::Bool(true), &string("true")), Ordering::Equal);
+        assert_eq!(compare(&Value::Bool(true), &Value::Number(1.0)), Ordering::Equal);
+    }
+
+    #[test]
+    fn does_math_like_scratch() {
+        assert_eq!(modulo(-1.0, 10.0), 9.0);
+        assert_eq!(round(-2.5), -2.0);
+        assert_eq!(number_to_string(1e21), "1e+21");
+        assert_eq!(number_to_string(1e-7), "1e-7");
+        assert_eq!(number_to_string(1.23e-7), "1.23e-7");
+        assert_eq!(number_to_string(0.000001), "0.000001");
+        assert_eq!(number_to_string(-0.0), "0");
+        assert_eq!(number_to_string(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(float_to_float(FloatToFloatOp::Tan, 90.0), f64::INFINITY);
+        assert_eq!(float_to_float(FloatToFloatOp::Tan, 270.0), f64::NEG_INFINITY);
+        assert_eq!(float_to_float(FloatToFloatOp::Tan, -90.0), f64::NEG_INFINITY);
+        assert_eq!(float_to_float(FloatToFloatOp::Tan, 450.0), f64::INFINITY);
+        assert_eq!(float_to_float(FloatToFloatOp::Tan, 45.0), 1.0);
+        assert_eq!(float_to_float(FloatToFloatOp::Sin, 180.0), 0.0);
+        assert_eq!(float_to_float(FloatToFloatOp::Log, 1000.0), 2.9999999999999996);
+    }
+}